@@ -1,9 +1,10 @@
 use crate::common::Const;
+use crate::compiler::Target;
 use crate::lexer::{BinaryOperator, Type, UnaryOperator};
 use crate::tac::Pseudoregister::Register;
 use crate::tac::{Operand, Pseudoregister, Reg};
 use std::collections::VecDeque;
-use std::fmt::{Display, Formatter};
+use std::fmt::{self, Display, Formatter, Write};
 use std::rc::Rc;
 
 fn should_split(src: &Rc<Operand>, dest: &Rc<Pseudoregister>) -> bool {
@@ -29,6 +30,7 @@ pub(crate) enum CondCode {
     AboveOrEqual,
     Below,
     BelowOrEqual,
+    Overflow,
 }
 
 impl Display for CondCode {
@@ -44,20 +46,35 @@ impl Display for CondCode {
             CondCode::AboveOrEqual => write!(f, "ae"),
             CondCode::Below => write!(f, "b"),
             CondCode::BelowOrEqual => write!(f, "be"),
+            CondCode::Overflow => write!(f, "o"),
         }
     }
 }
 
+/// Section the assembler places what follows in. Program emits every function under one
+/// `Text`, then the statics grouped by section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Section {
+    Text,
+    Data,
+    ReadOnly,
+    Bss,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum AsmAst {
+    Section(Section),
     Function {
         name: Rc<String>,
         global: bool,
+        /// Set up `%rbp` as a frame pointer, which `Ret` then tears down
+        frame: bool,
     },
     Static {
-        size: i32,
         name: Rc<String>,
         global: bool,
+        /// A `const` object, placed where writes to it fault
+        read_only: bool,
         init: Const,
     },
     Mov {
@@ -74,6 +91,10 @@ pub(crate) enum AsmAst {
         dest: Rc<Pseudoregister>,
     },
     MovAl(Rc<Pseudoregister>),
+    Lea {
+        name: Rc<String>,
+        dest: Rc<Pseudoregister>,
+    },
     Unary {
         operator: UnaryOperator,
         size: i32,
@@ -92,7 +113,7 @@ pub(crate) enum AsmAst {
     },
     Idiv {
         size: i32,
-        operand: Rc<Pseudoregister>,
+        operand: Rc<Operand>,
     },
     Div {
         size: i32,
@@ -102,6 +123,12 @@ pub(crate) enum AsmAst {
         size: i32,
     },
     Jmp(Rc<String>),
+    /// Indirect jump through `table`, emitted alongside it as offsets from the table
+    JumpTable {
+        index: Rc<Operand>,
+        table: Rc<String>,
+        targets: Vec<Rc<String>>,
+    },
     JmpCC {
         condition: CondCode,
         label: Rc<String>,
@@ -109,9 +136,66 @@ pub(crate) enum AsmAst {
     SetCC(CondCode),
     Label(Rc<String>),
     Push(Rc<Operand>),
-    Call(Rc<String>),
-    Ret,
-    Testl(Rc<Pseudoregister>),
+    Call {
+        name: Rc<String>,
+        external: bool,
+    },
+    Ret {
+        frame: bool,
+    },
+    Test(Rc<Pseudoregister>),
+    /// Deliberately illegal instruction, used to abort on a runtime trap
+    Ud2,
+    /// Source line of the instructions that follow, for the debugger's line table
+    Loc(i32),
+}
+
+/// Load an immediate into `scratch`, with `movabsq` when it is 64 bits wide, leaving every
+/// other register alone.
+fn immediate_to_register(
+    operand: &Rc<Operand>,
+    size: i32,
+    scratch: Reg,
+    out: &mut VecDeque<AsmAst>,
+) -> Rc<Operand> {
+    let register = Register(scratch, Type::of_size(size));
+    out.push_back(AsmAst::Mov {
+        size,
+        src: Rc::clone(operand),
+        dest: Rc::from(register.clone()),
+    });
+    Rc::from(Operand::Register(register))
+}
+
+/*
+Bring a 4-byte comparison operand up to 8 bytes in `scratch`, sign- or
+zero-extending by its signedness
+ */
+fn widen_operand(operand: &Rc<Operand>, scratch: Reg, out: &mut VecDeque<AsmAst>) -> Rc<Operand> {
+    if operand.size() == 8 {
+        return Rc::clone(operand);
+    }
+    let wide = Rc::from(Register(scratch.clone(), Type::Long));
+    if operand.is_immediate() {
+        out.push_back(AsmAst::Mov {
+            size: 8,
+            src: Rc::clone(operand),
+            dest: Rc::clone(&wide),
+        });
+    } else if operand.is_unsigned() {
+        // writing a 32-bit register clears the upper half
+        out.push_back(AsmAst::Mov {
+            size: 4,
+            src: Rc::clone(operand),
+            dest: Rc::from(Register(scratch, Type::Int)),
+        });
+    } else {
+        out.push_back(AsmAst::Movsx {
+            src: Rc::clone(operand),
+            dest: Rc::clone(&wide),
+        });
+    }
+    Rc::from(Operand::Register(wide.as_ref().clone()))
 }
 
 pub(crate) fn assembly_fix(mut instructions: VecDeque<AsmAst>) -> VecDeque<AsmAst> {
@@ -122,7 +206,162 @@ pub(crate) fn assembly_fix(mut instructions: VecDeque<AsmAst>) -> VecDeque<AsmAs
     out
 }
 
+/// Symbol name as the target's object format spells it.
+fn mangle(name: &Rc<String>, target: Target) -> Rc<String> {
+    if target == Target::MacOs {
+        Rc::from(format!("_{}", name))
+    } else {
+        Rc::clone(name)
+    }
+}
+
+fn mangle_pseudoregister(register: Rc<Pseudoregister>, target: Target) -> Rc<Pseudoregister> {
+    match register.as_ref() {
+        Pseudoregister::Data(name, type_) => {
+            Rc::from(Pseudoregister::Data(mangle(name, target), *type_))
+        }
+        _ => register,
+    }
+}
+
+fn mangle_operand(operand: Rc<Operand>, target: Target) -> Rc<Operand> {
+    match operand.as_ref() {
+        Operand::Register(Pseudoregister::Data(name, type_)) => Rc::from(Operand::Register(
+            Pseudoregister::Data(mangle(name, target), *type_),
+        )),
+        _ => operand,
+    }
+}
+
+pub(crate) fn mangle_symbols(instructions: VecDeque<AsmAst>, target: Target) -> VecDeque<AsmAst> {
+    if target != Target::MacOs {
+        return instructions;
+    }
+    instructions
+        .into_iter()
+        .map(|instruction| instruction.mangle_symbols(target))
+        .collect()
+}
+
 impl AsmAst {
+    /// Section of a static variable: read-only when `const`, otherwise `Bss` when it
+    /// starts out zero and `Data` when it doesn't.
+    pub(crate) fn data_section(&self) -> Option<Section> {
+        match self {
+            AsmAst::Static {
+                read_only: true, ..
+            } => Some(Section::ReadOnly),
+            AsmAst::Static { init, .. } if init.is_zero() => Some(Section::Bss),
+            AsmAst::Static { .. } => Some(Section::Data),
+            _ => None,
+        }
+    }
+
+    /*
+    Rename every function and static variable, whether defined or referenced
+     */
+    fn mangle_symbols(self, target: Target) -> AsmAst {
+        let operand = |operand| mangle_operand(operand, target);
+        let register = |register| mangle_pseudoregister(register, target);
+        match self {
+            AsmAst::Function {
+                name,
+                global,
+                frame,
+            } => AsmAst::Function {
+                name: mangle(&name, target),
+                global,
+                frame,
+            },
+            AsmAst::Static {
+                name,
+                global,
+                read_only,
+                init,
+            } => AsmAst::Static {
+                name: mangle(&name, target),
+                global,
+                read_only,
+                init,
+            },
+            AsmAst::Call { name, external } => AsmAst::Call {
+                name: mangle(&name, target),
+                external,
+            },
+            AsmAst::Lea { name, dest } => AsmAst::Lea {
+                name: mangle(&name, target),
+                dest: register(dest),
+            },
+            AsmAst::Mov { size, src, dest } => AsmAst::Mov {
+                size,
+                src: operand(src),
+                dest: register(dest),
+            },
+            AsmAst::Movsx { src, dest } => AsmAst::Movsx {
+                src: operand(src),
+                dest: register(dest),
+            },
+            AsmAst::MovZeroExtend { src, dest } => AsmAst::MovZeroExtend {
+                src: operand(src),
+                dest: register(dest),
+            },
+            AsmAst::MovAl(dest) => AsmAst::MovAl(register(dest)),
+            AsmAst::Unary {
+                operator,
+                size,
+                dest,
+            } => AsmAst::Unary {
+                operator,
+                size,
+                dest: register(dest),
+            },
+            AsmAst::Binary {
+                operator,
+                size,
+                src,
+                dest,
+            } => AsmAst::Binary {
+                operator,
+                size,
+                src: operand(src),
+                dest: register(dest),
+            },
+            AsmAst::Cmp { size, left, right } => AsmAst::Cmp {
+                size,
+                left: operand(left),
+                right: operand(right),
+            },
+            AsmAst::Idiv { size, operand: op } => AsmAst::Idiv {
+                size,
+                operand: operand(op),
+            },
+            AsmAst::Div { size, operand: op } => AsmAst::Div {
+                size,
+                operand: operand(op),
+            },
+            AsmAst::Push(op) => AsmAst::Push(operand(op)),
+            AsmAst::JumpTable {
+                index,
+                table,
+                targets,
+            } => AsmAst::JumpTable {
+                index: operand(index),
+                table,
+                targets,
+            },
+            AsmAst::Test(dest) => AsmAst::Test(register(dest)),
+            AsmAst::Section(_)
+            | AsmAst::Cdq { .. }
+            | AsmAst::Jmp(_)
+            | AsmAst::JmpCC { .. }
+            | AsmAst::SetCC(_)
+            | AsmAst::Label(_)
+            | AsmAst::Ret { .. }
+            | AsmAst::Ud2
+            | AsmAst::Loc(_) => self,
+        }
+    }
+
     fn fix_intermediate(&self, out: &mut VecDeque<AsmAst>) {
         match self {
             AsmAst::Binary {
@@ -132,10 +371,7 @@ impl AsmAst {
                 dest,
             } => {
                 if should_split(src, dest) {
-                    let r10 = std::rc::Rc::from(Register(
-                        Reg::R10,
-                        if *size == 4 { Type::Int } else { Type::Long },
-                    ));
+                    let r10 = std::rc::Rc::from(Register(Reg::R10, Type::of_size(*size)));
                     out.push_back(Self::Mov {
                         size: *size,
                         src: src.clone(),
@@ -153,10 +389,7 @@ impl AsmAst {
             }
             AsmAst::Mov { size, src, dest } => {
                 if should_split(src, dest) {
-                    let r10 = std::rc::Rc::from(Register(
-                        Reg::R10,
-                        if *size == 4 { Type::Int } else { Type::Long },
-                    ));
+                    let r10 = std::rc::Rc::from(Register(Reg::R10, Type::of_size(*size)));
                     out.push_back(AsmAst::Mov {
                         size: *size,
                         src: src.clone(),
@@ -171,6 +404,30 @@ impl AsmAst {
                     out.push_back(self.clone());
                 }
             }
+            AsmAst::Cmp { size, left, right } => {
+                // both operands must have the comparison's width
+                let size = (*size).max(left.size()).max(right.size());
+                let (left, right) = if size == 8 && (left.size() != 8 || right.size() != 8) {
+                    (
+                        widen_operand(left, Reg::R11, out),
+                        widen_operand(right, Reg::R10, out),
+                    )
+                } else {
+                    (left.clone(), right.clone())
+                };
+                // cmp only encodes a 32-bit immediate, and only as its first operand
+                let left = if size == 8 && left.is_immediate() {
+                    immediate_to_register(&left, size, Reg::R11, out)
+                } else {
+                    left
+                };
+                let right = if right.is_immediate() {
+                    immediate_to_register(&right, size, Reg::R10, out)
+                } else {
+                    right
+                };
+                out.push_back(AsmAst::Cmp { size, left, right });
+            }
             AsmAst::MovZeroExtend { src, dest } => {
                 out.push_back(AsmAst::Mov {
                     size: 4,
@@ -187,37 +444,56 @@ impl AsmAst {
         }
     }
 
-    pub(crate) fn make_assembly(&self, out: &mut String) {
+    pub(crate) fn make_assembly<W: Write>(&self, out: &mut W, target: Target) -> fmt::Result {
         match &self {
-            AsmAst::Function { name, global } => {
+            AsmAst::Section(section) => {
+                let directive = match (section, target) {
+                    (Section::Text, _) => ".text",
+                    (Section::Data, _) => ".data",
+                    (Section::Bss, _) => ".bss",
+                    (Section::ReadOnly, Target::Linux) => ".section .rodata",
+                    (Section::ReadOnly, Target::MacOs) => ".section __TEXT,__const",
+                    (Section::ReadOnly, Target::Windows) => ".section .rdata,\"dr\"",
+                };
+                writeln!(out, "{}", directive)?
+            }
+            AsmAst::Function {
+                name,
+                global,
+                frame,
+            } => {
                 if *global {
-                    *out += &format!(".global {}\n", name);
+                    writeln!(out, ".global {}", name)?;
                 }
-                *out += &format!(
-                    r#".text
-{}:
-pushq %rbp
+                writeln!(out, "{}:", name)?;
+                if *frame {
+                    out.write_str(
+                        r#"pushq %rbp
 movq %rsp, %rbp
 "#,
-                    name
-                );
+                    )?;
+                }
             }
             AsmAst::Mov { size, src, dest } => {
-                if *size == 8 && src.is_immediate() {
-                    *out += &format!(
+                if *size == 8 && src.is_immediate() && matches!(dest.as_ref(), Register(..)) {
+                    writeln!(out, "movabsq {}, {}", src, dest)?;
+                } else if *size == 8 && src.is_immediate() {
+                    // movabsq can only load a register
+                    write!(
+                        out,
                         r#"movabsq {}, %r10
 movq %r10, {}
 "#,
                         src, dest
-                    );
+                    )?;
                 } else if *size == 8 {
-                    *out += &format!("movq {}, {}\n", src, dest);
+                    writeln!(out, "movq {}, {}", src, dest)?;
                 } else {
-                    *out += &format!("movl {}, {}\n", src, dest);
+                    writeln!(out, "movl {}, {}", src, dest)?;
                 }
             }
-            AsmAst::Movsx { src, dest } => *out += &format!("movslq {}, {}\n", src, dest),
-            AsmAst::MovZeroExtend { src, dest } => *out += &format!("movzbl {}, {}\n", src, dest),
+            AsmAst::Movsx { src, dest } => writeln!(out, "movslq {}, {}", src, dest)?,
+            AsmAst::MovZeroExtend { src, dest } => writeln!(out, "movzbl {}, {}", src, dest)?,
             AsmAst::Unary {
                 size,
                 operator,
@@ -227,15 +503,12 @@ movq %r10, {}
                 let opcode = match operator {
                     UnaryOperator::Increment => format!("inc{}", suffix),
                     UnaryOperator::Decrement => format!("dec{}", suffix),
-                    UnaryOperator::LogicalNot => {
-                        *out += &format!("xorl $1, {}", dest);
-                        return;
-                    }
+                    UnaryOperator::LogicalNot => unreachable!("! is lowered to a comparison"),
                     UnaryOperator::BitwiseNot => format!("not{}", suffix),
                     UnaryOperator::Negate => format!("neg{}", suffix),
-                    UnaryOperator::UnaryAdd => return,
+                    UnaryOperator::UnaryAdd | UnaryOperator::AddressOf => return Ok(()),
                 };
-                *out += &format!("{} {}\n", opcode, dest);
+                writeln!(out, "{} {}", opcode, dest)?;
             }
             AsmAst::Binary {
                 operator,
@@ -256,72 +529,196 @@ movq %r10, {}
                     _ => unreachable!(),
                 };
                 if src.is_immediate() && *size == 8 {
-                    *out += &format!(
+                    write!(
+                        out,
                         r#"movabsq {}, %r10
 {} %r10, {}
 "#,
                         src, opcode, dest
-                    );
+                    )?;
                 } else {
-                    *out += &format!("{} {}, {}\n", opcode, src, dest);
+                    writeln!(out, "{} {}, {}", opcode, src, dest)?;
                 }
             }
             AsmAst::Cmp { size, left, right } => {
                 let suffix = if *size == 4 { 'l' } else { 'q' };
-                *out += &format!("cmp{} {}, {}\n", suffix, left, right);
+                writeln!(out, "cmp{} {}, {}", suffix, left, right)?;
             }
             AsmAst::Idiv { size, operand } => {
                 let suffix = if *size == 4 { 'l' } else { 'q' };
-                *out += &format!("idiv{} {}", suffix, operand);
+                write!(out, "idiv{} {}", suffix, operand)?;
             }
             AsmAst::Div { size, operand } => {
                 let suffix = if *size == 4 { 'l' } else { 'q' };
-                *out += &format!("div{} {}", suffix, operand);
+                write!(out, "div{} {}", suffix, operand)?;
             }
-            AsmAst::Cdq { size } => *out += if *size == 4 { "cdq" } else { "cqo" },
-            AsmAst::Jmp(label) => *out += &format!("jmp {}\n", label),
-            AsmAst::JmpCC { condition, label } => *out += &format!("j{} {}\n", condition, label),
-            AsmAst::SetCC(condition) => *out += &format!("set{} %al\n", condition),
-            AsmAst::Label(label) => *out += &format!("{}:\n", label),
-            AsmAst::Push(operand) => *out += &format!("pushq {}\n", operand),
-            AsmAst::Call(name) => *out += &format!("call {}\n", name),
-            AsmAst::Ret => {
-                *out += r#"movq %rbp, %rsp
-popq %rbp
-ret"#
+            AsmAst::Cdq { size } => out.write_str(if *size == 4 { "cdq" } else { "cqo" })?,
+            AsmAst::Jmp(label) => writeln!(out, "jmp {}", label)?,
+            AsmAst::JmpCC { condition, label } => writeln!(out, "j{} {}", condition, label)?,
+            AsmAst::SetCC(condition) => writeln!(out, "set{} %al", condition)?,
+            AsmAst::Label(label) => writeln!(out, "{}:", label)?,
+            AsmAst::Push(operand) => writeln!(out, "pushq {}", operand)?,
+            // external functions may live in a shared library, so go through the PLT
+            AsmAst::Call { name, external } if *external && target == Target::Linux => {
+                writeln!(out, "call {}@PLT", name)?
             }
+            AsmAst::Call { name, .. } => writeln!(out, "call {}", name)?,
+            AsmAst::JumpTable {
+                index,
+                table,
+                targets,
+            } => {
+                // writing %eax zero-extends a 4-byte index into %rax
+                if index.size() == 4 {
+                    writeln!(out, "movl {}, %eax", index)?;
+                } else {
+                    writeln!(out, "movq {}, %rax", index)?;
+                }
+                // offsets rather than addresses, so the table needs no relocation when loaded
+                write!(
+                    out,
+                    r#"leaq {table}(%rip), %r10
+movslq (%r10,%rax,4), %rax
+addq %r10, %rax
+jmp *%rax
+"#
+                )?;
+                // Mach-O and COFF keep the table in the code, where the offsets stay within
+                // one section
+                if target == Target::Linux {
+                    out.write_str(".section .rodata\n")?;
+                }
+                writeln!(out, ".balign 4\n{}:", table)?;
+                for label in targets {
+                    writeln!(out, ".long {} - {}", label, table)?;
+                }
+                if target == Target::Linux {
+                    out.write_str(".text\n")?;
+                }
+            }
+            AsmAst::Ud2 => out.write_str("ud2\n")?,
+            AsmAst::Loc(line) => writeln!(out, ".loc 1 {}", line)?,
+            AsmAst::Ret { frame: false } => out.write_str("ret")?,
+            AsmAst::Ret { frame: true } => out.write_str(
+                r#"movq %rbp, %rsp
+popq %rbp
+ret"#,
+            )?,
             AsmAst::Static {
-                size,
-                name,
-                global,
-                init,
+                name, global, init, ..
             } => {
-                let (initial, bss_data) = if matches!(
-                    init,
-                    Const::ConstLong(0)
-                        | Const::ConstULong(0)
-                        | Const::ConstInt(0)
-                        | Const::ConstUInt(0)
-                ) {
-                    (&format!(".zero {}\n", size), ".bss")
+                let type_ = init.type_();
+                let initial = if init.is_zero() {
+                    &format!(".zero {}\n", type_.size())
+                } else {
+                    &format!(".{} {}", type_.data_directive(), init)
+                };
+                // Mach-O's .align takes a power of two, .balign always takes bytes
+                let directive = if target == Target::MacOs {
+                    "balign"
                 } else {
-                    let which = if *size == 4 { "long" } else { "quad" };
-                    (&format!(".{} {}", which, init), ".data")
+                    "align"
                 };
-                let align = &format!(".align {}\n", size);
+                let align = &format!(".{} {}\n", directive, type_.align());
                 if *global {
-                    *out += &format!(".global {}\n", name);
+                    writeln!(out, ".global {}", name)?;
                 }
-                *out += &format!(
+                write!(
+                    out,
                     r#"{}
-{}
 {}:
 {}"#,
-                    bss_data, align, name, initial
-                );
+                    align, name, initial
+                )?;
             }
-            AsmAst::Testl(reg) => *out += &format!("testl {}, {}", reg, reg),
-            AsmAst::MovAl(dest) => *out += &format!("movzbl %al, {}\n", dest),
+            AsmAst::Test(reg) => {
+                let suffix = if reg.size() == 4 { 'l' } else { 'q' };
+                write!(out, "test{} {}, {}", suffix, reg, reg)?
+            }
+            AsmAst::MovAl(dest) => writeln!(out, "movzbl %al, {}", dest)?,
+            AsmAst::Lea { name, dest } => writeln!(out, "leaq {}(%rip), {}", name, dest)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmp_widens_narrow_immediate() {
+        let cmp = AsmAst::Cmp {
+            size: 4,
+            left: Rc::from(Operand::Immediate(Const::ConstInt(-1))),
+            right: Rc::from(Operand::Register(Register(Reg::DX, Type::Long))),
+        };
+        let fixed = assembly_fix(VecDeque::from([cmp]));
+        assert!(matches!(
+            fixed[0],
+            AsmAst::Mov {
+                size: 8,
+                dest: ref d,
+                ..
+            } if matches!(d.as_ref(), Register(Reg::R11, Type::Long))
+        ));
+
+        let mut out = String::new();
+        for instruction in fixed.iter() {
+            instruction.make_assembly(&mut out, Target::Linux).unwrap();
+        }
+        assert!(out.ends_with("cmpq %r11, %rdx\n"), "{}", out);
+    }
+
+    #[test]
+    fn test_cmp_materializes_wide_immediates() {
+        let wide = Rc::from(Operand::Immediate(Const::ConstLong(5000000000)));
+        let cmp = AsmAst::Cmp {
+            size: 8,
+            left: Rc::clone(&wide),
+            right: wide,
+        };
+        let mut out = String::new();
+        for instruction in assembly_fix(VecDeque::from([cmp])).iter() {
+            instruction.make_assembly(&mut out, Target::Linux).unwrap();
+        }
+        assert_eq!(
+            out,
+            "movabsq $5000000000, %r11\nmovabsq $5000000000, %r10\ncmpq %r11, %r10\n"
+        );
+    }
+
+    #[test]
+    fn test_cmp_wide_immediate_keeps_widened_register() {
+        let cmp = AsmAst::Cmp {
+            size: 8,
+            left: Rc::from(Operand::Immediate(Const::ConstLong(5000000000))),
+            right: Rc::from(Operand::Register(Register(Reg::DX, Type::Int))),
+        };
+        let mut out = String::new();
+        for instruction in assembly_fix(VecDeque::from([cmp])).iter() {
+            instruction.make_assembly(&mut out, Target::Linux).unwrap();
+        }
+        assert_eq!(
+            out,
+            "movslq %edx, %r10\nmovabsq $5000000000, %r11\ncmpq %r11, %r10\n"
+        );
+    }
+
+    #[test]
+    fn test_cmp_sign_extends_narrow_register() {
+        let cmp = AsmAst::Cmp {
+            size: 8,
+            left: Rc::from(Operand::Register(Pseudoregister::Pseudoregister(
+                8,
+                Type::Long,
+            ))),
+            right: Rc::from(Operand::Register(Register(Reg::DX, Type::Int))),
+        };
+        let mut out = String::new();
+        for instruction in assembly_fix(VecDeque::from([cmp])).iter() {
+            instruction.make_assembly(&mut out, Target::Linux).unwrap();
         }
+        assert_eq!(out, "movslq %edx, %r10\ncmpq -8(%rbp), %r10\n");
     }
 }
@@ -1,14 +1,22 @@
 use crate::CompilerError;
 use crate::CompilerError::SemanticError;
-use crate::asm_ast::AsmAst;
+use crate::asm_ast::{AsmAst, Section};
 use crate::common::{Const, Position};
+use crate::compiler::{CompileOptions, OptLevel};
+use crate::control_flow::write_dot;
+use crate::errors::Diagnostic;
 use crate::lexer::{BinaryOperator, StorageClass, Type, UnaryOperator};
+use crate::optimizer::{
+    can_fall_off_end, combine_division, eliminate_dead_stores, eliminate_tail_recursion,
+    fuse_compare_and_branch, hoist_loop_invariants, omit_unused_frame, reachable_functions,
+    referenced_functions, remove_unreachable, share_epilogue,
+};
 use crate::tac::{FunctionBody, TACInstruction};
 use crate::tac_generator::TacVisitor;
-use crate::type_check::TypeCheckVisitor;
+use crate::type_check::{TypeCheckVisitor, get_common_type, truncation_warning};
 use crate::variable_resolution::VariableResolutionVisitor;
 use std::cmp::PartialEq;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::DerefMut;
 use std::rc::Rc;
 
@@ -41,6 +49,21 @@ pub(crate) trait Visitor {
     {
         expression.accept(self)
     }
+    /// An expression evaluated only for its side effects, as in `f();`.
+    fn visit_expression_statement(
+        &mut self,
+        _line_number: &Rc<Position>,
+        expression: &mut ASTNode<Expression>,
+    ) -> Result<(), CompilerError>
+    where
+        Self: Sized,
+    {
+        expression.accept(self)
+    }
+    /// Called before each statement and block-scope declaration.
+    fn visit_line(&mut self, _line_number: &Rc<Position>) -> Result<(), CompilerError> {
+        Ok(())
+    }
     fn visit_block(
         &mut self,
         _line_number: &Rc<Position>,
@@ -123,7 +146,6 @@ pub(crate) trait Visitor {
         &mut self,
         _line_number: &Rc<Position>,
         _label: &mut Rc<String>,
-        _is_for: &mut bool,
     ) -> Result<(), CompilerError> {
         Ok(())
     }
@@ -148,6 +170,35 @@ pub(crate) trait Visitor {
         }
         body.accept(self)
     }
+    fn visit_switch(
+        &mut self,
+        _line_number: &Rc<Position>,
+        condition: &mut ASTNode<Expression>,
+        body: &mut Box<ASTNode<Statement>>,
+        _label: &mut Rc<String>,
+        _cases: &mut SwitchCases,
+    ) -> Result<(), CompilerError>
+    where
+        Self: Sized,
+    {
+        condition.accept(self)?;
+        body.accept(self)
+    }
+    fn visit_case(
+        &mut self,
+        _line_number: &Rc<Position>,
+        value: &mut Option<ASTNode<Expression>>,
+        body: &mut Box<ASTNode<Statement>>,
+        _label: &mut Rc<String>,
+    ) -> Result<(), CompilerError>
+    where
+        Self: Sized,
+    {
+        if let Some(value) = value {
+            value.accept(self)?;
+        }
+        body.accept(self)
+    }
     fn visit_const(
         &mut self,
         _line_number: &Rc<Position>,
@@ -233,18 +284,51 @@ pub(crate) trait Visitor {
     {
         exp.accept(self)
     }
+    fn visit_compound_literal(
+        &mut self,
+        _line_number: &Rc<Position>,
+        _literal_type: &mut Type,
+        init: &mut Box<ASTNode<Expression>>,
+        _type_: &mut Type,
+    ) -> Result<(), CompilerError>
+    where
+        Self: Sized,
+    {
+        init.accept(self)
+    }
+    fn visit_size_of(
+        &mut self,
+        _line_number: &Rc<Position>,
+        exp: &mut Box<ASTNode<Expression>>,
+        _type_: &mut Type,
+    ) -> Result<(), CompilerError>
+    where
+        Self: Sized,
+    {
+        exp.accept(self)
+    }
 }
 
 pub(crate) struct FunAttr {
     pub(crate) defined: bool,
     pub(crate) global: bool,
+    pub(crate) noreturn: bool,
     pub(crate) func_type: Rc<FuncType>,
+    pub(crate) inline_body: Option<Rc<InlineBody>>,
+}
+
+/// Resolved and type checked body of an inline function that is a single `return expr;`.
+pub(crate) struct InlineBody {
+    pub(crate) params: Vec<String>,
+    pub(crate) expression: ASTNode<Expression>,
 }
 
 pub(crate) struct StaticAttr {
     pub(crate) init: InitialValue,
     pub(crate) global: bool,
     pub(crate) type_: Type,
+    /// Declared `const`, so it goes in a read-only section
+    pub(crate) constant: bool,
 }
 
 #[derive(Debug)]
@@ -260,7 +344,7 @@ pub(crate) struct FuncType {
     pub(crate) ret: Type,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct ASTNode<T> {
     pub(crate) line_number: Rc<Position>,
     pub(crate) type_: Type,
@@ -276,6 +360,27 @@ pub(crate) struct FunctionDeclaration {
     pub(crate) body: Option<ASTNode<Block>>,
     pub(crate) storage_class: Option<StorageClass>,
     pub(crate) func_type: Rc<FuncType>,
+    pub(crate) noreturn: bool,
+    pub(crate) inline: bool,
+}
+
+impl FunctionDeclaration {
+    /// The returned expression of an inline function whose body is just `return expr;`.
+    fn inline_expression(&self) -> Option<&ASTNode<Expression>> {
+        if !self.inline {
+            return None;
+        }
+        match self.body.as_ref()?.kind.as_slice() {
+            [item] => match &item.kind {
+                BlockItem::S(statement) => match &statement.kind {
+                    Statement::Return(expression) => Some(expression),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 pub(crate) type Block = Vec<ASTNode<BlockItem>>;
@@ -290,6 +395,14 @@ pub(crate) enum BlockItem {
 pub(crate) enum Declaration {
     FunctionDeclaration(FunctionDeclaration),
     VariableDeclaration(VariableDeclaration),
+    StaticAssert(StaticAssert),
+}
+
+/// `_Static_assert(condition, "message")`, checked while type checking and never emitted
+#[derive(Debug)]
+pub(crate) struct StaticAssert {
+    pub(crate) condition: ASTNode<Expression>,
+    pub(crate) message: String,
 }
 
 #[derive(Debug)]
@@ -298,9 +411,11 @@ pub(crate) struct VariableDeclaration {
     pub(crate) init: Option<ASTNode<Expression>>,
     pub(crate) storage_class: Option<StorageClass>,
     pub(crate) var_type: Type,
+    pub(crate) volatile: bool,
+    pub(crate) constant: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum Expression {
     Constant(Const),
     Variable(Rc<String>),
@@ -319,10 +434,18 @@ pub(crate) enum Expression {
         if_true: Box<ASTNode<Expression>>,
         if_false: Box<ASTNode<Expression>>,
     },
+    /// The already evaluated condition of the enclosing `Condition`, the middle of GNU `a ?: b`
+    ConditionValue,
+    /// The already evaluated left side of the enclosing `Assignment`, the `x` read by `x += y`
+    AssignmentTarget,
     FunctionCall(Rc<String>, Box<Vec<ASTNode<Expression>>>),
     Prefix(UnaryOperator, Box<ASTNode<Expression>>),
     Postfix(UnaryOperator, Box<ASTNode<Expression>>),
     Cast(Type, Box<ASTNode<Expression>>),
+    /// `(type){init}`, an unnamed object holding `init`
+    CompoundLiteral(Type, Box<ASTNode<Expression>>),
+    /// `sizeof exp`, whose operand is type-checked but never evaluated
+    SizeOf(Box<ASTNode<Expression>>),
 }
 
 #[derive(Debug)]
@@ -336,10 +459,7 @@ pub(crate) enum Statement {
     },
     Compound(ASTNode<Block>),
     Break(Rc<String>),
-    Continue {
-        label: Rc<String>,
-        is_for: bool,
-    },
+    Continue(Rc<String>),
     While {
         condition: ASTNode<Expression>,
         body: Box<ASTNode<Statement>>,
@@ -353,9 +473,25 @@ pub(crate) enum Statement {
         body: Box<ASTNode<Statement>>,
         label: Rc<String>,
     },
+    Switch {
+        condition: ASTNode<Expression>,
+        body: Box<ASTNode<Statement>>,
+        label: Rc<String>,
+        /// Filled in during resolution
+        cases: SwitchCases,
+    },
+    /// `case value:` or, without a value, `default:`
+    Case {
+        value: Option<ASTNode<Expression>>,
+        body: Box<ASTNode<Statement>>,
+        label: Rc<String>,
+    },
     Null,
 }
 
+/// Every `case` in a switch body with its label, `None` standing for `default`
+pub(crate) type SwitchCases = Vec<(Option<Const>, Rc<String>)>;
+
 #[derive(Debug)]
 pub(crate) enum ForInit {
     InitDecl(Declaration),
@@ -364,19 +500,13 @@ pub(crate) enum ForInit {
 
 pub(crate) fn is_lvalue_node(node: &Expression) -> bool {
     match node {
-        Expression::Prefix(_, _) | Expression::Variable(_) => true,
+        Expression::Prefix(_, _) | Expression::Variable(_) | Expression::CompoundLiteral(..) => {
+            true
+        }
         _ => false,
     }
 }
 
-pub(crate) fn extract_base_variable(node: &Expression) -> Rc<String> {
-    match node {
-        Expression::Variable(v) => Rc::clone(v),
-        Expression::Prefix(_, v) => extract_base_variable(&v.kind),
-        _ => panic!("Not a variable"),
-    }
-}
-
 impl PartialEq for FuncType {
     fn eq(&self, other: &Self) -> bool {
         self.params == other.params && self.ret == other.ret
@@ -384,7 +514,16 @@ impl PartialEq for FuncType {
 }
 
 impl ASTNode<Program> {
-    pub(crate) fn generate(&mut self, out: &mut VecDeque<AsmAst>) -> Result<(), CompilerError> {
+    /// Generate the program, handing `out` each function's code as soon as it's ready
+    /// and the statics at the end. Static functions wait until the end too unless
+    /// optimizations are off, since only then is it known whether anything calls them.
+    pub(crate) fn generate(
+        &mut self,
+        out: &mut dyn FnMut(VecDeque<AsmAst>) -> Result<(), CompilerError>,
+        options: &CompileOptions,
+        warnings: &mut Vec<Diagnostic>,
+        mut cfg_dot: Option<&mut String>,
+    ) -> Result<(), CompilerError> {
         let mut shared_functions_map: HashMap<String, FunAttr> = HashMap::new();
         let mut shared_variables_map: HashMap<String, StaticAttr> = HashMap::new();
 
@@ -401,6 +540,15 @@ impl ASTNode<Program> {
                     }
                 }
                 Declaration::VariableDeclaration(var) => {
+                    if let Some(init) = &var.init
+                        && let Ok(Some(value)) = init.fold_constant()
+                    {
+                        warnings.extend(truncation_warning(
+                            &value,
+                            &var.var_type,
+                            &declaration.line_number,
+                        ));
+                    }
                     if let Some(value) = Self::typecheck_file_scope_variable_declaration(
                         &mut shared_functions_map,
                         &mut shared_variables_map,
@@ -409,15 +557,41 @@ impl ASTNode<Program> {
                         return value;
                     }
                 }
+                // checked below, once every global it might name is known
+                Declaration::StaticAssert(_) => {}
             }
         }
 
+        // all the code comes first, then the data one section at a time
+        let mut in_text = false;
+        let mut emit_code = |mut code: VecDeque<AsmAst>| {
+            if !in_text {
+                code.push_front(AsmAst::Section(Section::Text));
+                in_text = true;
+            }
+            out(code)
+        };
+        // code of each static function, kept back until we know it can be called
+        let mut held_back = vec![];
+        let mut references = HashMap::new();
+
         // second: regular
         for declaration in &mut self.kind {
+            if let Declaration::StaticAssert(_) = &declaration.kind {
+                let mut visitor = VariableResolutionVisitor::new(
+                    Rc::from(String::new()),
+                    &shared_functions_map,
+                    &mut shared_variables_map,
+                );
+                visitor.visit_declaration(&declaration.line_number, &mut declaration.kind)?;
+                let mut visitor =
+                    TypeCheckVisitor::new(&shared_functions_map, &shared_variables_map);
+                visitor.visit_declaration(&declaration.line_number, &mut declaration.kind)?;
+            }
             if let Declaration::FunctionDeclaration(func) = &declaration.kind {
                 let func_name = Rc::clone(&func.name);
                 let mut visitor = VariableResolutionVisitor::new(
-                    func_name,
+                    Rc::clone(&func_name),
                     &shared_functions_map,
                     &mut shared_variables_map,
                 );
@@ -425,16 +599,57 @@ impl ASTNode<Program> {
                 let mut visitor =
                     TypeCheckVisitor::new(&shared_functions_map, &shared_variables_map);
                 visitor.visit_declaration(&declaration.line_number, &mut declaration.kind)?;
-                println!("{:#?}", declaration);
-                declaration.generate(out)?;
+                warnings.append(&mut visitor.warnings);
+                if let Declaration::FunctionDeclaration(func) = &declaration.kind
+                    && options.opt_level != OptLevel::O0
+                    && let Some(expression) = func.inline_expression()
+                {
+                    let inline_body = InlineBody {
+                        params: func.params.clone(),
+                        expression: expression.clone(),
+                    };
+                    let function = shared_functions_map.get_mut(func.name.as_ref()).unwrap();
+                    function.inline_body = Some(Rc::new(inline_body));
+                }
+                let mut function_asm = VecDeque::new();
+                let referenced = declaration.generate(
+                    &mut function_asm,
+                    &shared_functions_map,
+                    options,
+                    warnings,
+                    cfg_dot.as_deref_mut(),
+                )?;
+                if !function_asm.is_empty() {
+                    references.insert(Rc::clone(&func_name), referenced);
+                    if shared_functions_map[func_name.as_str()].global
+                        || options.opt_level == OptLevel::O0
+                    {
+                        emit_code(function_asm)?;
+                    } else {
+                        held_back.push((func_name, function_asm));
+                    }
+                }
             }
         }
 
-        for (name, static_attr) in shared_variables_map.iter() {
+        let reachable = reachable_functions(&references, &shared_functions_map);
+        for (name, function_asm) in held_back {
+            if reachable.contains(&name) {
+                emit_code(function_asm)?;
+            } else {
+                options.log(format_args!("dce: dropped unused static function {}", name));
+            }
+        }
+
+        let mut statics = VecDeque::new();
+        let mut variables: Vec<_> = shared_variables_map.iter().collect();
+        variables.sort_by_key(|(name, _)| *name);
+        for (name, static_attr) in variables {
             let tac = match &static_attr.init {
                 InitialValue::Tentative => TACInstruction::StaticVariable {
                     name: Rc::from(name.clone()),
                     global: static_attr.global,
+                    read_only: static_attr.constant,
                     init: match static_attr.type_ {
                         Type::Int => Const::ConstInt(0),
                         Type::Long => Const::ConstLong(0),
@@ -446,11 +661,27 @@ impl ASTNode<Program> {
                 InitialValue::Initial(i) => TACInstruction::StaticVariable {
                     name: Rc::from(name.clone()),
                     global: static_attr.global,
+                    read_only: static_attr.constant,
                     init: i.clone(),
                 },
                 InitialValue::NoInitializer => continue,
             };
-            tac.make_assembly(out, &FunctionBody::new());
+            tac.make_assembly(&mut statics, &FunctionBody::new());
+        }
+        statics
+            .make_contiguous()
+            .sort_by_key(|variable| variable.data_section());
+        let mut data = VecDeque::new();
+        let mut section = None;
+        for variable in statics {
+            if variable.data_section() != section {
+                section = variable.data_section();
+                data.extend(section.map(AsmAst::Section));
+            }
+            data.push_back(variable);
+        }
+        if !data.is_empty() {
+            out(data)?;
         }
 
         Ok(())
@@ -462,13 +693,18 @@ impl ASTNode<Program> {
         var: &&mut VariableDeclaration,
     ) -> Option<Result<(), CompilerError>> {
         let mut initial_value = if let Some(init) = &var.init {
-            if let Expression::Constant(i) = &init.kind {
-                InitialValue::Initial(i.clone())
-            } else {
-                return Some(Err(SemanticError(format!(
-                    "Initial value {:?} of {} is non-constant",
-                    init.kind, var.name
-                ))));
+            match init.fold_constant() {
+                Ok(Some(i)) => InitialValue::Initial(i.convert_to(&var.var_type)),
+                Ok(None) => {
+                    return Some(Err(SemanticError(
+                        format!(
+                            "Initial value {:?} of {} is non-constant",
+                            init.kind, var.name
+                        )
+                        .into(),
+                    )));
+                }
+                Err(err) => return Some(Err(err)),
             }
         } else {
             if var.storage_class == Some(StorageClass::Extern) {
@@ -481,38 +717,36 @@ impl ASTNode<Program> {
         let identifier = (*var.name).clone();
 
         if shared_functions_map.contains_key(&identifier) {
-            return Some(Err(SemanticError(format!(
-                "Function {} redeclared as variable",
-                identifier
-            ))));
+            return Some(Err(SemanticError(
+                format!("Function {} redeclared as variable", identifier).into(),
+            )));
         }
 
         if let Some(StaticAttr {
             global: old_global,
             init: old_init,
             type_: old_type,
+            constant: old_constant,
         }) = shared_variables_map.get(&identifier)
         {
-            if var.var_type != *old_type {
-                return Some(Err(SemanticError(format!(
-                    "Conflicting variable type definitions of {}",
-                    var.name
-                ))));
+            if var.var_type != *old_type || var.constant != *old_constant {
+                return Some(Err(SemanticError(
+                    format!("Conflicting variable type definitions of {}", var.name).into(),
+                )));
             }
             if var.storage_class == Some(StorageClass::Extern) {
                 global = *old_global;
             } else if *old_global != global {
-                return Some(Err(SemanticError(format!(
-                    "Conflicting variable linkage of {}",
-                    identifier
-                ))));
+                return Some(Err(SemanticError(
+                    format!("Conflicting variable linkage of {}", identifier).into(),
+                )));
             }
             if let InitialValue::Initial(i) = old_init {
                 if let Some(_) = var.init {
-                    return Some(Err(SemanticError(format!(
-                        "Conflict file scope variable definitions of {}",
-                        identifier
-                    ))));
+                    return Some(Err(SemanticError(
+                        format!("Conflict file scope variable definitions of {}", identifier)
+                            .into(),
+                    )));
                 } else {
                     initial_value = InitialValue::Initial(i.clone());
                 }
@@ -528,6 +762,7 @@ impl ASTNode<Program> {
                 init: initial_value,
                 global,
                 type_: var.var_type,
+                constant: var.constant,
             },
         );
         None
@@ -541,40 +776,42 @@ impl ASTNode<Program> {
         let name = Rc::clone(&func.name);
         let func_type = Rc::clone(&func.func_type);
         let has_body = func.body.is_some();
+        let mut noreturn = func.noreturn;
+        let mut defined = has_body;
         let identifier = (*name).clone();
         if shared_variables_map.contains_key(&identifier) {
-            return Some(Err(SemanticError(format!(
-                "Variable {} redeclared as function",
-                identifier
-            ))));
+            return Some(Err(SemanticError(
+                format!("Variable {} redeclared as function", identifier).into(),
+            )));
         }
         if let Some(old_decl) = shared_functions_map.get(&identifier) {
             if old_decl.defined && has_body {
                 // Error if duplicate definition (duplicate prototypes are fine)
-                return Some(Err(SemanticError(format!(
-                    "Duplicate definition of {}",
-                    name
-                ))));
+                return Some(Err(SemanticError(
+                    format!("Duplicate definition of {}", name).into(),
+                )));
             }
             if old_decl.global && func.storage_class == Some(StorageClass::Static) {
-                return Some(Err(SemanticError(format!(
-                    "Static function declaration of {} follows non-static",
-                    name
-                ))));
+                return Some(Err(SemanticError(
+                    format!("Static function declaration of {} follows non-static", name).into(),
+                )));
             }
             if *old_decl.func_type != *func_type {
-                return Some(Err(SemanticError(format!(
-                    "Incompatible function declaration of {}",
-                    name
-                ))));
+                return Some(Err(SemanticError(
+                    format!("Incompatible function declaration of {}", name).into(),
+                )));
             }
+            noreturn |= old_decl.noreturn;
+            defined |= old_decl.defined;
         }
         shared_functions_map.insert(
             identifier,
             FunAttr {
-                defined: func.body.is_some(),
+                defined,
                 global: func.storage_class != Some(StorageClass::Static),
+                noreturn,
                 func_type,
+                inline_body: None,
             },
         );
         None
@@ -582,22 +819,100 @@ impl ASTNode<Program> {
 }
 
 impl ASTNode<Declaration> {
-    pub(crate) fn generate(&mut self, out: &mut VecDeque<AsmAst>) -> Result<(), CompilerError> {
+    /// Emit a function's code, returning the functions it calls or takes the address of.
+    /// With `cfg_dot`, also append the control-flow graph of its final TAC there.
+    pub(crate) fn generate(
+        &mut self,
+        out: &mut VecDeque<AsmAst>,
+        functions_map: &HashMap<String, FunAttr>,
+        options: &CompileOptions,
+        warnings: &mut Vec<Diagnostic>,
+        cfg_dot: Option<&mut String>,
+    ) -> Result<HashSet<Rc<String>>, CompilerError> {
         if let Declaration::FunctionDeclaration(func) = &mut self.kind {
             let identifier = Rc::clone(&func.name);
+            let param_count = func.params.len();
+            // main alone may end without a return, which then returns 0
+            let must_return = func.body.is_some()
+                && func.func_type.ret != Type::Void
+                && identifier.as_str() != "main";
 
             let mut function_body = FunctionBody::new();
-            let mut tac_visitor = TacVisitor::new(Rc::clone(&identifier), &mut function_body);
+            function_body.debug_lines = options.debug_source.is_some();
+            function_body.fold_constants = options.opt_level >= OptLevel::O1;
+            function_body.short_circuit_branches = options.opt_level >= OptLevel::O1;
+            function_body.jump_tables = options.opt_level >= OptLevel::O1;
+            let mut tac_visitor =
+                TacVisitor::new(Rc::clone(&identifier), &mut function_body, functions_map);
             self.accept(&mut tac_visitor)?;
-            println!("{:#?}", function_body);
+            if let Some(instruction) = function_body.valueless_operand() {
+                return Err(SemanticError(Diagnostic::at(
+                    format!(
+                        "Expression without a value used by {:?} in {} at {:?}",
+                        instruction, identifier, self.line_number
+                    ),
+                    &self.line_number,
+                )));
+            }
 
+            if must_return && can_fall_off_end(&function_body, functions_map) {
+                warnings.push(Diagnostic::at(
+                    format!(
+                        "Function {} can reach its end without returning a value at {:?}",
+                        identifier, self.line_number
+                    ),
+                    &self.line_number,
+                ));
+            }
             function_body.add_default_return();
+            if options.trap_overflow {
+                function_body.overflow_trap =
+                    Some(Rc::from(format!(".{}_overflow_trap", identifier)));
+            }
+            if options.opt_level >= OptLevel::O1 {
+                remove_unreachable(&mut function_body, functions_map);
+            }
+            if options.opt_level >= OptLevel::O2 {
+                eliminate_tail_recursion(&mut function_body, &identifier, param_count);
+                eliminate_dead_stores(&mut function_body);
+            }
+            if options.opt_level >= OptLevel::O1 {
+                fuse_compare_and_branch(&mut function_body);
+                combine_division(&mut function_body);
+            }
+            if options.opt_level >= OptLevel::O2 {
+                hoist_loop_invariants(&mut function_body);
+            }
+            if options.opt_level >= OptLevel::O1 {
+                omit_unused_frame(&mut function_body);
+                share_epilogue(&mut function_body, &identifier);
+            }
+            options.log(format_args!(
+                "tac: {} instructions in {}",
+                function_body.instructions.len(),
+                identifier
+            ));
+            if let Some(cfg_dot) = cfg_dot {
+                write_dot(&function_body, &identifier, cfg_dot);
+            }
 
             for instruction in &function_body.instructions {
                 instruction.make_assembly(out, &function_body);
             }
+            if let Some(epilogue) = &function_body.epilogue {
+                // the last return falls straight through into the epilogue
+                if matches!(out.back(), Some(AsmAst::Jmp(label)) if label == epilogue) {
+                    out.pop_back();
+                }
+                out.push_back(AsmAst::Label(Rc::clone(epilogue)));
+                out.push_back(AsmAst::Ret { frame: true });
+            }
+            if let Some(trap) = &function_body.overflow_trap {
+                out.push_back(AsmAst::Label(Rc::clone(trap)));
+                out.push_back(AsmAst::Ud2);
+            }
 
-            return Ok(());
+            return Ok(referenced_functions(&function_body));
         }
 
         unimplemented!();
@@ -616,7 +931,10 @@ impl ASTNode<Block> {
 impl ASTNode<BlockItem> {
     pub(crate) fn accept<V: Visitor>(&mut self, visitor: &mut V) -> Result<(), CompilerError> {
         match &mut self.kind {
-            BlockItem::D(declaration) => declaration.accept(visitor),
+            BlockItem::D(declaration) => {
+                visitor.visit_line(&self.line_number)?;
+                declaration.accept(visitor)
+            }
             BlockItem::S(statement) => statement.deref_mut().accept(visitor),
         }
     }
@@ -672,15 +990,92 @@ impl ASTNode<Expression> {
             Expression::Cast(target_type, exp) => {
                 visitor.visit_cast(&self.line_number, target_type, exp, &mut self.type_)
             }
+            Expression::CompoundLiteral(literal_type, init) => visitor.visit_compound_literal(
+                &self.line_number,
+                literal_type,
+                init,
+                &mut self.type_,
+            ),
+            Expression::SizeOf(exp) => {
+                visitor.visit_size_of(&self.line_number, exp, &mut self.type_)
+            }
+            Expression::ConditionValue | Expression::AssignmentTarget => Ok(()),
+        }
+    }
+}
+
+impl ASTNode<Expression> {
+    /// Fold an expression built only from constants, `None` if it isn't one.
+    pub(crate) fn fold_constant(&self) -> Result<Option<Const>, CompilerError> {
+        match &self.kind {
+            Expression::Constant(value) => Ok(Some(value.clone())),
+            Expression::Unary(UnaryOperator::AddressOf, _) => Ok(None),
+            Expression::Unary(op, exp) => Ok(exp.fold_constant()?.map(|c| c.apply_unary(*op))),
+            Expression::Binary {
+                op: op @ (BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr),
+                left,
+                right,
+            } => {
+                let Some(left) = left.fold_constant()? else {
+                    return Ok(None);
+                };
+                // short-circuit, the right side is never evaluated
+                if (*op == BinaryOperator::LogicalAnd) == left.is_zero() {
+                    return Ok(Some(Const::ConstInt(!left.is_zero() as i32)));
+                }
+                match right.fold_constant()? {
+                    Some(right) => Ok(Some(left.apply_binary(*op, &right)?)),
+                    None => Ok(None),
+                }
+            }
+            Expression::Binary { op, left, right } => {
+                match (left.fold_constant()?, right.fold_constant()?) {
+                    (Some(left), Some(right)) => Ok(Some(left.apply_binary(*op, &right)?)),
+                    _ => Ok(None),
+                }
+            }
+            Expression::Condition {
+                condition,
+                if_true,
+                if_false,
+            } => match (
+                condition.fold_constant()?,
+                if_true.fold_constant()?,
+                if_false.fold_constant()?,
+            ) {
+                (Some(condition), Some(if_true), Some(if_false)) => {
+                    let common_type = get_common_type(&if_true.type_(), &if_false.type_());
+                    let value = if condition.is_zero() {
+                        if_false
+                    } else {
+                        if_true
+                    };
+                    Ok(Some(value.convert_to(&common_type)))
+                }
+                _ => Ok(None),
+            },
+            Expression::Cast(target_type, exp) => {
+                Ok(exp.fold_constant()?.map(|c| c.convert_to(target_type)))
+            }
+            // the operand's type is only known once it has been type-checked
+            Expression::SizeOf(exp) if exp.type_ != Type::Void => {
+                Ok(Some(Const::ConstULong(exp.type_.size() as u64)))
+            }
+            _ => Ok(None),
         }
     }
 }
 
 impl ASTNode<Statement> {
     pub(crate) fn accept<V: Visitor>(&mut self, visitor: &mut V) -> Result<(), CompilerError> {
+        if !matches!(self.kind, Statement::Null | Statement::Compound(_)) {
+            visitor.visit_line(&self.line_number)?;
+        }
         match &mut self.kind {
             Statement::Return(val) => visitor.visit_return(&self.line_number, val),
-            Statement::Expression(exp) => exp.accept(visitor),
+            Statement::Expression(exp) => {
+                visitor.visit_expression_statement(&self.line_number, exp)
+            }
             Statement::If {
                 condition,
                 if_true,
@@ -688,9 +1083,7 @@ impl ASTNode<Statement> {
             } => visitor.visit_if_else(&self.line_number, condition, if_true, if_false),
             Statement::Compound(block) => visitor.visit_block(&self.line_number, &mut block.kind),
             Statement::Break(label) => visitor.visit_break(&self.line_number, label),
-            Statement::Continue { label, is_for } => {
-                visitor.visit_continue(&self.line_number, label, is_for)
-            }
+            Statement::Continue(label) => visitor.visit_continue(&self.line_number, label),
             Statement::While {
                 condition,
                 body,
@@ -704,11 +1097,40 @@ impl ASTNode<Statement> {
                 body,
                 label,
             } => visitor.visit_for(&self.line_number, init, condition, increment, body, label),
+            Statement::Switch {
+                condition,
+                body,
+                label,
+                cases,
+            } => visitor.visit_switch(&self.line_number, condition, body, label, cases),
+            Statement::Case { value, body, label } => {
+                visitor.visit_case(&self.line_number, value, body, label)
+            }
             Statement::Null => Ok(()),
         }
     }
 }
 
+impl ASTNode<Statement> {
+    /// Whether an enclosing `switch` can jump into this statement through a `case` or
+    /// `default`, which keeps otherwise dead code alive.
+    pub(crate) fn has_case_label(&self) -> bool {
+        match &self.kind {
+            Statement::Case { .. } => true,
+            Statement::If {
+                if_true, if_false, ..
+            } => if_true.has_case_label() || if_false.as_ref().is_some_and(|s| s.has_case_label()),
+            Statement::Compound(block) => block.kind.iter().any(|item| match &item.kind {
+                BlockItem::S(statement) => statement.has_case_label(),
+                BlockItem::D(_) => false,
+            }),
+            Statement::While { body, .. } | Statement::For { body, .. } => body.has_case_label(),
+            // cases inside a nested switch belong to that switch
+            _ => false,
+        }
+    }
+}
+
 impl ASTNode<ForInit> {
     pub(crate) fn accept<V: Visitor>(&mut self, visitor: &mut V) -> Result<(), CompilerError> {
         match &mut self.kind {
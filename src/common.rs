@@ -1,6 +1,12 @@
+use crate::errors::CompilerError;
+use crate::errors::CompilerError::SemanticError;
+use crate::lexer::{BinaryOperator, Type, UnaryOperator};
+use crate::type_check::get_common_type;
+use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
+use std::rc::Rc;
 
-pub(crate) type Position = (i32, String);
+pub type Position = (i32, String);
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Const {
@@ -10,11 +16,137 @@ pub(crate) enum Const {
     ConstULong(u64),
 }
 
+macro_rules! apply_integer_op {
+    ($op:expr, $a:expr, $b:expr, $variant:path) => {
+        match $op {
+            BinaryOperator::Addition => Ok($variant($a.wrapping_add($b))),
+            BinaryOperator::Subtraction => Ok($variant($a.wrapping_sub($b))),
+            BinaryOperator::Multiply => Ok($variant($a.wrapping_mul($b))),
+            BinaryOperator::Divide | BinaryOperator::Modulo if $b == 0 => Err(SemanticError(
+                "Division by zero in constant expression".to_string().into(),
+            )),
+            BinaryOperator::Divide => Ok($variant($a.wrapping_div($b))),
+            BinaryOperator::Modulo => Ok($variant($a.wrapping_rem($b))),
+            BinaryOperator::BitwiseAnd => Ok($variant($a & $b)),
+            BinaryOperator::BitwiseOr => Ok($variant($a | $b)),
+            BinaryOperator::BitwiseXor => Ok($variant($a ^ $b)),
+            BinaryOperator::BitwiseShiftLeft => Ok($variant($a.wrapping_shl($b as u32))),
+            BinaryOperator::BitwiseShiftRight => Ok($variant($a.wrapping_shr($b as u32))),
+            BinaryOperator::Equals => Ok(Const::ConstInt(($a == $b) as i32)),
+            BinaryOperator::NotEquals => Ok(Const::ConstInt(($a != $b) as i32)),
+            BinaryOperator::LessThan => Ok(Const::ConstInt(($a < $b) as i32)),
+            BinaryOperator::LessThanOrEquals => Ok(Const::ConstInt(($a <= $b) as i32)),
+            BinaryOperator::GreaterThan => Ok(Const::ConstInt(($a > $b) as i32)),
+            BinaryOperator::GreaterThanOrEquals => Ok(Const::ConstInt(($a >= $b) as i32)),
+            BinaryOperator::LogicalAnd => Ok(Const::ConstInt(($a != 0 && $b != 0) as i32)),
+            BinaryOperator::LogicalOr => Ok(Const::ConstInt(($a != 0 || $b != 0) as i32)),
+            BinaryOperator::Ternary | BinaryOperator::Assign => Err(SemanticError(
+                format!("{:?} is not allowed in a constant expression", $op).into(),
+            )),
+        }
+    };
+}
+
 impl Const {
     pub(crate) fn size(&self) -> i32 {
+        self.type_().size()
+    }
+
+    pub(crate) fn type_(&self) -> Type {
         match self {
-            Const::ConstInt(_) | Const::ConstUInt(_) => 4,
-            Const::ConstLong(_) | Const::ConstULong(_) => 8,
+            Const::ConstInt(_) => Type::Int,
+            Const::ConstLong(_) => Type::Long,
+            Const::ConstUInt(_) => Type::UInt,
+            Const::ConstULong(_) => Type::ULong,
+        }
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        match self {
+            Const::ConstInt(i) => *i == 0,
+            Const::ConstLong(i) => *i == 0,
+            Const::ConstUInt(i) => *i == 0,
+            Const::ConstULong(i) => *i == 0,
+        }
+    }
+
+    /// The exact value, wide enough to compare constants of any type.
+    pub(crate) fn value(&self) -> i128 {
+        match self {
+            Const::ConstInt(i) => *i as i128,
+            Const::ConstLong(i) => *i as i128,
+            Const::ConstUInt(i) => *i as i128,
+            Const::ConstULong(i) => *i as i128,
+        }
+    }
+
+    /// Convert to `t` the same way a cast does at runtime: truncate, sign or zero extend.
+    pub(crate) fn convert_to(&self, t: &Type) -> Const {
+        let bits = match self {
+            Const::ConstInt(i) => *i as i64 as u64,
+            Const::ConstLong(i) => *i as u64,
+            Const::ConstUInt(i) => *i as u64,
+            Const::ConstULong(i) => *i,
+        };
+        match t {
+            Type::Int => Const::ConstInt(bits as i32),
+            Type::Long => Const::ConstLong(bits as i64),
+            Type::UInt => Const::ConstUInt(bits as u32),
+            Type::ULong => Const::ConstULong(bits),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Evaluate `self op rhs` after the usual arithmetic conversions, wrapping on overflow.
+    pub(crate) fn apply_binary(
+        &self,
+        op: BinaryOperator,
+        rhs: &Const,
+    ) -> Result<Const, CompilerError> {
+        let common_type = get_common_type(&self.type_(), &rhs.type_());
+        match (self.convert_to(&common_type), rhs.convert_to(&common_type)) {
+            (Const::ConstInt(a), Const::ConstInt(b)) => {
+                apply_integer_op!(op, a, b, Const::ConstInt)
+            }
+            (Const::ConstLong(a), Const::ConstLong(b)) => {
+                apply_integer_op!(op, a, b, Const::ConstLong)
+            }
+            (Const::ConstUInt(a), Const::ConstUInt(b)) => {
+                apply_integer_op!(op, a, b, Const::ConstUInt)
+            }
+            (Const::ConstULong(a), Const::ConstULong(b)) => {
+                apply_integer_op!(op, a, b, Const::ConstULong)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn apply_unary(&self, op: UnaryOperator) -> Const {
+        match op {
+            UnaryOperator::UnaryAdd => self.clone(),
+            UnaryOperator::LogicalNot => Const::ConstInt(self.is_zero() as i32),
+            UnaryOperator::Negate => match self {
+                Const::ConstInt(i) => Const::ConstInt(i.wrapping_neg()),
+                Const::ConstLong(i) => Const::ConstLong(i.wrapping_neg()),
+                Const::ConstUInt(i) => Const::ConstUInt(i.wrapping_neg()),
+                Const::ConstULong(i) => Const::ConstULong(i.wrapping_neg()),
+            },
+            UnaryOperator::BitwiseNot => match self {
+                Const::ConstInt(i) => Const::ConstInt(!i),
+                Const::ConstLong(i) => Const::ConstLong(!i),
+                Const::ConstUInt(i) => Const::ConstUInt(!i),
+                Const::ConstULong(i) => Const::ConstULong(!i),
+            },
+            UnaryOperator::Increment | UnaryOperator::Decrement => {
+                let op = if op == UnaryOperator::Increment {
+                    BinaryOperator::Addition
+                } else {
+                    BinaryOperator::Subtraction
+                };
+                self.apply_binary(op, &Const::ConstInt(1).convert_to(&self.type_()))
+                    .unwrap()
+            }
+            UnaryOperator::AddressOf => unreachable!("constants have no address"),
         }
     }
 }
@@ -41,3 +173,119 @@ impl From<u64> for Const {
         Const::ConstLong(v as i64)
     }
 }
+
+/// Hands out one shared `Rc<str>` per distinct string, so maps keyed by names neither
+/// copy a name for every entry nor allocate to look one up.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    strings: HashSet<Rc<str>>,
+}
+
+impl Interner {
+    pub(crate) fn intern(&mut self, s: &str) -> Rc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return Rc::clone(interned);
+        }
+        let interned: Rc<str> = Rc::from(s);
+        self.strings.insert(Rc::clone(&interned));
+        interned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Const::{ConstInt, ConstLong, ConstUInt, ConstULong};
+    use super::*;
+
+    #[test]
+    fn test_unsigned_wraparound() {
+        assert_eq!(
+            ConstUInt(0)
+                .apply_binary(BinaryOperator::Subtraction, &ConstUInt(1))
+                .unwrap(),
+            ConstUInt(u32::MAX)
+        );
+        assert_eq!(
+            ConstULong(u64::MAX)
+                .apply_binary(BinaryOperator::Addition, &ConstULong(2))
+                .unwrap(),
+            ConstULong(1)
+        );
+        assert_eq!(
+            ConstUInt(1).apply_unary(UnaryOperator::Negate),
+            ConstUInt(u32::MAX)
+        );
+        // int converts to unsigned int, so -1 is the largest value
+        assert_eq!(
+            ConstInt(-1)
+                .apply_binary(BinaryOperator::GreaterThan, &ConstUInt(1))
+                .unwrap(),
+            ConstInt(1)
+        );
+    }
+
+    #[test]
+    fn test_signed_long_arithmetic() {
+        assert_eq!(
+            ConstLong(i64::MAX)
+                .apply_binary(BinaryOperator::Addition, &ConstInt(1))
+                .unwrap(),
+            ConstLong(i64::MIN)
+        );
+        assert_eq!(
+            ConstInt(-7)
+                .apply_binary(BinaryOperator::Divide, &ConstLong(2))
+                .unwrap(),
+            ConstLong(-3)
+        );
+        assert_eq!(
+            ConstInt(-7)
+                .apply_binary(BinaryOperator::Modulo, &ConstLong(2))
+                .unwrap(),
+            ConstLong(-1)
+        );
+        assert_eq!(
+            ConstLong(3000000000)
+                .apply_binary(BinaryOperator::Multiply, &ConstInt(2))
+                .unwrap(),
+            ConstLong(6000000000)
+        );
+    }
+
+    #[test]
+    fn test_display_extremes() {
+        // these print straight into .long/.quad directives, so must be exact decimals
+        assert_eq!(ConstULong(u64::MAX).to_string(), "18446744073709551615");
+        assert_eq!(ConstUInt(u32::MAX).to_string(), "4294967295");
+        assert_eq!(ConstLong(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(ConstInt(i32::MIN).to_string(), "-2147483648");
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert!(matches!(
+            ConstInt(1).apply_binary(BinaryOperator::Divide, &ConstInt(0)),
+            Err(SemanticError(_))
+        ));
+    }
+
+    #[test]
+    fn test_convert_to() {
+        assert_eq!(ConstInt(-1).convert_to(&Type::ULong), ConstULong(u64::MAX));
+        assert_eq!(
+            ConstUInt(u32::MAX).convert_to(&Type::Long),
+            ConstLong(4294967295)
+        );
+        assert_eq!(ConstLong(4294967298).convert_to(&Type::Int), ConstInt(2));
+    }
+
+    #[test]
+    fn test_interner_shares_equal_strings() {
+        let mut interner = Interner::default();
+        let first = interner.intern("x");
+        let label = String::from("x");
+        assert!(Rc::ptr_eq(&first, &interner.intern(&label)));
+        assert!(!Rc::ptr_eq(&first, &interner.intern("y")));
+        assert_eq!(&*interner.intern("y"), "y");
+    }
+}
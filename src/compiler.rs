@@ -1,20 +1,264 @@
-use std::collections::VecDeque;
-use crate::asm_ast::assembly_fix;
-use crate::lexer::lex;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::asm_ast::{assembly_fix, mangle_symbols};
 use crate::parser::Parser;
-use crate::errors::CompilerError;
+use crate::preprocessor::preprocess;
+use crate::errors::{CompilerError, Diagnostic};
+
+/// Platform whose assembler and linker conventions the output follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Linux,
+    MacOs,
+    Windows,
+}
+
+impl Target {
+    /// The platform the compiler itself was built for.
+    pub fn host() -> Self {
+        if cfg!(target_os = "macos") {
+            Target::MacOs
+        } else if cfg!(windows) {
+            Target::Windows
+        } else {
+            Target::Linux
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linux" => Ok(Target::Linux),
+            "macos" => Ok(Target::MacOs),
+            "windows" => Ok(Target::Windows),
+            _ => Err(format!("Unknown target {}", s)),
+        }
+    }
+}
+
+/// How hard the compiler works to shrink the emitted code.
+///
+/// The default is `O2`, which produces the code the compiler emitted before levels
+/// existed, since the passes it runs were already always on. `O0` is not that earlier
+/// output but a plainer translation with every pass, inlining included, turned off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// Translate each statement as written
+    O0,
+    /// Expand calls to `inline` functions, fold constant loop conditions, branch straight
+    /// on `&&`/`||` conditions, fuse compares into branches, share divisions and drop
+    /// unreachable code and unused static functions
+    O1,
+    /// Also remove dead stores, hoist loop invariants and turn tail recursion into loops
+    O2,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            _ => Err(format!("Unknown optimization level {}", s)),
+        }
+    }
+}
+
+/// Settings that change the code the compiler emits.
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub target: Target,
+    /// Abort on signed `int`/`long` overflow instead of silently wrapping
+    pub trap_overflow: bool,
+    /// Directory `#include "..."` in the main source is relative to
+    pub source_dir: PathBuf,
+    /// Searched for `#include <...>`, and for `#include "..."` not found next to the includer
+    pub include_dirs: Vec<PathBuf>,
+    /// Print a summary of each pass to stderr
+    pub verbose: bool,
+    /// Source file named in the `.file`/`.loc` line tables; `None` emits no debug info
+    pub debug_source: Option<PathBuf>,
+    /// Which optimization passes run; `O2` unless lowered with `-O`
+    pub opt_level: OptLevel,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            target: Target::host(),
+            trap_overflow: false,
+            source_dir: PathBuf::new(),
+            include_dirs: vec![],
+            verbose: false,
+            debug_source: None,
+            opt_level: OptLevel::O2,
+        }
+    }
+}
+
+impl CompileOptions {
+    /// Report on stderr what a pass did, when `verbose` is on.
+    pub(crate) fn log(&self, summary: std::fmt::Arguments) {
+        if self.verbose {
+            eprintln!("{}", summary);
+        }
+    }
+}
 
 pub fn compile(source: String) -> Result<String, CompilerError> {
+    compile_with(source, &CompileOptions::default())
+}
+
+pub fn compile_for(source: String, target: Target) -> Result<String, CompilerError> {
+    compile_with(
+        source,
+        &CompileOptions {
+            target,
+            ..CompileOptions::default()
+        },
+    )
+}
+
+pub fn compile_with(source: String, options: &CompileOptions) -> Result<String, CompilerError> {
+    compile_with_warnings(source, options).map(|(asm, _)| asm)
+}
+
+/// Like `compile_with`, also returning warnings about valid but suspicious code.
+pub fn compile_with_warnings(
+    source: String,
+    options: &CompileOptions,
+) -> Result<(String, Vec<Diagnostic>), CompilerError> {
     let mut out = String::with_capacity(1024);
-    let tokens = lex(source);
+    let warnings = emit(&source, options, &mut out)?;
+    Ok((out, warnings))
+}
+
+/// The control-flow graph of every function in `source` as Graphviz DOT, one digraph
+/// per function whose basic blocks are linked by jump and fallthrough edges.
+pub fn dump_cfg_dot(source: String) -> Result<String, CompilerError> {
+    dump_cfg_dot_with(source, &CompileOptions::default())
+}
+
+/// Like `dump_cfg_dot`, building the graphs from the TAC `options` produces.
+pub fn dump_cfg_dot_with(
+    source: String,
+    options: &CompileOptions,
+) -> Result<String, CompilerError> {
+    let tokens = preprocess(&source, &options.source_dir, &options.include_dirs)?;
+    let mut parser = Parser::new(tokens);
+    let mut program_node = parser.parse_program()?;
+    let mut dot = String::new();
+    program_node.generate(
+        &mut |_| Ok(()),
+        options,
+        &mut parser.warnings,
+        Some(&mut dot),
+    )?;
+    Ok(dot)
+}
+
+/// Like `compile`, writing the assembly to `w` instead of returning it as one string.
+pub fn compile_to_writer<W: std::io::Write>(source: &str, w: &mut W) -> Result<(), CompilerError> {
+    compile_to_writer_with(source, &CompileOptions::default(), w)
+}
+
+/// Like `compile_with`, writing each function's assembly to `w` as soon as it is generated.
+/// Only static functions, whose use is known once every function is seen, and the statics
+/// wait for the end, so an error part way through leaves the earlier functions written.
+pub fn compile_to_writer_with<W: std::io::Write>(
+    source: &str,
+    options: &CompileOptions,
+    w: &mut W,
+) -> Result<(), CompilerError> {
+    let mut sink = IoSink {
+        inner: w,
+        error: None,
+    };
+    match emit(source, options, &mut sink) {
+        Ok(_) => Ok(()),
+        Err(err) => match sink.error {
+            Some(io_error) => Err(CompilerError::IoError(io_error.to_string().into())),
+            None => Err(err),
+        },
+    }
+}
+
+/// Adapts an `io::Write` for `make_assembly`, keeping the error `fmt::Error` can't carry.
+struct IoSink<'a, W: std::io::Write> {
+    inner: &'a mut W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: std::io::Write> std::fmt::Write for IoSink<'_, W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            std::fmt::Error
+        })
+    }
+}
+
+/// Passes assembly through to `inner`, counting the lines that hold more than whitespace.
+struct LineCounter<'a, W: std::fmt::Write> {
+    inner: &'a mut W,
+    lines: usize,
+    in_line: bool,
+}
+
+impl<W: std::fmt::Write> std::fmt::Write for LineCounter<'_, W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                self.in_line = false;
+            } else if !self.in_line && !c.is_whitespace() {
+                self.in_line = true;
+                self.lines += 1;
+            }
+        }
+        self.inner.write_str(s)
+    }
+}
+
+/// Compile `source`, writing each function's instructions to `out` once it is generated.
+fn emit<W: std::fmt::Write>(
+    source: &str,
+    options: &CompileOptions,
+    out: &mut W,
+) -> Result<Vec<Diagnostic>, CompilerError> {
+    let write_error = |_| CompilerError::IoError("Failed to write assembly".to_string().into());
+    let tokens = preprocess(source, &options.source_dir, &options.include_dirs)?;
+    options.log(format_args!("lex: {} tokens", tokens.len()));
     let mut parser = Parser::new(tokens);
     let mut program_node = parser.parse_program()?;
-    let mut asm = VecDeque::new();
-    program_node.generate(&mut asm)?;
-    let asm = assembly_fix(asm);
-    for instruction in asm.iter() {
-        out += "\n";
-        instruction.make_assembly(&mut out);
-    }
-    Ok(out)
+    options.log(format_args!("parse: {} nodes", parser.node_count()));
+    if let Some(path) = &options.debug_source {
+        out.write_fmt(format_args!("\n.file 1 {:?}\n", path.display().to_string()))
+            .map_err(write_error)?;
+    }
+    let mut counter = LineCounter {
+        inner: out,
+        lines: 0,
+        in_line: false,
+    };
+    program_node.generate(
+        &mut |code| {
+            for instruction in mangle_symbols(assembly_fix(code), options.target) {
+                std::fmt::Write::write_str(&mut counter, "\n").map_err(write_error)?;
+                instruction
+                    .make_assembly(&mut counter, options.target)
+                    .map_err(write_error)?;
+            }
+            Ok(())
+        },
+        options,
+        &mut parser.warnings,
+        None,
+    )?;
+    options.log(format_args!("emit: {} assembly lines", counter.lines));
+    Ok(parser.warnings)
 }
@@ -0,0 +1,91 @@
+use crate::tac::{FunctionBody, TACInstruction};
+use std::collections::HashMap;
+
+/// Blocks control can reach from the end of block `index`, with the edge's label.
+fn successors(
+    last: &TACInstruction,
+    index: usize,
+    block_count: usize,
+    blocks_by_label: &HashMap<&str, usize>,
+) -> Vec<(usize, Option<&'static str>)> {
+    let block = |label: &str| blocks_by_label[label];
+    let next = Some(index + 1).filter(|&next| next < block_count);
+    match last {
+        TACInstruction::ReturnInstruction { .. } => vec![],
+        TACInstruction::Jump { label } => vec![(block(label), None)],
+        TACInstruction::JumpTable { targets, .. } => {
+            let mut blocks: Vec<usize> = targets.iter().map(|label| block(label)).collect();
+            blocks.sort();
+            blocks.dedup();
+            blocks.into_iter().map(|block| (block, None)).collect()
+        }
+        TACInstruction::JumpIfZero { label, .. }
+        | TACInstruction::JumpIfNotZero { label, .. }
+        | TACInstruction::JumpIfComparison { label, .. } => {
+            let mut edges = vec![(block(label), Some("taken"))];
+            edges.extend(next.map(|next| (next, Some("fallthrough"))));
+            edges
+        }
+        _ => next.map(|next| (next, None)).into_iter().collect(),
+    }
+}
+
+/*
+Split a function's TAC into basic blocks, each starting at a label or right after
+a jump or return, and write them as a Graphviz digraph with an edge for every
+jump target and fallthrough. A conditional jump gets both a taken and a
+fallthrough edge.
+ */
+pub(crate) fn write_dot(function_body: &FunctionBody, name: &str, out: &mut String) {
+    let instructions = &function_body.instructions;
+    let mut starts = vec![0];
+    for (i, instruction) in instructions.iter().enumerate() {
+        let start = match instruction {
+            TACInstruction::Label { .. } => i,
+            TACInstruction::Jump { .. }
+            | TACInstruction::JumpTable { .. }
+            | TACInstruction::JumpIfZero { .. }
+            | TACInstruction::JumpIfNotZero { .. }
+            | TACInstruction::JumpIfComparison { .. }
+            | TACInstruction::ReturnInstruction { .. } => i + 1,
+            _ => continue,
+        };
+        if start < instructions.len() && starts.last() != Some(&start) {
+            starts.push(start);
+        }
+    }
+    let blocks: Vec<&[TACInstruction]> = starts
+        .iter()
+        .enumerate()
+        .map(|(k, &start)| &instructions[start..*starts.get(k + 1).unwrap_or(&instructions.len())])
+        .collect();
+    let blocks_by_label: HashMap<&str, usize> = blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(k, block)| match block.first() {
+            Some(TACInstruction::Label { label }) => Some((label.as_str(), k)),
+            _ => None,
+        })
+        .collect();
+
+    out.push_str(&format!("digraph \"{}\" {{\n    node [shape=box];\n", name));
+    for (k, block) in blocks.iter().enumerate() {
+        let text: String = block
+            .iter()
+            .map(|instruction| format!("{:?}\\l", instruction).replace('"', "\\\""))
+            .collect();
+        out.push_str(&format!("    b{} [label=\"{}\"];\n", k, text));
+    }
+    for (k, block) in blocks.iter().enumerate() {
+        let Some(last) = block.last() else {
+            continue;
+        };
+        for (target, label) in successors(last, k, blocks.len(), &blocks_by_label) {
+            let attributes = label
+                .map(|label| format!(" [label=\"{}\"]", label))
+                .unwrap_or_default();
+            out.push_str(&format!("    b{} -> b{}{};\n", k, target, attributes));
+        }
+    }
+    out.push_str("}\n");
+}
@@ -1,10 +1,116 @@
+use crate::common::Position;
 use std::error::Error;
 use std::fmt;
+use std::ops::Deref;
+
+/// Broad category of a `CompilerError`, for tools that don't want to match on messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Syntax,
+    Semantic,
+    Type,
+    Io,
+}
+
+/// Human readable message plus where in the source it was raised, when known.
+#[derive(Debug)]
+pub struct Diagnostic {
+    message: String,
+    position: Option<Position>,
+    /// 1-based character offset into the line, for errors the lexer and preprocessor find
+    column: Option<usize>,
+}
+
+impl Diagnostic {
+    pub(crate) fn at(message: String, position: &Position) -> Self {
+        Diagnostic {
+            message,
+            position: Some(position.clone()),
+            column: None,
+        }
+    }
+
+    /// Diagnostic for a spot in the raw source, found before there are functions to name.
+    pub(crate) fn at_column(message: String, line: i32, column: usize) -> Self {
+        Diagnostic {
+            message,
+            position: Some((line, String::new())),
+            column: Some(column),
+        }
+    }
+}
+
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Diagnostic {
+            message,
+            position: None,
+            column: None,
+        }
+    }
+}
+
+impl Deref for Diagnostic {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
 
 #[derive(Debug)]
 pub enum CompilerError {
-    SyntaxError(String),
-    SemanticError(String),
+    SyntaxError(Diagnostic),
+    SemanticError(Diagnostic),
+    /// Operands or values whose types don't fit where they are used
+    TypeError(Diagnostic),
+    /// The assembly could not be written to the output sink
+    IoError(Diagnostic),
+}
+
+impl CompilerError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            CompilerError::SyntaxError(_) => ErrorKind::Syntax,
+            CompilerError::SemanticError(_) => ErrorKind::Semantic,
+            CompilerError::TypeError(_) => ErrorKind::Type,
+            CompilerError::IoError(_) => ErrorKind::Io,
+        }
+    }
+
+    /// Line and enclosing function the error was raised at.
+    pub fn position(&self) -> Option<&Position> {
+        self.diagnostic().position.as_ref()
+    }
+
+    pub fn line(&self) -> Option<i32> {
+        self.position().map(|(line, _)| *line)
+    }
+
+    /// Column within `line()`, known only for errors in the raw text such as an
+    /// unterminated string or comment.
+    pub fn column(&self) -> Option<usize> {
+        self.diagnostic().column
+    }
+
+    pub fn message(&self) -> &str {
+        &self.diagnostic().message
+    }
+
+    fn diagnostic(&self) -> &Diagnostic {
+        match self {
+            CompilerError::SyntaxError(diagnostic)
+            | CompilerError::SemanticError(diagnostic)
+            | CompilerError::TypeError(diagnostic)
+            | CompilerError::IoError(diagnostic) => diagnostic,
+        }
+    }
 }
 
 impl fmt::Display for CompilerError {
@@ -12,6 +118,8 @@ impl fmt::Display for CompilerError {
         match self {
             CompilerError::SyntaxError(what) => write!(f, "Syntax Error: {}", what),
             CompilerError::SemanticError(what) => write!(f, "Semantic Error: {}", what),
+            CompilerError::TypeError(what) => write!(f, "Type Error: {}", what),
+            CompilerError::IoError(what) => write!(f, "IO Error: {}", what),
         }
     }
 }
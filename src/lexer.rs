@@ -1,6 +1,9 @@
 use crate::common::Const;
 use crate::common::Const::{ConstInt, ConstLong, ConstUInt, ConstULong};
+use crate::errors::CompilerError::SyntaxError;
+use crate::errors::{CompilerError, Diagnostic};
 use crate::lexer::Symbol::{Ambiguous, Binary, Unary};
+use std::cell::Cell;
 use std::collections::VecDeque;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -36,6 +39,7 @@ pub(crate) enum UnaryOperator {
     BitwiseNot,
     Negate,
     UnaryAdd,
+    AddressOf,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -62,6 +66,15 @@ pub(crate) enum Symbol {
 pub(crate) enum StorageClass {
     Static,
     Extern,
+    Auto,
+    Register,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Qualifier {
+    Volatile,
+    Restrict,
+    Const,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -76,11 +89,41 @@ pub(crate) enum Type {
 }
 
 impl Type {
+    /// Bytes a value of this type occupies. Operand, register and constant sizes all
+    /// come from here.
     pub(crate) fn size(&self) -> i32 {
         match self {
             Type::Void => 0,
             Type::Int | Type::UInt => 4,
             Type::Long | Type::ULong => 8,
+            Type::Signed | Type::Unsigned => unreachable!("{:?} is only a specifier", self),
+        }
+    }
+
+    /// Natural alignment of a value of this type, in bytes.
+    pub(crate) fn align(&self) -> i32 {
+        match self {
+            Type::Void => 1,
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong => 8,
+            Type::Signed | Type::Unsigned => unreachable!("{:?} is only a specifier", self),
+        }
+    }
+
+    /// The signed integer type `size` bytes wide, for viewing a register at that width.
+    pub(crate) fn of_size(size: i32) -> Type {
+        match size {
+            4 => Type::Int,
+            8 => Type::Long,
+            _ => unreachable!("no integer type is {} bytes", size),
+        }
+    }
+
+    /// Assembler directive that emits an initialized value of this type.
+    pub(crate) fn data_directive(&self) -> &'static str {
+        match self {
+            Type::Int | Type::UInt => "long",
+            Type::Long | Type::ULong => "quad",
             _ => unreachable!(),
         }
     }
@@ -96,8 +139,16 @@ pub(crate) enum Keyword {
     For,
     Continue,
     Break,
+    Switch,
+    Case,
+    Default,
     Type(Type),
     StorageClass(StorageClass),
+    Qualifier(Qualifier),
+    Inline,
+    Attribute,
+    SizeOf,
+    StaticAssert,
 }
 
 #[derive(Debug, Clone, PartialEq)] // String prevents Copy. PartialEq is useful for tests.
@@ -106,9 +157,14 @@ pub(crate) enum Token {
     Symbol(Symbol),
     Name(String),
     NumberLiteral(Const),
+    /// Contents of a `"..."` literal with its escapes resolved
+    StringLiteral(String),
     Invalid,
     Overflow,
     EOF,
+    /// The tokens that follow start on this source line. Only the parser's position
+    /// tracking sees these; they never reach the grammar.
+    Line(i32),
 }
 
 fn match_keyword(string: &str) -> Option<Keyword> {
@@ -122,20 +178,78 @@ fn match_keyword(string: &str) -> Option<Keyword> {
         "for" => Some(Keyword::For),
         "continue" => Some(Keyword::Continue),
         "break" => Some(Keyword::Break),
+        "switch" => Some(Keyword::Switch),
+        "case" => Some(Keyword::Case),
+        "default" => Some(Keyword::Default),
         "static" => Some(Keyword::StorageClass(StorageClass::Static)),
         "extern" => Some(Keyword::StorageClass(StorageClass::Extern)),
+        "auto" => Some(Keyword::StorageClass(StorageClass::Auto)),
+        "register" => Some(Keyword::StorageClass(StorageClass::Register)),
         "long" => Some(Keyword::Type(Type::Long)),
         "unsigned" => Some(Keyword::Type(Type::Unsigned)),
         "signed" => Some(Keyword::Type(Type::Signed)),
+        "volatile" => Some(Keyword::Qualifier(Qualifier::Volatile)),
+        "const" => Some(Keyword::Qualifier(Qualifier::Const)),
+        "restrict" | "__restrict" => Some(Keyword::Qualifier(Qualifier::Restrict)),
+        "inline" | "__inline" | "__inline__" => Some(Keyword::Inline),
+        "__attribute__" | "__attribute" => Some(Keyword::Attribute),
+        "sizeof" => Some(Keyword::SizeOf),
+        "_Static_assert" => Some(Keyword::StaticAssert),
         _ => None,
     }
 }
 
-pub(crate) fn lex(source: String) -> VecDeque<Token> {
+/// `(is_long, is_unsigned)` for an integer constant's suffix: at most one `u`, before or
+/// after at most one `l` or `ll`, whose letters must share a case. `None` if invalid.
+fn integer_suffix(suffix: &str) -> Option<(bool, bool)> {
+    let (length, is_unsigned) = match suffix
+        .strip_prefix(['u', 'U'])
+        .or_else(|| suffix.strip_suffix(['u', 'U']))
+    {
+        Some(length) => (length, true),
+        None => (suffix, false),
+    };
+    match length {
+        "" => Some((false, is_unsigned)),
+        "l" | "L" | "ll" | "LL" => Some((true, is_unsigned)),
+        _ => None,
+    }
+}
+
+/// Error for a string or comment still open at the end of its line or the file, placed
+/// where it started: `offset` bytes into `source`
+fn unterminated(what: &str, line: i32, source: &str, offset: usize) -> CompilerError {
+    let line_start = source[..offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    let column = source[line_start..offset].chars().count() + 1;
+    SyntaxError(Diagnostic::at_column(
+        format!(
+            "Unterminated {} starting at line {}, column {}",
+            what, line, column
+        ),
+        line,
+        column,
+    ))
+}
+
+/// Lex a piece of a file starting at `first_line`. The caller appends the EOF once every
+/// included file has been spliced in.
+pub(crate) fn lex(source: &str, first_line: i32) -> Result<VecDeque<Token>, CompilerError> {
     let mut tokens: VecDeque<Token> = VecDeque::new();
-    let mut chars = source.chars().peekable();
+    // byte offset of the last char taken from `source`, which right after `chars.next()`
+    // is the one it returned
+    let offset = Cell::new(0);
+    let mut chars = source
+        .char_indices()
+        .inspect(|(i, _)| offset.set(*i))
+        .map(|(_, c)| c)
+        .peekable();
+    let mut line = first_line;
+    let mut marked_line = None;
 
     'main_loop: while let Some(c) = chars.next() {
+        let token_start = offset.get();
         let next: Token = match c {
             '{' => Token::Symbol(Symbol::OpenBrace),
             '}' => Token::Symbol(Symbol::CloseBrace),
@@ -169,10 +283,30 @@ pub(crate) fn lex(source: String) -> VecDeque<Token> {
                     // single line comment
                     while let Some(next) = chars.next() {
                         if next == '\n' {
+                            line += 1;
                             break;
                         }
                     }
                     continue;
+                } else if chars.peek() == Some(&'*') {
+                    chars.next();
+                    let start = line;
+                    let mut previous = ' ';
+                    loop {
+                        match chars.next() {
+                            Some('/') if previous == '*' => break,
+                            Some(next) => {
+                                if next == '\n' {
+                                    line += 1;
+                                }
+                                previous = next;
+                            }
+                            None => {
+                                return Err(unterminated("comment", start, source, token_start));
+                            }
+                        }
+                    }
+                    continue;
                 } else {
                     Token::Symbol(Binary(BinaryOperator::Divide))
                 }
@@ -234,6 +368,25 @@ pub(crate) fn lex(source: String) -> VecDeque<Token> {
                 }
             }
             ',' => Token::Symbol(Symbol::Comma),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break Token::StringLiteral(string),
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(escaped @ ('"' | '\'' | '\\' | '?')) => string.push(escaped),
+                            _ => break Token::Invalid,
+                        },
+                        // a literal may not run past the end of its line
+                        Some('\n') | None => {
+                            return Err(unterminated("string literal", line, source, token_start));
+                        }
+                        Some(c) => string.push(c),
+                    }
+                }
+            }
             '0'..='9' => {
                 let mut number_string = String::new();
                 number_string.push(c);
@@ -244,29 +397,18 @@ pub(crate) fn lex(source: String) -> VecDeque<Token> {
                     number_string.push(*char);
                     chars.next();
                 }
-                let mut is_long = false;
-                let mut is_unsigned = false;
-                for _ in 0..2 {
-                    match chars.peek() {
-                        Some(char) if *char == 'l' || *char == 'L' => {
-                            chars.next();
-                            if is_long {
-                                tokens.push_back(Token::Invalid);
-                                continue 'main_loop;
-                            }
-                            is_long = true;
-                        }
-                        Some(char) if *char == 'u' || *char == 'U' => {
-                            chars.next();
-                            if is_unsigned {
-                                tokens.push_back(Token::Invalid);
-                                continue 'main_loop;
-                            }
-                            is_unsigned = true;
-                        }
-                        _ => break,
+                let mut suffix = String::new();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_ascii_alphanumeric() || next == '_') {
+                        break;
                     }
+                    suffix.push(next);
+                    chars.next();
                 }
+                let Some((is_long, is_unsigned)) = integer_suffix(&suffix) else {
+                    tokens.push_back(Token::Invalid);
+                    continue 'main_loop;
+                };
                 if is_long {
                     match number_string.parse::<u64>() {
                         Ok(num) => {
@@ -314,14 +456,22 @@ pub(crate) fn lex(source: String) -> VecDeque<Token> {
                 }
                 match match_keyword(identifier.as_str()) {
                     Some(key) => Token::Keyword(key),
+                    None if identifier == "__LINE__" => Token::NumberLiteral(ConstInt(line)),
                     None => Token::Name(identifier),
                 }
             }
-            ' ' | '\n' | '\t' => continue,
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            ' ' | '\t' => continue,
             _ => Token::Invalid,
         };
+        if marked_line != Some(line) {
+            tokens.push_back(Token::Line(line));
+            marked_line = Some(line);
+        }
         tokens.push_back(next);
     }
-    tokens.push_back(Token::EOF);
-    tokens
+    Ok(tokens)
 }
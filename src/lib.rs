@@ -4,16 +4,23 @@ pub(crate) mod ast;
 pub(crate) mod common;
 pub(crate) mod lexer;
 pub(crate) mod parser;
+pub(crate) mod preprocessor;
 pub(crate) mod tac;
 pub(crate) mod tac_generator;
 pub(crate) mod variable_resolution;
 pub(crate) mod type_check;
 pub(crate) mod asm_ast;
+pub(crate) mod optimizer;
+pub(crate) mod control_flow;
 
 // Make these public externally
 pub mod compiler;
 pub mod errors;
 
 // ... re-exports ...
-pub use compiler::compile;
-pub use errors::CompilerError;
+pub use common::Position;
+pub use compiler::{
+    CompileOptions, OptLevel, Target, compile, compile_for, compile_to_writer,
+    compile_to_writer_with, compile_with, compile_with_warnings, dump_cfg_dot, dump_cfg_dot_with,
+};
+pub use errors::{CompilerError, Diagnostic, ErrorKind};
@@ -1,21 +1,77 @@
 use std::{env, fs, process};
 use std::io::Write;
 use std::path::Path;
-use compiler::compile;
+use compiler::{CompileOptions, compile_with_warnings, dump_cfg_dot_with};
 
 fn main() {
     // Get command line arguments
     let args: Vec<String> = env::args().collect();
 
+    // Split the flags from the input file
+    let mut options = CompileOptions {
+        verbose: env::var_os("RUST_LOG").is_some_and(|level| !level.is_empty()),
+        ..CompileOptions::default()
+    };
+    let mut input_file = None;
+    let mut debug_info = false;
+    let mut dump_cfg = false;
+    let mut flags = args[1..].iter();
+    while let Some(arg) = flags.next() {
+        if arg == "-I" {
+            let Some(dir) = flags.next() else {
+                eprintln!("-I expects a directory");
+                process::exit(1);
+            };
+            options.include_dirs.push(dir.into());
+        } else if let Some(dir) = arg.strip_prefix("-I") {
+            options.include_dirs.push(dir.into());
+        } else if arg == "--verbose" {
+            options.verbose = true;
+        } else if arg == "-g" {
+            debug_info = true;
+        } else if arg == "--dump-cfg" {
+            dump_cfg = true;
+        } else if let Some(level) = arg.strip_prefix("-O") {
+            options.opt_level = match level.parse() {
+                Ok(level) => level,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            };
+        } else if arg == "--trap-overflow" {
+            options.trap_overflow = true;
+        } else if let Some(name) = arg.strip_prefix("--target=") {
+            options.target = match name.parse() {
+                Ok(target) => target,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            };
+        } else {
+            input_file = Some(arg);
+        }
+    }
+
     // Check if input file was provided
-    if args.len() < 2 {
-        eprintln!("Usage: {} <input file>", args[0]);
+    let Some(input_file) = input_file else {
+        eprintln!(
+            "Usage: {} [--target=linux|macos|windows] [--trap-overflow] [--verbose] [-g] [--dump-cfg] [-O0|-O1|-O2] [-I dir]... <input file>",
+            args[0]
+        );
         process::exit(1);
-    }
+    };
 
     // Get the input file path
-    let input_file = &args[1];
     let input_path = Path::new(input_file);
+    // #include "..." is relative to the file being compiled
+    if let Some(dir) = input_path.parent() {
+        options.source_dir = dir.to_path_buf();
+    }
+    if debug_info {
+        options.debug_source = Some(input_path.to_path_buf());
+    }
 
     // Check if the file exists
     if !input_path.exists() {
@@ -32,17 +88,24 @@ fn main() {
         }
     };
 
-    // Determine the output file path (change extension to .asm)
+    // Determine the output file path (change extension to .asm, or .dot for --dump-cfg)
     let output_path = {
         let mut path = input_path.to_path_buf();
-        path.set_extension("asm");
+        path.set_extension(if dump_cfg { "dot" } else { "asm" });
         path
     };
 
     // Try to compile the source code
-    match compile_and_write(&source, &output_path) {
+    let result = if dump_cfg {
+        dump_cfg_and_write(&source, &output_path, &options)
+    } else {
+        compile_and_write(&source, &output_path, &options)
+    };
+    match result {
         Ok(_) => {
-            println!("Successfully compiled to: {}", output_path.display());
+            if options.verbose {
+                eprintln!("Successfully compiled to: {}", output_path.display());
+            }
         }
         Err(err) => {
             eprintln!("{}", err);
@@ -52,9 +115,16 @@ fn main() {
 }
 
 /// Compile the source code and write the output to a file
-fn compile_and_write(source: &str, output_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+fn compile_and_write(
+    source: &str,
+    output_path: &Path,
+    options: &CompileOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Compile the source code
-    let output = compile(source.parse().unwrap())?;
+    let (output, warnings) = compile_with_warnings(source.to_string(), options)?;
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
 
     // Write the output to a file
     let mut file = fs::File::create(output_path)?;
@@ -62,3 +132,14 @@ fn compile_and_write(source: &str, output_path: &Path) -> Result<(), Box<dyn std
 
     Ok(())
 }
+
+/// Write the control-flow graph of each function to a Graphviz DOT file
+fn dump_cfg_and_write(
+    source: &str,
+    output_path: &Path,
+    options: &CompileOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dot = dump_cfg_dot_with(source.to_string(), options)?;
+    fs::write(output_path, dot)?;
+    Ok(())
+}
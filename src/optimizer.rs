@@ -0,0 +1,609 @@
+use crate::ast::FunAttr;
+use crate::lexer::BinaryOperator;
+use crate::tac::{FunctionBody, Operand, Pseudoregister, Reg, TACInstruction};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Functions a body calls or takes the address of.
+pub(crate) fn referenced_functions(function_body: &FunctionBody) -> HashSet<Rc<String>> {
+    function_body
+        .instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            TACInstruction::FunctionCall(name) | TACInstruction::LoadAddress { name, .. } => {
+                Some(Rc::clone(name))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/*
+Functions reachable through calls and address-of from the externally visible ones.
+A static function outside this set can never run, so its code needn't be emitted.
+ */
+pub(crate) fn reachable_functions(
+    references: &HashMap<Rc<String>, HashSet<Rc<String>>>,
+    functions_map: &HashMap<String, FunAttr>,
+) -> HashSet<Rc<String>> {
+    let mut pending: Vec<Rc<String>> = references
+        .keys()
+        .filter(|name| functions_map.get(name.as_str()).is_some_and(|f| f.global))
+        .cloned()
+        .collect();
+    let mut reachable = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if reachable.insert(Rc::clone(&name))
+            && let Some(referenced) = references.get(&name)
+        {
+            pending.extend(referenced.iter().cloned());
+        }
+    }
+    reachable
+}
+
+/*
+Whether control can run past the last instruction, following every branch that
+isn't decided by a constant. Returns and calls to noreturn functions end a path.
+ */
+pub(crate) fn can_fall_off_end(
+    function_body: &FunctionBody,
+    functions_map: &HashMap<String, FunAttr>,
+) -> bool {
+    let instructions = &function_body.instructions;
+    let target = |label: &str| label_position(function_body, label).into_iter();
+    let mut visited = HashSet::new();
+    let mut pending = vec![0];
+    while let Some(index) = pending.pop() {
+        if index == instructions.len() {
+            return true;
+        }
+        if !visited.insert(index) {
+            continue;
+        }
+        let next = Some(index + 1).into_iter();
+        match &instructions[index] {
+            TACInstruction::ReturnInstruction { .. } => {}
+            TACInstruction::FunctionCall(name)
+                if functions_map
+                    .get(name.as_ref())
+                    .is_some_and(|function| function.noreturn) => {}
+            TACInstruction::Jump { label } => pending.extend(target(label)),
+            TACInstruction::JumpTable { targets, .. } => {
+                pending.extend(targets.iter().flat_map(|label| target(label)))
+            }
+            TACInstruction::JumpIfZero { label, operand }
+            | TACInstruction::JumpIfNotZero { label, operand } => {
+                let on_zero = matches!(&instructions[index], TACInstruction::JumpIfZero { .. });
+                match operand.as_ref() {
+                    Operand::Immediate(value) if value.is_zero() == on_zero => {
+                        pending.extend(target(label))
+                    }
+                    Operand::Immediate(_) => pending.extend(next),
+                    _ => pending.extend(target(label).chain(next)),
+                }
+            }
+            TACInstruction::JumpIfComparison { label, .. } => {
+                pending.extend(target(label).chain(next))
+            }
+            _ => pending.extend(next),
+        }
+    }
+    false
+}
+
+/// Stack slot written by an instruction, if any.
+fn defined_slot(instruction: &TACInstruction) -> Option<i32> {
+    let dest = match instruction {
+        TACInstruction::StoreValueInstruction { dest, .. }
+        | TACInstruction::UnaryOpInstruction { dest, .. }
+        | TACInstruction::BinaryOpInstruction { dest, .. }
+        | TACInstruction::SignExtend { dest, .. }
+        | TACInstruction::Truncate { dest, .. }
+        | TACInstruction::ZeroExtend { dest, .. }
+        | TACInstruction::LoadAddress { dest, .. } => dest,
+        _ => return None,
+    };
+    match dest.as_ref() {
+        Pseudoregister::Pseudoregister(offset, _) => Some(*offset),
+        _ => None,
+    }
+}
+
+fn operand_slot(operand: &Rc<Operand>) -> Option<i32> {
+    match operand.as_ref() {
+        Operand::Register(Pseudoregister::Pseudoregister(offset, _)) => Some(*offset),
+        _ => None,
+    }
+}
+
+/// Stack slots read by an instruction.
+fn used_slots(instruction: &TACInstruction) -> Vec<i32> {
+    instruction
+        .used_operands()
+        .into_iter()
+        .filter_map(operand_slot)
+        .collect()
+}
+
+/// Instructions across which nothing is known about liveness.
+fn is_barrier(instruction: &TACInstruction) -> bool {
+    matches!(
+        instruction,
+        TACInstruction::Label { .. }
+            | TACInstruction::Jump { .. }
+            | TACInstruction::JumpTable { .. }
+            | TACInstruction::JumpIfZero { .. }
+            | TACInstruction::JumpIfNotZero { .. }
+            | TACInstruction::JumpIfComparison { .. }
+            | TACInstruction::ReturnInstruction { .. }
+            | TACInstruction::FunctionCall(_)
+            | TACInstruction::FunctionInstruction { .. }
+    )
+}
+
+/*
+Walk each basic block backwards, tracking the stack slots that are overwritten
+before being read. A store into one of those slots is dead, unless the slot
+belongs to a volatile variable.
+ */
+pub(crate) fn eliminate_dead_stores(function_body: &mut FunctionBody) {
+    let mut overwritten: HashSet<i32> = HashSet::new();
+    let mut dead = vec![false; function_body.instructions.len()];
+
+    for (i, instruction) in function_body.instructions.iter().enumerate().rev() {
+        if is_barrier(instruction) {
+            overwritten.clear();
+            continue;
+        }
+        if let Some(slot) = defined_slot(instruction) {
+            if matches!(instruction, TACInstruction::StoreValueInstruction { .. })
+                && overwritten.contains(&slot)
+                && !function_body.volatile_slots.contains(&slot)
+            {
+                dead[i] = true;
+                continue;
+            }
+            overwritten.insert(slot);
+        }
+        for slot in used_slots(instruction) {
+            overwritten.remove(&slot);
+        }
+    }
+
+    let mut dead = dead.into_iter();
+    function_body.instructions.retain(|_| !dead.next().unwrap());
+}
+
+/*
+Drop instructions that follow an unconditional jump, a return or a call to a
+noreturn function, up to the next label.
+ */
+pub(crate) fn remove_unreachable(
+    function_body: &mut FunctionBody,
+    functions_map: &HashMap<String, FunAttr>,
+) {
+    let mut reachable = true;
+    function_body.instructions.retain(|instruction| {
+        if matches!(instruction, TACInstruction::Label { .. }) {
+            reachable = true;
+        }
+        if !reachable {
+            return false;
+        }
+        reachable = match instruction {
+            TACInstruction::Jump { .. }
+            | TACInstruction::JumpTable { .. }
+            | TACInstruction::ReturnInstruction { .. } => false,
+            TACInstruction::FunctionCall(name) => !functions_map
+                .get(name.as_ref())
+                .is_some_and(|function| function.noreturn),
+            _ => true,
+        };
+        true
+    });
+}
+
+/// Whether `call`, `store`, `ret` return the result of a call to `name` unchanged.
+fn is_tail_call(
+    name: &str,
+    call: &TACInstruction,
+    store: &TACInstruction,
+    ret: &TACInstruction,
+) -> bool {
+    let TACInstruction::FunctionCall(callee) = call else {
+        return false;
+    };
+    let TACInstruction::StoreValueInstruction { dest, src } = store else {
+        return false;
+    };
+    let TACInstruction::ReturnInstruction { val } = ret else {
+        return false;
+    };
+    callee.as_str() == name
+        && matches!(
+            src.as_ref(),
+            Operand::Register(Pseudoregister::Register(Reg::AX, _))
+        )
+        && matches!(dest.as_ref(), Pseudoregister::Pseudoregister(..))
+        && operand_slot(val) == defined_slot(store)
+}
+
+/*
+Turn `return name(...)` inside `name` into a jump back to the code that copies the
+argument registers into the parameter slots, so self-recursion in tail position
+runs in constant stack space. Only done when every argument is passed in a register.
+ */
+pub(crate) fn eliminate_tail_recursion(
+    function_body: &mut FunctionBody,
+    name: &Rc<String>,
+    param_count: usize,
+) {
+    if param_count > 6 {
+        return;
+    }
+    let Some(entry) = function_body
+        .instructions
+        .iter()
+        .position(|instruction| matches!(instruction, TACInstruction::AllocateStackInstruction))
+    else {
+        return;
+    };
+
+    let entry_label: Rc<String> = Rc::from(format!(".{}_tail_call.entry", name));
+    let mut rewritten = false;
+    let mut i = entry + 1;
+    while i + 2 < function_body.instructions.len() {
+        let window = &function_body.instructions[i..i + 3];
+        if is_tail_call(name, &window[0], &window[1], &window[2]) {
+            function_body.instructions.splice(
+                i..i + 3,
+                [TACInstruction::Jump {
+                    label: Rc::clone(&entry_label),
+                }],
+            );
+            rewritten = true;
+        }
+        i += 1;
+    }
+
+    if rewritten {
+        function_body
+            .instructions
+            .insert(entry + 1, TACInstruction::Label { label: entry_label });
+    }
+}
+
+/// Comparison that holds exactly when `op` does not.
+fn negate_comparison(op: BinaryOperator) -> Option<BinaryOperator> {
+    Some(match op {
+        BinaryOperator::Equals => BinaryOperator::NotEquals,
+        BinaryOperator::NotEquals => BinaryOperator::Equals,
+        BinaryOperator::LessThan => BinaryOperator::GreaterThanOrEquals,
+        BinaryOperator::GreaterThanOrEquals => BinaryOperator::LessThan,
+        BinaryOperator::GreaterThan => BinaryOperator::LessThanOrEquals,
+        BinaryOperator::LessThanOrEquals => BinaryOperator::GreaterThan,
+        _ => return None,
+    })
+}
+
+/// The single jump replacing `comparison` followed by `jump`, if `jump` is the only
+/// reader of the comparison's result.
+fn fused_branch(
+    comparison: &TACInstruction,
+    jump: &TACInstruction,
+    reads: &HashMap<i32, usize>,
+    volatile_slots: &HashSet<i32>,
+) -> Option<TACInstruction> {
+    let TACInstruction::BinaryOpInstruction {
+        op, left, right, ..
+    } = comparison
+    else {
+        return None;
+    };
+    let negated = negate_comparison(*op)?;
+    let (label, operand, op) = match jump {
+        TACInstruction::JumpIfZero { label, operand } => (label, operand, negated),
+        TACInstruction::JumpIfNotZero { label, operand } => (label, operand, *op),
+        _ => return None,
+    };
+    let slot = defined_slot(comparison)?;
+    if operand_slot(operand) != Some(slot)
+        || reads.get(&slot) != Some(&1)
+        || volatile_slots.contains(&slot)
+    {
+        return None;
+    }
+    Some(TACInstruction::JumpIfComparison {
+        op,
+        left: Rc::clone(left),
+        right: Rc::clone(right),
+        label: Rc::clone(label),
+    })
+}
+
+/*
+A comparison whose 0/1 result is only tested by the conditional jump right after
+it becomes one compare-and-jump, so conditions no longer go through setcc and a
+second test.
+ */
+pub(crate) fn fuse_compare_and_branch(function_body: &mut FunctionBody) {
+    let mut reads: HashMap<i32, usize> = HashMap::new();
+    for instruction in &function_body.instructions {
+        for slot in used_slots(instruction) {
+            *reads.entry(slot).or_default() += 1;
+        }
+    }
+
+    let mut i = 0;
+    while i + 1 < function_body.instructions.len() {
+        let window = &function_body.instructions[i..i + 2];
+        if let Some(fused) = fused_branch(
+            &window[0],
+            &window[1],
+            &reads,
+            &function_body.volatile_slots,
+        ) {
+            function_body.instructions.splice(i..i + 2, [fused]);
+        }
+        i += 1;
+    }
+}
+
+/// Whether two operands name the same constant or the same stack slot.
+fn same_operand(a: &Rc<Operand>, b: &Rc<Operand>) -> bool {
+    match (a.as_ref(), b.as_ref()) {
+        (Operand::Immediate(x), Operand::Immediate(y)) => x == y,
+        _ => {
+            operand_slot(a).is_some() && operand_slot(a) == operand_slot(b) && a.size() == b.size()
+        }
+    }
+}
+
+/// The `DivRem` replacing `first`, a division or modulo, and `second`, the other one
+/// of the pair on the same operands, if the stores in `between` change nothing
+/// `second` reads or writes.
+fn paired_division(
+    first: &TACInstruction,
+    between: &[TACInstruction],
+    second: &TACInstruction,
+    volatile_slots: &HashSet<i32>,
+) -> Option<TACInstruction> {
+    let (
+        TACInstruction::BinaryOpInstruction {
+            dest: first_dest,
+            op: first_op,
+            left,
+            right,
+        },
+        TACInstruction::BinaryOpInstruction {
+            dest: second_dest,
+            op: second_op,
+            left: second_left,
+            right: second_right,
+        },
+    ) = (first, second)
+    else {
+        return None;
+    };
+    let (quotient, remainder) = match (first_op, second_op) {
+        (BinaryOperator::Divide, BinaryOperator::Modulo) => (first_dest, second_dest),
+        (BinaryOperator::Modulo, BinaryOperator::Divide) => (second_dest, first_dest),
+        _ => return None,
+    };
+    if !same_operand(left, second_left) || !same_operand(right, second_right) {
+        return None;
+    }
+    let operand_slots: Vec<i32> = [left, right].into_iter().filter_map(operand_slot).collect();
+    if operand_slots
+        .iter()
+        .any(|slot| volatile_slots.contains(slot))
+    {
+        return None;
+    }
+    let first_slot = defined_slot(first)?;
+    let second_slot = defined_slot(second)?;
+    if operand_slots.contains(&first_slot) || first_slot == second_slot {
+        return None;
+    }
+    // the second result is now written early, so nothing in between may touch its slot
+    let undisturbed = between.iter().all(|instruction| {
+        defined_slot(instruction)
+            .is_none_or(|slot| slot != second_slot && !operand_slots.contains(&slot))
+            && !used_slots(instruction).contains(&second_slot)
+    });
+    undisturbed.then(|| TACInstruction::DivRem {
+        quotient: Rc::clone(quotient),
+        remainder: Rc::clone(remainder),
+        left: Rc::clone(left),
+        right: Rc::clone(right),
+    })
+}
+
+/*
+A division and a modulo of the same operands, separated only by plain stores,
+become one `DivRem`, so a single div/idiv yields both the quotient and the
+remainder.
+ */
+pub(crate) fn combine_division(function_body: &mut FunctionBody) {
+    let mut i = 0;
+    while i < function_body.instructions.len() {
+        let instructions = &function_body.instructions;
+        let j = (i + 1..instructions.len())
+            .find(|&j| {
+                !matches!(
+                    instructions[j],
+                    TACInstruction::StoreValueInstruction { .. } | TACInstruction::Line(_)
+                )
+            })
+            .unwrap_or(instructions.len());
+        if j < instructions.len()
+            && let Some(combined) = paired_division(
+                &instructions[i],
+                &instructions[i + 1..j],
+                &instructions[j],
+                &function_body.volatile_slots,
+            )
+        {
+            function_body.instructions.remove(j);
+            function_body.instructions[i] = combined;
+        }
+        i += 1;
+    }
+}
+
+/// Index of the instruction in the straight-line start of the loop body between
+/// `start` and `end` that computes the same value on every iteration, if any.
+fn loop_invariant(
+    function_body: &FunctionBody,
+    start: usize,
+    end: usize,
+    end_label: &str,
+) -> Option<usize> {
+    let instructions = &function_body.instructions;
+    let continue_label = end_label.replace("_end.loop", "_continue.loop");
+    let region = start + 1..end;
+    let mut definitions: HashMap<i32, usize> = HashMap::new();
+    for instruction in &instructions[region.clone()] {
+        if let Some(slot) = defined_slot(instruction) {
+            *definitions.entry(slot).or_default() += 1;
+        }
+    }
+    // a slot read after the loop must keep its value when the loop body never runs
+    let used_outside: HashSet<i32> = instructions[..start]
+        .iter()
+        .chain(&instructions[end..])
+        .flat_map(used_slots)
+        .collect();
+    let invariant = |operand: &Rc<Operand>| match operand.as_ref() {
+        Operand::Immediate(_) => true,
+        _ => operand_slot(operand).is_some_and(|slot| {
+            !definitions.contains_key(&slot) && !function_body.volatile_slots.contains(&slot)
+        }),
+    };
+
+    let mut used_before: HashSet<i32> = HashSet::new();
+    for i in region {
+        match &instructions[i] {
+            TACInstruction::BinaryOpInstruction {
+                dest,
+                op,
+                left,
+                right,
+            } if defined_slot(&instructions[i]).is_some_and(|slot| {
+                definitions.get(&slot) == Some(&1)
+                    && !used_before.contains(&slot)
+                    && !used_outside.contains(&slot)
+                    && !function_body.volatile_slots.contains(&slot)
+            }) && invariant(left)
+                && invariant(right)
+                && !can_trap(*op, dest, function_body) =>
+            {
+                return Some(i);
+            }
+            TACInstruction::Jump { label }
+            | TACInstruction::JumpIfZero { label, .. }
+            | TACInstruction::JumpIfNotZero { label, .. }
+            | TACInstruction::JumpIfComparison { label, .. }
+                if label.as_str() == end_label => {}
+            // a while loop's continue target, reached only from inside the loop
+            TACInstruction::Label { label } if label.as_str() == continue_label => {}
+            TACInstruction::Label { .. }
+            | TACInstruction::Jump { .. }
+            | TACInstruction::JumpTable { .. }
+            | TACInstruction::JumpIfZero { .. }
+            | TACInstruction::JumpIfNotZero { .. }
+            | TACInstruction::JumpIfComparison { .. }
+            | TACInstruction::ReturnInstruction { .. } => return None,
+            _ => {}
+        }
+        used_before.extend(used_slots(&instructions[i]));
+    }
+    None
+}
+
+/// Whether computing `op` could abort the program, which must not happen early.
+fn can_trap(op: BinaryOperator, dest: &Rc<Pseudoregister>, function_body: &FunctionBody) -> bool {
+    match op {
+        BinaryOperator::Divide | BinaryOperator::Modulo => true,
+        BinaryOperator::Addition | BinaryOperator::Subtraction | BinaryOperator::Multiply => {
+            function_body.overflow_trap.is_some() && !dest.is_unsigned()
+        }
+        _ => false,
+    }
+}
+
+fn label_position(function_body: &FunctionBody, name: &str) -> Option<usize> {
+    function_body.instructions.iter().position(|instruction| {
+        matches!(instruction, TACInstruction::Label { label } if label.as_str() == name)
+    })
+}
+
+/*
+Move arithmetic at the start of a loop body whose operands no instruction in the
+loop writes to just before the loop's start label, so it runs once. Only results
+that are used nowhere outside the loop move, and nothing that can trap.
+ */
+pub(crate) fn hoist_loop_invariants(function_body: &mut FunctionBody) {
+    let start_labels: Vec<Rc<String>> = function_body
+        .instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            TACInstruction::Label { label } if label.ends_with("_start.loop") => {
+                Some(Rc::clone(label))
+            }
+            _ => None,
+        })
+        .collect();
+    // inner loops first, so what leaves them can move on out of the enclosing loop
+    for start_label in start_labels.iter().rev() {
+        let end_label = start_label.replace("_start.loop", "_end.loop");
+        while let (Some(start), Some(end)) = (
+            label_position(function_body, start_label),
+            label_position(function_body, &end_label),
+        ) && let Some(i) = loop_invariant(function_body, start, end, &end_label)
+        {
+            let hoisted = function_body.instructions.remove(i);
+            function_body.instructions.insert(start, hoisted);
+        }
+    }
+}
+
+/*
+A function that calls nothing and keeps every value in registers never
+addresses the stack, so it can run without a frame of its own.
+ */
+pub(crate) fn omit_unused_frame(function_body: &mut FunctionBody) {
+    let touches_stack = |instruction: &TACInstruction| match instruction {
+        TACInstruction::FunctionCall(_)
+        | TACInstruction::PushArgument(_)
+        | TACInstruction::AdjustStack(_) => true,
+        TACInstruction::DivRem {
+            quotient,
+            remainder,
+            ..
+        } if [quotient, remainder]
+            .iter()
+            .any(|dest| matches!(dest.as_ref(), Pseudoregister::Pseudoregister(..))) =>
+        {
+            true
+        }
+        _ => defined_slot(instruction).is_some() || !used_slots(instruction).is_empty(),
+    };
+    function_body.frameless = !function_body.instructions.iter().any(touches_stack);
+}
+
+/*
+With more than one return, each tearing down the frame itself, the returns
+instead jump to one copy of the epilogue after the body.
+ */
+pub(crate) fn share_epilogue(function_body: &mut FunctionBody, name: &str) {
+    let returns = function_body
+        .instructions
+        .iter()
+        .filter(|instruction| matches!(instruction, TACInstruction::ReturnInstruction { .. }))
+        .count();
+    if !function_body.frameless && returns > 1 {
+        function_body.epilogue = Some(Rc::from(format!(".{}_epilogue", name)));
+    }
+}
@@ -3,19 +3,21 @@ use crate::ast::Expression::{
     Assignment, Condition, Constant, FunctionCall, Postfix, Prefix, Unary, Variable,
 };
 use crate::ast::ForInit::{InitDecl, InitExp};
-use crate::ast::Statement::{Compound, For, If, Null, Return, While};
+use crate::ast::Statement::{Case, Compound, For, If, Null, Return, Switch, While};
 use crate::ast::{
     ASTNode, Block, BlockItem, Declaration, Expression, ForInit, FuncType, FunctionDeclaration,
-    Program, Statement, VariableDeclaration, extract_base_variable, is_lvalue_node,
+    Program, Statement, StaticAssert, VariableDeclaration, is_lvalue_node,
 };
-use crate::common::Position;
-use crate::errors::CompilerError;
+use crate::common::{Const, Position};
 use crate::errors::CompilerError::{SemanticError, SyntaxError};
+use crate::errors::{CompilerError, Diagnostic};
 use crate::lexer::BinaryOperator::Assign;
 use crate::lexer::Symbol::{Ambiguous, Binary};
 use crate::lexer::{
-    BinaryOperator, Keyword, StorageClass, Symbol, Token, Type, UnaryOperator, UnaryOrBinaryOp,
+    BinaryOperator, Keyword, Qualifier, StorageClass, Symbol, Token, Type, UnaryOperator,
+    UnaryOrBinaryOp,
 };
+use std::cell::Cell;
 use std::collections::{HashSet, VecDeque};
 use std::rc::Rc;
 
@@ -50,24 +52,38 @@ macro_rules! expect_token {
                 Ok(())
             } else {
                 let line = Rc::clone(&$parser.line_number);
-                Err(CompilerError::SyntaxError(format!(
-                    "Expected token matching pattern but got {:?} at {:?}",
-                    token, line
+                Err(CompilerError::SyntaxError(Diagnostic::at(
+                    format!(
+                        "Expected token matching pattern but got {:?} at {:?}",
+                        token, line
+                    ),
+                    &line,
                 )))
             }
         } else {
             let line = Rc::clone(&$parser.line_number);
-            Err(CompilerError::SyntaxError(format!(
-                "Unexpected end of tokens at {:?}",
-                line
+            Err(CompilerError::SyntaxError(Diagnostic::at(
+                format!("Unexpected end of tokens at {:?}", line),
+                &line,
             )))
         }
     }};
 }
 
+/// Deepest nesting of statements and expressions accepted, enough for the 127 nested
+/// blocks or 63 nested parentheses C guarantees. Anything deeper is rejected here rather
+/// than overflowing a default 8 MiB stack in the recursive passes that follow.
+const MAX_NESTING: usize = 160;
+
 pub(crate) struct Parser {
     loop_label_counter: i32,
+    depth: usize,
+    nodes: Cell<usize>,
+    /// Suspicious but valid code found while parsing
+    pub(crate) warnings: Vec<Diagnostic>,
     tokens: VecDeque<Token>,
+    /// Source line of every token, indexed from the first token
+    token_lines: Vec<i32>,
     line_number: Rc<Position>,
 }
 
@@ -95,11 +111,38 @@ fn get_precedence(op: Symbol) -> i32 {
     }
 }
 
+fn is_relational(op: BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::LessThan
+            | BinaryOperator::LessThanOrEquals
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanOrEquals
+    )
+}
+
 impl Parser {
     pub(crate) fn new(tokens: VecDeque<Token>) -> Self {
+        let mut line = 0;
+        let mut token_lines = vec![];
+        let tokens = tokens
+            .into_iter()
+            .filter(|token| {
+                if let Token::Line(start) = token {
+                    line = *start;
+                    return false;
+                }
+                token_lines.push(line);
+                true
+            })
+            .collect();
         Parser {
             loop_label_counter: 0,
+            depth: 0,
+            nodes: Cell::new(0),
+            warnings: vec![],
             tokens,
+            token_lines,
             line_number: Rc::from((0, "".to_string())),
         }
     }
@@ -119,33 +162,34 @@ impl Parser {
         loop {
             // Parse type specifiers
             let mut specifiers = vec![];
-            while let Token::Keyword(spec @ Keyword::Type(..)) = self.peek_token() {
+            while let Token::Keyword(spec @ (Keyword::Type(..) | Keyword::Qualifier(..))) =
+                self.peek_token()
+            {
                 self.tokens.pop_front();
                 specifiers.push(spec);
             }
 
             if specifiers.is_empty() {
-                return Err(SyntaxError(format!(
-                    "Expected type specifier but got {:?} at {:?}",
-                    self.peek_token(),
-                    self.line_number
+                return Err(SyntaxError(Diagnostic::at(
+                    format!(
+                        "Expected type specifier but got {:?} at {:?}",
+                        self.peek_token(),
+                        self.line_number
+                    ),
+                    &self.line_number,
                 )));
             }
 
-            let (type_, _) = self.parse_type_and_storage_class(specifiers)?;
+            let (type_, ..) = self.parse_type_and_storage_class(specifiers)?;
 
-            // Parse parameter name
+            // Parse parameter name, which prototypes may leave out
             if let Token::Name(name) = self.peek_token() {
                 self.tokens.pop_front();
                 params.push(name);
-                types.push(type_);
             } else {
-                return Err(SyntaxError(format!(
-                    "Expected parameter name but got {:?} at {:?}",
-                    self.peek_token(),
-                    self.line_number
-                )));
+                params.push(String::new());
             }
+            types.push(type_);
 
             // Check for end of parameter list or more parameters
             if match_and_consume!(self, Token::Symbol(Symbol::CloseParenthesis)) {
@@ -158,24 +202,33 @@ impl Parser {
 
     fn parse_type_specifier(&self, types: Vec<Type>) -> Result<Type, CompilerError> {
         if types.is_empty() {
-            return Err(SyntaxError(format!(
-                "Invalid type specifier {:?} at {:?}",
-                types, self.line_number
+            return Err(SyntaxError(Diagnostic::at(
+                format!(
+                    "Invalid type specifier {:?} at {:?}",
+                    types, self.line_number
+                ),
+                &self.line_number,
             )));
         }
         let mut seen = HashSet::new();
         for item in types.iter() {
             if !seen.insert(*item) {
-                return Err(SyntaxError(format!(
-                    "Invalid type specifier {:?} at {:?}",
-                    types, self.line_number
+                return Err(SyntaxError(Diagnostic::at(
+                    format!(
+                        "Invalid type specifier {:?} at {:?}",
+                        types, self.line_number
+                    ),
+                    &self.line_number,
                 )));
             }
         }
         if seen.contains(&Type::Signed) && seen.contains(&Type::Unsigned) {
-            return Err(SyntaxError(format!(
-                "Invalid type specifier {:?} at {:?}",
-                types, self.line_number
+            return Err(SyntaxError(Diagnostic::at(
+                format!(
+                    "Invalid type specifier {:?} at {:?}",
+                    types, self.line_number
+                ),
+                &self.line_number,
             )));
         }
         if seen.contains(&Type::Unsigned) && seen.contains(&Type::Long) {
@@ -214,22 +267,30 @@ impl Parser {
     fn parse_type_and_storage_class(
         &mut self,
         specifier_list: Vec<Keyword>,
-    ) -> Result<(Type, Option<StorageClass>), CompilerError> {
+    ) -> Result<(Type, Option<StorageClass>, bool, bool), CompilerError> {
         let mut types = vec![];
         let mut storage_classes = vec![];
+        let mut volatile = false;
+        let mut constant = false;
         for specifier in specifier_list.iter() {
-            if let Keyword::Type(type_) = specifier {
-                types.push(*type_);
-            } else if let Keyword::StorageClass(class) = specifier {
-                storage_classes.push(class);
+            match specifier {
+                Keyword::Type(type_) => types.push(*type_),
+                Keyword::StorageClass(class) => storage_classes.push(class),
+                Keyword::Qualifier(Qualifier::Volatile) => volatile = true,
+                Keyword::Qualifier(Qualifier::Const) => constant = true,
+                // restrict only promises no aliasing, which nothing here relies on
+                _ => {}
             }
         }
 
         let type_ = self.parse_type_specifier(types)?;
         if storage_classes.len() > 1 {
-            return Err(SyntaxError(format!(
-                "Invalid storage class {:?} at {:?}",
-                storage_classes, self.line_number
+            return Err(SyntaxError(Diagnostic::at(
+                format!(
+                    "Invalid storage class {:?} at {:?}",
+                    storage_classes, self.line_number
+                ),
+                &self.line_number,
             )));
         };
 
@@ -238,55 +299,140 @@ impl Parser {
         } else {
             None
         };
-        Ok((type_, storage_class))
+        Ok((type_, storage_class, volatile, constant))
+    }
+
+    /*
+    Skip any number of GCC __attribute__((...)) clauses, returning the attribute names
+     */
+    fn parse_attributes(&mut self) -> Result<Vec<String>, CompilerError> {
+        let mut attributes = vec![];
+        while match_and_consume!(self, Token::Keyword(Keyword::Attribute)) {
+            expect_token!(self, Token::Symbol(Symbol::OpenParenthesis))?;
+            expect_token!(self, Token::Symbol(Symbol::OpenParenthesis))?;
+            let mut depth = 0;
+            loop {
+                match self.consume_and_pop() {
+                    Token::Symbol(Symbol::OpenParenthesis) => depth += 1,
+                    Token::Symbol(Symbol::CloseParenthesis) if depth == 0 => break,
+                    Token::Symbol(Symbol::CloseParenthesis) => depth -= 1,
+                    Token::Name(name) if depth == 0 => attributes.push(name),
+                    Token::EOF => {
+                        return Err(SyntaxError(Diagnostic::at(
+                            format!("Unterminated attribute at {:?}", self.line_number),
+                            &self.line_number,
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+            expect_token!(self, Token::Symbol(Symbol::CloseParenthesis))?;
+        }
+        Ok(attributes)
     }
 
-    fn parse_top_level(&mut self) -> Result<ASTNode<Declaration>, CompilerError> {
+    /// Parse a function, or every variable of a `,`-separated declaration list
+    fn parse_top_level(&mut self) -> Result<Vec<ASTNode<Declaration>>, CompilerError> {
+        // a stray semicolon between declarations declares nothing, which GCC and Clang accept
+        if match_and_consume!(self, Token::Symbol(Symbol::Semicolon)) {
+            return Ok(vec![]);
+        }
+        if match_and_consume!(self, Token::Keyword(Keyword::StaticAssert)) {
+            return Ok(vec![self.parse_static_assert()?]);
+        }
+        let mut attributes = self.parse_attributes()?;
         let mut specifiers = vec![];
-        while let Token::Keyword(spec @ (Keyword::Type(..) | Keyword::StorageClass(..))) =
-            self.peek_token()
+        let mut inline = false;
+        while let Token::Keyword(
+            spec @ (Keyword::Type(..)
+            | Keyword::StorageClass(..)
+            | Keyword::Qualifier(..)
+            | Keyword::Inline),
+        ) = self.peek_token()
         {
             self.tokens.pop_front();
-            specifiers.push(spec);
-        }
-        let (type_, storage_class) = self.parse_type_and_storage_class(specifiers)?;
-        let function_name =
-            if let Some(name) = match_and_consume!(self, Token::Name(name) => Some(name)) {
-                name
+            if spec == Keyword::Inline {
+                inline = true;
             } else {
-                return Err(SyntaxError(format!(
-                    "Expected identifier but got {:?} at {:?}",
-                    self.peek_token(),
-                    self.line_number
-                )));
-            };
-        self.line_number = Rc::from((0, function_name.clone()));
+                specifiers.push(spec);
+            }
+        }
+        let (type_, storage_class, volatile, constant) =
+            self.parse_type_and_storage_class(specifiers)?;
+        if matches!(
+            storage_class,
+            Some(StorageClass::Auto | StorageClass::Register)
+        ) {
+            return Err(SemanticError(Diagnostic::at(
+                format!(
+                    "Invalid storage class {:?} at file scope at {:?}",
+                    storage_class, self.line_number
+                ),
+                &self.line_number,
+            )));
+        }
+        let function_name = self.parse_declarator_name()?;
+        self.line_number = Rc::from((self.token_line(), function_name.clone()));
         let mut block_items: Vec<ASTNode<BlockItem>> = Vec::new();
         let next = self.peek_token();
         match next {
             Token::Symbol(Symbol::OpenParenthesis) => {} // function
-            Token::Symbol(Binary(Assign)) | Token::Symbol(Symbol::Semicolon) => {
+            Token::Symbol(Binary(Assign))
+            | Token::Symbol(Symbol::Semicolon)
+            | Token::Keyword(Keyword::Attribute) => {
                 // top level variable
-                let declaration =
-                    self.parse_declaration((type_, storage_class), Some(function_name))?;
-                self.tokens.pop_front(); // consume semicolon
-                return Ok(self.make_node(Declaration::VariableDeclaration(declaration.kind)));
+                if inline {
+                    return Err(SemanticError(Diagnostic::at(
+                        format!(
+                            "Variable {} declared inline at {:?}",
+                            function_name, self.line_number
+                        ),
+                        &self.line_number,
+                    )));
+                }
+                let specifiers = (type_, storage_class, volatile, constant);
+                let declaration = self.parse_declaration(specifiers, Some(function_name))?;
+                let mut declarations =
+                    vec![self.make_node(Declaration::VariableDeclaration(declaration.kind))];
+                while match_and_consume!(self, Token::Symbol(Symbol::Comma)) {
+                    let declaration = self.parse_declaration(specifiers, None)?;
+                    if let Token::Symbol(Symbol::OpenParenthesis) = self.peek_token() {
+                        return Err(SyntaxError(Diagnostic::at(
+                            format!(
+                                "Function {} declared in a variable declaration list at {:?}",
+                                declaration.kind.name, self.line_number
+                            ),
+                            &self.line_number,
+                        )));
+                    }
+                    declarations
+                        .push(self.make_node(Declaration::VariableDeclaration(declaration.kind)));
+                }
+                expect_token!(self, Token::Symbol(Symbol::Semicolon))?;
+                return Ok(declarations);
             }
             _ => {
-                return Err(SyntaxError(format!(
-                    "Unexpected token {:?} at {:?}",
-                    self.peek_token(),
-                    self.line_number
+                return Err(SyntaxError(Diagnostic::at(
+                    format!(
+                        "Unexpected token {:?} at {:?}",
+                        self.peek_token(),
+                        self.line_number
+                    ),
+                    &self.line_number,
                 )));
             }
         }
 
         let (params, types) = self.parse_params()?;
+        attributes.extend(self.parse_attributes()?);
+        let noreturn = attributes
+            .iter()
+            .any(|attribute| attribute == "noreturn" || attribute == "__noreturn__");
 
         // function prototype
         if match_and_consume!(self, Token::Symbol(Symbol::Semicolon)) {
-            return Ok(
-                self.make_node(Declaration::FunctionDeclaration(FunctionDeclaration {
+            return Ok(vec![self.make_node(Declaration::FunctionDeclaration(
+                FunctionDeclaration {
                     name: Rc::from(function_name),
                     params,
                     body: None,
@@ -295,29 +441,40 @@ impl Parser {
                         params: types,
                         ret: type_,
                     }),
-                })),
-            );
+                    noreturn,
+                    inline,
+                },
+            ))]);
         }
 
         // full definition
         expect_token!(self, Token::Symbol(Symbol::OpenBrace))?;
+        if params.iter().any(|param| param.is_empty()) {
+            return Err(SemanticError(Diagnostic::at(
+                format!(
+                    "Unnamed parameter in definition of {} at {:?}",
+                    function_name, self.line_number
+                ),
+                &self.line_number,
+            )));
+        }
 
         let mut next_token = self.peek_token();
         loop {
             match next_token {
                 Token::Symbol(Symbol::CloseBrace) => break,
-                Token::EOF => return Err(SyntaxError("Unexpected EOF".to_string())),
+                Token::EOF => return Err(SyntaxError("Unexpected EOF".to_string().into())),
                 _ => {
-                    let item = self.parse_block_item()?;
-                    block_items.push(item);
+                    let items = self.parse_block_item()?;
+                    block_items.extend(items);
                 }
             }
             next_token = self.peek_token();
         }
         let function_body = self.make_node::<Block>(block_items);
         expect_token!(self, Token::Symbol(Symbol::CloseBrace))?;
-        Ok(
-            self.make_node(Declaration::FunctionDeclaration(FunctionDeclaration {
+        Ok(vec![self.make_node(Declaration::FunctionDeclaration(
+            FunctionDeclaration {
                 name: Rc::from(function_name),
                 params,
                 body: Some(function_body),
@@ -326,36 +483,50 @@ impl Parser {
                     params: types,
                     ret: type_,
                 }),
-            })),
-        )
+                noreturn,
+                inline,
+            },
+        ))])
+    }
+
+    /// The identifier of a declarator, inside any number of redundant parentheses: `(x)`
+    fn parse_declarator_name(&mut self) -> Result<String, CompilerError> {
+        if match_and_consume!(self, Token::Symbol(Symbol::OpenParenthesis)) {
+            let name = self.parse_declarator_name()?;
+            expect_token!(self, Token::Symbol(Symbol::CloseParenthesis))?;
+            return Ok(name);
+        }
+        match self.consume_and_pop() {
+            Token::Name(name) => Ok(name),
+            current => Err(SyntaxError(Diagnostic::at(
+                format!(
+                    "Expected identifier but got {:?} at {:?}",
+                    current, self.line_number
+                ),
+                &self.line_number,
+            ))),
+        }
     }
 
     fn parse_declaration(
         &mut self,
-        specifiers: (Type, Option<StorageClass>),
+        specifiers: (Type, Option<StorageClass>, bool, bool),
         name: Option<String>,
     ) -> Result<ASTNode<VariableDeclaration>, CompilerError> {
-        let identifier = if let Some(name) = name {
-            name
-        } else {
-            let current = self.consume_and_pop();
-            match current {
-                Token::Name(name) => name,
-                _ => {
-                    return Err(SyntaxError(format!(
-                        "Expected identifier but got {:?} at {:?}",
-                        current, self.line_number
-                    )));
-                }
-            }
+        let identifier = match name {
+            Some(name) => name,
+            None => self.parse_declarator_name()?,
         };
+        self.parse_attributes()?;
         if match_and_consume!(self, Token::Symbol(Binary(Assign))) {
-            let expression = self.parse_binary_op(0)?;
+            let expression = self.parse_assignment()?;
             Ok(self.make_node(VariableDeclaration {
                 name: Rc::from(identifier),
                 init: Some(expression),
                 storage_class: specifiers.1,
                 var_type: specifiers.0,
+                volatile: specifiers.2,
+                constant: specifiers.3,
             }))
         } else {
             Ok(self.make_node(VariableDeclaration {
@@ -363,29 +534,65 @@ impl Parser {
                 init: None,
                 storage_class: specifiers.1,
                 var_type: specifiers.0,
+                volatile: specifiers.2,
+                constant: specifiers.3,
             }))
         }
     }
 
+    /// The `(condition, "message");` after `_Static_assert`
+    fn parse_static_assert(&mut self) -> Result<ASTNode<Declaration>, CompilerError> {
+        expect_token!(self, Token::Symbol(Symbol::OpenParenthesis))?;
+        let condition = self.parse_binary_op(get_precedence(Binary(BinaryOperator::Ternary)))?;
+        expect_token!(self, Token::Symbol(Symbol::Comma))?;
+        let Some(message) = match_and_consume!(self, Token::StringLiteral(s) => Some(s)) else {
+            return Err(SyntaxError(Diagnostic::at(
+                format!(
+                    "Expected a string literal message but got {:?} at {:?}",
+                    self.peek_token(),
+                    self.line_number
+                ),
+                &self.line_number,
+            )));
+        };
+        expect_token!(self, Token::Symbol(Symbol::CloseParenthesis))?;
+        self.end_line()?;
+        Ok(self.make_node(Declaration::StaticAssert(StaticAssert {
+            condition,
+            message,
+        })))
+    }
+
+    /*
+    Every assignment and increment target goes through here
+     */
+    fn check_lvalue(&self, expression: &ASTNode<Expression>) -> Result<(), CompilerError> {
+        if is_lvalue_node(&expression.kind) {
+            Ok(())
+        } else {
+            Err(SemanticError(Diagnostic::at(
+                format!(
+                    "{:?} is not assignable at {:?}",
+                    expression.kind, self.line_number
+                ),
+                &self.line_number,
+            )))
+        }
+    }
+
     fn parse_increment_decrement(
         &mut self,
         expression: ASTNode<Expression>,
         symbol: UnaryOperator,
         is_prefix: bool,
     ) -> Result<ASTNode<Expression>, CompilerError> {
-        if is_lvalue_node(&expression.kind) {
-            let which = if is_prefix {
-                Prefix(symbol, Box::from(expression))
-            } else {
-                Postfix(symbol, Box::from(expression))
-            };
-            Ok(self.make_node(which))
+        self.check_lvalue(&expression)?;
+        let which = if is_prefix {
+            Prefix(symbol, Box::from(expression))
         } else {
-            Err(SemanticError(format!(
-                "Expected lvalue node at {:?} but got {:?}",
-                expression, self.line_number
-            )))
-        }
+            Postfix(symbol, Box::from(expression))
+        };
+        Ok(self.make_node(which))
     }
 
     fn parse_arguments(&mut self) -> Result<Box<Vec<ASTNode<Expression>>>, CompilerError> {
@@ -398,7 +605,7 @@ impl Parser {
                 return Ok(Box::new(params));
             }
             _ => {
-                params.push(self.parse_binary_op(0)?);
+                params.push(self.parse_assignment()?);
             }
         }
 
@@ -407,7 +614,7 @@ impl Parser {
                 return Ok(Box::new(params));
             }
             expect_token!(self, Token::Symbol(Symbol::Comma))?;
-            params.push(self.parse_binary_op(0)?);
+            params.push(self.parse_assignment()?);
         }
     }
 
@@ -430,6 +637,14 @@ impl Parser {
                     }
                     expect_token!(self, Token::Symbol(Symbol::CloseParenthesis))?;
                     let type_ = self.parse_type_specifier(types)?;
+                    if match_and_consume!(self, Token::Symbol(Symbol::OpenBrace)) {
+                        let init = self.parse_assignment()?;
+                        match_and_consume!(self, Token::Symbol(Symbol::Comma));
+                        expect_token!(self, Token::Symbol(Symbol::CloseBrace))?;
+                        return Ok(
+                            self.make_node(Expression::CompoundLiteral(type_, Box::from(init)))
+                        );
+                    }
                     let exp = self.parse_unary_or_primary()?;
                     Ok(self.make_node(Expression::Cast(type_, Box::from(exp))))
                 } else {
@@ -452,14 +667,18 @@ impl Parser {
                     Ok(self.make_node(Variable(Rc::from(identifier))))
                 }
             }
-            _ => Err(SyntaxError(format!(
-                "Unexpected token {:?} at {:?}",
-                token, self.line_number
+            _ => Err(SyntaxError(Diagnostic::at(
+                format!("Unexpected token {:?} at {:?}", token, self.line_number),
+                &self.line_number,
             ))),
         }
     }
 
     fn parse_unary_or_primary(&mut self) -> Result<ASTNode<Expression>, CompilerError> {
+        self.nested(Self::parse_unary_or_primary_inner)
+    }
+
+    fn parse_unary_or_primary_inner(&mut self) -> Result<ASTNode<Expression>, CompilerError> {
         if let Some(token) = match_and_consume!(self, op @ Token::Symbol(Symbol::Unary(_) | Ambiguous(_)) => Some(op))
         {
             match token {
@@ -489,6 +708,30 @@ impl Parser {
             }
         }
 
+        if match_and_consume!(self, Token::Keyword(Keyword::SizeOf)) {
+            if self.peek_token() == Token::Symbol(Symbol::OpenParenthesis)
+                && matches!(self.tokens.get(1), Some(Token::Keyword(Keyword::Type(_))))
+            {
+                self.tokens.pop_front();
+                let mut types = vec![];
+                while let Some(t) =
+                    match_and_consume!(self, Token::Keyword(Keyword::Type(t)) => Some(t))
+                {
+                    types.push(t);
+                }
+                expect_token!(self, Token::Symbol(Symbol::CloseParenthesis))?;
+                let size = self.parse_type_specifier(types)?.size();
+                return Ok(self.make_node(Constant(Const::ConstULong(size as u64))));
+            }
+            let expression = self.parse_unary_or_primary()?;
+            return Ok(self.make_node(Expression::SizeOf(Box::from(expression))));
+        }
+
+        if match_and_consume!(self, Token::Symbol(Binary(BinaryOperator::BitwiseAnd))) {
+            let expression = self.parse_unary_or_primary()?;
+            return Ok(self.make_node(Unary(UnaryOperator::AddressOf, Box::from(expression))));
+        }
+
         let primary = self.parse_primary(self.peek_token())?;
         if let Some(op) = match_and_consume!(self,Token::Symbol(Symbol::Unary(
                 op @ (UnaryOperator::Increment | UnaryOperator::Decrement),
@@ -529,17 +772,32 @@ impl Parser {
         next_token = peek(tokens)
     return left
     */
+    /// An expression that stops before any comma, as in initializers and arguments where
+    /// the comma separates declarators or arguments instead.
+    fn parse_assignment(&mut self) -> Result<ASTNode<Expression>, CompilerError> {
+        self.parse_binary_op(get_precedence(Binary(Assign)))
+    }
+
     fn parse_binary_op(
         &mut self,
         min_precedence: i32,
+    ) -> Result<ASTNode<Expression>, CompilerError> {
+        self.nested(|parser| parser.parse_binary_op_inner(min_precedence))
+    }
+
+    fn parse_binary_op_inner(
+        &mut self,
+        min_precedence: i32,
     ) -> Result<ASTNode<Expression>, CompilerError> {
         let mut left = self.parse_unary_or_primary()?;
+        // whether `left` was combined here rather than parsed as one (maybe parenthesized) operand
+        let mut combined = false;
         loop {
             let token = self.peek_token();
             if !matches!(token, Token::Symbol(_)) {
-                return Err(SyntaxError(format!(
-                    "Unexpected token {:?} at {:?}",
-                    token, self.line_number
+                return Err(SyntaxError(Diagnostic::at(
+                    format!("Unexpected token {:?} at {:?}", token, self.line_number),
+                    &self.line_number,
                 )));
             }
             let token = if let Token::Symbol(token @ (Binary(_) | Ambiguous(_))) = token {
@@ -553,45 +811,35 @@ impl Parser {
             self.tokens.pop_front();
             if match_and_consume!(self, Token::Symbol(Binary(Assign))) {
                 // compound assignment
-                if is_lvalue_node(&left.kind) {
-                    /*
-                    Turn x ?= rhs into x = (x ? rhs)
-                    */
-                    let right = self.parse_binary_op(get_precedence(Binary(Assign)))?;
-                    let left_variable = self.make_node(Variable(extract_base_variable(&left.kind)));
-                    let op = if let Binary(op) = token {
-                        op
-                    } else if token == Ambiguous(UnaryOrBinaryOp::Addition) {
-                        BinaryOperator::Addition
-                    } else {
-                        BinaryOperator::Subtraction
-                    };
-                    let binary = self.make_node(Expression::Binary {
-                        op,
-                        left: Box::from(left_variable),
-                        right: Box::from(right),
-                    });
-                    left = self.make_node(Assignment {
-                        left: Box::from(left),
-                        right: Box::from(binary),
-                    });
-                    continue;
+                self.check_lvalue(&left)?;
+                /*
+                Turn x ?= rhs into x = (x ? rhs), where the inner x reuses the lvalue the
+                assignment already evaluated instead of evaluating it again
+                */
+                let right = self.parse_binary_op(get_precedence(Binary(Assign)))?;
+                let left_variable = self.make_node(Expression::AssignmentTarget);
+                let op = if let Binary(op) = token {
+                    op
+                } else if token == Ambiguous(UnaryOrBinaryOp::Addition) {
+                    BinaryOperator::Addition
                 } else {
-                    return Err(SemanticError(format!(
-                        "Expected lvalue at {:?}",
-                        self.line_number
-                    )));
-                }
+                    BinaryOperator::Subtraction
+                };
+                let binary = self.make_node(Expression::Binary {
+                    op,
+                    left: Box::from(left_variable),
+                    right: Box::from(right),
+                });
+                left = self.make_node(Assignment {
+                    left: Box::from(left),
+                    right: Box::from(binary),
+                });
+                continue;
             }
             match token {
                 Binary(symbol) => match symbol {
                     Assign => {
-                        if !is_lvalue_node(&left.kind) {
-                            return Err(SemanticError(format!(
-                                "Expected lvalue node at {:?}",
-                                self.line_number
-                            )));
-                        }
+                        self.check_lvalue(&left)?;
                         let right = self.parse_binary_op(get_precedence(token))?;
                         left = self.make_node(Assignment {
                             left: Box::from(left),
@@ -599,7 +847,12 @@ impl Parser {
                         });
                     }
                     BinaryOperator::Ternary => {
-                        let middle = self.parse_condition()?;
+                        // GNU `a ?: b` yields `a` itself when it is nonzero
+                        let middle = if match_and_consume!(self, Token::Symbol(Symbol::Colon)) {
+                            self.make_node(Expression::ConditionValue)
+                        } else {
+                            self.parse_condition()?
+                        };
                         let right = self.parse_binary_op(get_precedence(token))?;
                         left = self.make_node(Condition {
                             condition: Box::from(left),
@@ -609,6 +862,19 @@ impl Parser {
                     }
 
                     _ => {
+                        if combined
+                            && is_relational(symbol)
+                            && matches!(left.kind, Expression::Binary { op, .. } if is_relational(op))
+                        {
+                            self.warnings.push(Diagnostic::at(
+                                format!(
+                                    "Chained comparison at {:?} compares a 0/1 result, \
+                                     not the middle operand",
+                                    self.line_number
+                                ),
+                                &self.line_number,
+                            ));
+                        }
                         let right = self.parse_binary_op(get_precedence(token) + 1)?;
                         left = self.make_node(Expression::Binary {
                             op: symbol,
@@ -635,23 +901,25 @@ impl Parser {
                 }
                 _ => unreachable!(),
             }
+            combined = true;
         }
         Ok(left)
     }
 
     fn parse_for_init(&mut self) -> Result<ASTNode<ForInit>, CompilerError> {
         match self.peek_token() {
-            Token::Keyword(spec @ Keyword::Type(_)) => {
+            Token::Keyword(spec @ (Keyword::Type(_) | Keyword::Qualifier(_))) => {
                 let mut specifiers = vec![spec];
                 self.tokens.pop_front();
-                while let Token::Keyword(spec @ (Keyword::Type(_) | Keyword::StorageClass(_))) =
-                    self.peek_token()
+                while let Token::Keyword(
+                    spec @ (Keyword::Type(_) | Keyword::StorageClass(_) | Keyword::Qualifier(_)),
+                ) = self.peek_token()
                 {
                     specifiers.push(spec);
                     self.tokens.pop_front();
                 }
-                let (type_, storage_class) = self.parse_type_and_storage_class(specifiers)?;
-                let variable_declaration = self.parse_declaration((type_, storage_class), None)?;
+                let specifiers = self.parse_type_and_storage_class(specifiers)?;
+                let variable_declaration = self.parse_declaration(specifiers, None)?;
                 let declaration =
                     self.make_node(Declaration::VariableDeclaration(variable_declaration.kind));
                 Ok(self.make_node(InitDecl(declaration.kind)))
@@ -665,6 +933,16 @@ impl Parser {
     }
 
     fn parse_statement(&mut self) -> Result<ASTNode<Statement>, CompilerError> {
+        // the statement is on the line it starts on, whatever lines its body spans
+        self.mark_line();
+        let line_number = Rc::clone(&self.line_number);
+        let mut statement = self.nested(Self::parse_statement_inner)?;
+        self.line_number = line_number;
+        statement.line_number = Rc::clone(&self.line_number);
+        Ok(statement)
+    }
+
+    fn parse_statement_inner(&mut self) -> Result<ASTNode<Statement>, CompilerError> {
         if let Some(keyword) = match_and_consume!(self, Token::Keyword(keyword) => Some(keyword)) {
             match keyword {
                 Keyword::Return => {
@@ -693,9 +971,9 @@ impl Parser {
                         }))
                     }
                 }
-                Keyword::Else => Err(SyntaxError(format!(
-                    "Unexpected else at {:?}",
-                    self.line_number
+                Keyword::Else => Err(SyntaxError(Diagnostic::at(
+                    format!("Unexpected else at {:?}", self.line_number),
+                    &self.line_number,
                 ))),
                 Keyword::While => {
                     let label = self.loop_label_counter.to_string();
@@ -716,10 +994,7 @@ impl Parser {
                     Ok(node)
                 }
                 Keyword::Continue => {
-                    let node = self.make_node(Statement::Continue {
-                        label: Rc::from("".to_string()),
-                        is_for: false,
-                    });
+                    let node = self.make_node(Statement::Continue(Rc::from("".to_string())));
                     Ok(node)
                 }
                 Keyword::Do => {
@@ -766,9 +1041,37 @@ impl Parser {
                         label: Rc::from(label),
                     }))
                 }
-                _ => Err(SyntaxError(format!(
-                    "Unexpected keyword {:?} at {:?}",
-                    keyword, self.line_number
+                Keyword::Switch => {
+                    let label = self.loop_label_counter.to_string();
+                    self.loop_label_counter += 1;
+                    expect_token!(self, Token::Symbol(Symbol::OpenParenthesis))?;
+                    let condition = self.parse_binary_op(0)?;
+                    expect_token!(self, Token::Symbol(Symbol::CloseParenthesis))?;
+                    let body = Box::from(self.parse_statement()?);
+                    Ok(self.make_node(Switch {
+                        condition,
+                        body,
+                        label: Rc::from(label),
+                        cases: vec![],
+                    }))
+                }
+                Keyword::Case | Keyword::Default => {
+                    let value = if keyword == Keyword::Case {
+                        Some(self.parse_binary_op(0)?)
+                    } else {
+                        None
+                    };
+                    expect_token!(self, Token::Symbol(Symbol::Colon))?;
+                    let body = Box::from(self.parse_statement()?);
+                    Ok(self.make_node(Case {
+                        value,
+                        body,
+                        label: Rc::from("".to_string()),
+                    }))
+                }
+                _ => Err(SyntaxError(Diagnostic::at(
+                    format!("Unexpected keyword {:?} at {:?}", keyword, self.line_number),
+                    &self.line_number,
                 ))),
             }
         } else {
@@ -784,8 +1087,8 @@ impl Parser {
                                 break;
                             }
                             _ => {
-                                let block = self.parse_block_item()?;
-                                block_items.push(block);
+                                let items = self.parse_block_item()?;
+                                block_items.extend(items);
                             }
                         }
                         next_token = self.peek_token();
@@ -805,63 +1108,124 @@ impl Parser {
         }
     }
 
-    fn parse_block_item(&mut self) -> Result<ASTNode<BlockItem>, CompilerError> {
-        if let Some(spec) = match_and_consume!(self, Token::Keyword(spec @ (Keyword::Type(_) | Keyword::StorageClass(_))) => Some(spec))
+    /// One statement, or one item per declarator of a declaration
+    fn parse_block_item(&mut self) -> Result<Vec<ASTNode<BlockItem>>, CompilerError> {
+        self.mark_line();
+        if match_and_consume!(self, Token::Keyword(Keyword::StaticAssert)) {
+            let assertion = self.parse_static_assert()?;
+            return Ok(vec![self.make_node(D(assertion))]);
+        }
+        if let Some(spec) = match_and_consume!(self, Token::Keyword(spec @ (Keyword::Type(_) | Keyword::StorageClass(_) | Keyword::Qualifier(_))) => Some(spec))
         {
             let mut specifiers = vec![spec];
-            while let Token::Keyword(spec @ (Keyword::Type(_) | Keyword::StorageClass(_))) =
-                self.peek_token()
+            while let Token::Keyword(
+                spec @ (Keyword::Type(_) | Keyword::StorageClass(_) | Keyword::Qualifier(_)),
+            ) = self.peek_token()
             {
                 self.tokens.pop_front();
                 specifiers.push(spec);
             }
-            let (type_, storage_class) = self.parse_type_and_storage_class(specifiers)?;
-            let out = self.parse_declaration((type_, storage_class), None)?;
-            if let Token::Symbol(Symbol::OpenParenthesis) = self.peek_token() {
-                return Err(SemanticError(format!(
-                    "Inner function declaration of {} at {:?}",
-                    out.kind.name, self.line_number
+            let specifiers = self.parse_type_and_storage_class(specifiers)?;
+            let mut items = vec![];
+            loop {
+                let out = self.parse_declaration(specifiers, None)?;
+                if let Token::Symbol(Symbol::OpenParenthesis) = self.peek_token() {
+                    return Err(SemanticError(Diagnostic::at(
+                        format!(
+                            "Inner function declaration of {} at {:?}",
+                            out.kind.name, self.line_number
+                        ),
+                        &self.line_number,
+                    )));
+                }
+                items.push(self.make_node(D(
+                    self.make_node(Declaration::VariableDeclaration(out.kind)),
                 )));
+                if !match_and_consume!(self, Token::Symbol(Symbol::Comma)) {
+                    break;
+                }
             }
             self.end_line()?;
-            Ok(self.make_node(D(self.make_node(Declaration::VariableDeclaration(out.kind)))))
+            Ok(items)
         } else {
             let statement = self.parse_statement()?;
-            Ok(self.make_node(S(Box::from(statement))))
+            Ok(vec![self.make_node(S(Box::from(statement)))])
         }
     }
 
     pub(crate) fn parse_program(&mut self) -> Result<ASTNode<Program>, CompilerError> {
         let mut declarations = Vec::new();
 
-        while !matches!(self.tokens.front().unwrap(), Token::EOF) {
-            let declaration = self.parse_top_level()?;
-            declarations.push(declaration);
+        while !matches!(self.peek_token(), Token::EOF) {
+            declarations.extend(self.parse_top_level()?);
         }
 
+        // an empty translation unit is accepted, as GCC and Clang do, and emits nothing
         expect_token!(self, Token::EOF)?;
 
         Ok(self.make_node(declarations))
     }
 
     fn peek_token(&self) -> Token {
-        self.tokens.front().unwrap().clone()
+        self.tokens.front().cloned().unwrap_or(Token::EOF)
+    }
+
+    /// Source line of the next token, or of the last one once they run out.
+    fn token_line(&self) -> i32 {
+        let consumed = self.token_lines.len() - self.tokens.len();
+        match self.token_lines.get(consumed) {
+            Some(line) => *line,
+            None => self.token_lines.last().copied().unwrap_or(0),
+        }
+    }
+
+    /// Move `line_number` to the line of the next token, keeping the function name.
+    fn mark_line(&mut self) {
+        self.line_number = Rc::from((self.token_line(), self.line_number.1.clone()));
+    }
+
+    /// Run `parse` one level deeper, failing once the input nests past `MAX_NESTING`.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, CompilerError>,
+    ) -> Result<T, CompilerError> {
+        if self.depth >= MAX_NESTING {
+            return Err(SyntaxError(Diagnostic::at(
+                format!(
+                    "Nested more than {} levels deep at {:?}",
+                    MAX_NESTING, self.line_number
+                ),
+                &self.line_number,
+            )));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
     fn end_line(&mut self) -> Result<(), CompilerError> {
         if match_and_consume!(self, Token::Symbol(Symbol::Semicolon)) {
-            self.line_number = Rc::from((self.line_number.0 + 1, self.line_number.1.clone()));
             Ok(())
         } else {
-            Err(SyntaxError(format!(
-                "Expected semicolon but got {:?} at {:?}",
-                self.peek_token(),
-                self.line_number
+            Err(SyntaxError(Diagnostic::at(
+                format!(
+                    "Expected semicolon but got {:?} at {:?}",
+                    self.peek_token(),
+                    self.line_number
+                ),
+                &self.line_number,
             )))
         }
     }
 
+    /// How many AST nodes have been built so far
+    pub(crate) fn node_count(&self) -> usize {
+        self.nodes.get()
+    }
+
     fn make_node<T>(&self, kind: T) -> ASTNode<T> {
+        self.nodes.set(self.nodes.get() + 1);
         ASTNode {
             line_number: Rc::clone(&self.line_number),
             kind,
@@ -870,6 +1234,6 @@ impl Parser {
     }
 
     fn consume_and_pop(&mut self) -> Token {
-        self.tokens.pop_front().unwrap()
+        self.tokens.pop_front().unwrap_or(Token::EOF)
     }
 }
@@ -0,0 +1,166 @@
+use crate::errors::CompilerError::SyntaxError;
+use crate::errors::{CompilerError, Diagnostic};
+use crate::lexer::{Token, lex};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Same limit as GCC, deep enough for real headers while stopping self-inclusion quickly
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+/// Lex `source`, splicing in the tokens of every `#include`d file.
+pub(crate) fn preprocess(
+    source: &str,
+    source_dir: &Path,
+    include_dirs: &[PathBuf],
+) -> Result<VecDeque<Token>, CompilerError> {
+    let mut preprocessor = Preprocessor {
+        include_dirs,
+        depth: 0,
+    };
+    let mut tokens = preprocessor.expand(source, source_dir)?;
+    tokens.push_back(Token::EOF);
+    Ok(tokens)
+}
+
+struct Preprocessor<'a> {
+    include_dirs: &'a [PathBuf],
+    depth: usize,
+}
+
+impl Preprocessor<'_> {
+    fn expand(&mut self, source: &str, directory: &Path) -> Result<VecDeque<Token>, CompilerError> {
+        let mut tokens = VecDeque::new();
+        // lines since the last directive, lexed together so __LINE__ stays accurate
+        let mut segment = String::new();
+        let mut segment_start = 1;
+        // a `#` inside a block comment spanning lines is comment text, not a directive
+        let mut in_comment = false;
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i as i32 + 1;
+            let directive = if in_comment {
+                None
+            } else {
+                line.trim_start().strip_prefix('#')
+            };
+            let Some(directive) = directive else {
+                in_comment = ends_in_comment(line, in_comment);
+                segment += line;
+                segment.push('\n');
+                continue;
+            };
+            tokens.extend(lex(&segment, segment_start)?);
+            segment.clear();
+            segment_start = line_number + 1;
+
+            let Some(target) = directive.trim_start().strip_prefix("include") else {
+                let column = line.len() - line.trim_start().len() + 1;
+                return Err(SyntaxError(Diagnostic::at_column(
+                    format!(
+                        "Unsupported preprocessor directive #{} at line {}",
+                        directive.trim(),
+                        line_number
+                    ),
+                    line_number,
+                    column,
+                )));
+            };
+            let path = self.resolve(target.trim(), directory, line_number)?;
+            tokens.extend(self.include(&path, line_number)?);
+        }
+        tokens.extend(lex(&segment, segment_start)?);
+        Ok(tokens)
+    }
+
+    /// Find the file named by `"name"` (next to the including file, then the include
+    /// path) or `<name>` (include path only).
+    fn resolve(
+        &self,
+        target: &str,
+        directory: &Path,
+        line_number: i32,
+    ) -> Result<PathBuf, CompilerError> {
+        let (name, candidates) =
+            if let Some(name) = target.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+                let mut candidates = vec![directory.join(name)];
+                candidates.extend(self.include_dirs.iter().map(|dir| dir.join(name)));
+                (name, candidates)
+            } else if let Some(name) = target.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+                (
+                    name,
+                    self.include_dirs.iter().map(|dir| dir.join(name)).collect(),
+                )
+            } else {
+                return Err(SyntaxError(
+                    format!("Malformed #include {} at line {}", target, line_number).into(),
+                ));
+            };
+        match candidates.iter().find(|path| path.is_file()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(SyntaxError(
+                format!(
+                    "Cannot find include file {} (tried {:?}) at line {}",
+                    name, candidates, line_number
+                )
+                .into(),
+            )),
+        }
+    }
+
+    fn include(&mut self, path: &Path, line_number: i32) -> Result<VecDeque<Token>, CompilerError> {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(SyntaxError(
+                format!(
+                    "#include of {} nested too deeply at line {}",
+                    path.display(),
+                    line_number
+                )
+                .into(),
+            ));
+        }
+        let source = fs::read_to_string(path).map_err(|err| {
+            SyntaxError(format!("Cannot read include file {}: {}", path.display(), err).into())
+        })?;
+        self.depth += 1;
+        let tokens = self.expand(&source, path.parent().unwrap_or(Path::new("")));
+        self.depth -= 1;
+        // positions only name lines of the main file, so header code keeps the #include's line
+        Ok(tokens?
+            .into_iter()
+            .filter(|token| !matches!(token, Token::Line(_)))
+            .collect())
+    }
+}
+
+/// Whether a block comment is still open at the end of `line`, given whether one was open
+/// at its start. Quoted literals and `//` comments are skipped, so `"/*"` opens nothing.
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if in_comment && chars.peek() == Some(&'/') => {
+                chars.next();
+                in_comment = false;
+            }
+            _ if in_comment => {}
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                in_comment = true;
+            }
+            '/' if chars.peek() == Some(&'/') => return false,
+            '"' | '\'' => {
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ if next == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    in_comment
+}
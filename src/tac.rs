@@ -1,13 +1,13 @@
 use crate::asm_ast::AsmAst::{
-    Binary, Call, Cdq, Cmp, Div, Function, Idiv, Jmp, JmpCC, Label, Mov, MovAl, MovZeroExtend,
-    Movsx, Push, Ret, SetCC, Static, Testl, Unary,
+    Binary, Call, Cdq, Cmp, Div, Function, Idiv, Jmp, JmpCC, JumpTable, Label, Lea, Mov, MovAl,
+    MovZeroExtend, Movsx, Push, Ret, SetCC, Static, Test, Unary,
 };
 use crate::asm_ast::{AsmAst, CondCode};
 use crate::common::Const;
 use crate::common::Const::ConstLong;
 use crate::lexer::{BinaryOperator, Type, UnaryOperator};
 use crate::tac::Pseudoregister::Register;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Display;
 use std::rc::Rc;
 
@@ -43,20 +43,20 @@ impl Pseudoregister {
         Pseudoregister::Pseudoregister(offset, *t)
     }
 
-    fn size(&self) -> i32 {
+    pub(crate) fn type_(&self) -> Type {
         match self {
-            Pseudoregister::Pseudoregister(_, t) => t.size(),
-            Register(_, t) => t.size(),
-            Pseudoregister::Data(_, t) => t.size(),
+            Pseudoregister::Pseudoregister(_, t) | Register(_, t) | Pseudoregister::Data(_, t) => {
+                *t
+            }
         }
     }
 
-    fn is_unsigned(&self) -> bool {
-        match self {
-            Pseudoregister::Pseudoregister(_, t) => matches!(t, Type::ULong | Type::UInt),
-            Register(_, t) => matches!(t, Type::ULong | Type::UInt),
-            Pseudoregister::Data(_, t) => matches!(t, Type::ULong | Type::UInt),
-        }
+    pub(crate) fn size(&self) -> i32 {
+        self.type_().size()
+    }
+
+    pub(crate) fn is_unsigned(&self) -> bool {
+        matches!(self.type_(), Type::ULong | Type::UInt)
     }
 }
 
@@ -69,7 +69,7 @@ pub(crate) enum Operand {
 }
 
 impl Operand {
-    fn size(&self) -> i32 {
+    pub(crate) fn size(&self) -> i32 {
         match self {
             Operand::Register(reg) => reg.size(),
             Operand::Immediate(c) => c.size(),
@@ -85,7 +85,7 @@ impl Operand {
         }
     }
 
-    fn is_unsigned(&self) -> bool {
+    pub(crate) fn is_unsigned(&self) -> bool {
         match self {
             Operand::Immediate(c) => matches!(c, Const::ConstUInt(_) | Const::ConstULong(_)),
             Operand::Register(reg) => reg.is_unsigned(),
@@ -150,6 +150,7 @@ pub(crate) enum TACInstruction {
     StaticVariable {
         name: Rc<String>,
         global: bool,
+        read_only: bool,
         init: Const,
     },
     UnaryOpInstruction {
@@ -171,9 +172,22 @@ pub(crate) enum TACInstruction {
         label: Rc<String>,
         operand: Rc<Operand>,
     },
+    /// Jump to `label` when `left op right` holds, without materializing the comparison
+    JumpIfComparison {
+        op: BinaryOperator,
+        left: Rc<Operand>,
+        right: Rc<Operand>,
+        label: Rc<String>,
+    },
     Jump {
         label: Rc<String>,
     },
+    /// Jump to `targets[index]`, through a table of their addresses; `index` is in range
+    JumpTable {
+        index: Rc<Operand>,
+        table: Rc<String>,
+        targets: Vec<Rc<String>>,
+    },
     Label {
         label: Rc<String>,
     },
@@ -187,7 +201,8 @@ pub(crate) enum TACInstruction {
     AllocateStackInstruction,
     FunctionCall(Rc<String>),
     PushArgument(Rc<Operand>),
-    AdjustStack(usize),
+    /// Release this many bytes of stack, or reserve them when negative
+    AdjustStack(i64),
     SignExtend {
         dest: Rc<Pseudoregister>,
         src: Rc<Operand>,
@@ -200,13 +215,44 @@ pub(crate) enum TACInstruction {
         dest: Rc<Pseudoregister>,
         src: Rc<Operand>,
     },
+    LoadAddress {
+        dest: Rc<Pseudoregister>,
+        name: Rc<String>,
+    },
+    /// Start of the code for a statement on this source line, when emitting debug info
+    Line(i32),
+    /// `left / right` and `left % right` from a single division
+    DivRem {
+        quotient: Rc<Pseudoregister>,
+        remainder: Rc<Pseudoregister>,
+        left: Rc<Operand>,
+        right: Rc<Operand>,
+    },
 }
 
 #[derive(Debug)]
 pub(crate) struct FunctionBody {
     pub(crate) current_offset: i32,
     pub(crate) instructions: Vec<TACInstruction>,
-    pub(crate) variable_to_pseudoregister: HashMap<String, Rc<Pseudoregister>>,
+    pub(crate) variable_to_pseudoregister: HashMap<Rc<str>, Rc<Pseudoregister>>,
+    /// Called functions that are not defined in this translation unit
+    pub(crate) external_functions: HashSet<Rc<String>>,
+    /// Stack slots of `volatile` variables, which optimizations must leave alone
+    pub(crate) volatile_slots: HashSet<i32>,
+    /// Label signed arithmetic jumps to on overflow, when overflow trapping is on
+    pub(crate) overflow_trap: Option<Rc<String>>,
+    /// Mark where each statement's code starts with a `Line` instruction
+    pub(crate) debug_lines: bool,
+    /// Skip the test of a loop condition that folds to a constant
+    pub(crate) fold_constants: bool,
+    /// Branch on each operand of a `&&`/`||` condition instead of testing its 0/1 value
+    pub(crate) short_circuit_branches: bool,
+    /// Dispatch a switch over dense cases through a jump table instead of a comparison each
+    pub(crate) jump_tables: bool,
+    /// Leave out the `%rbp` frame, for a leaf function that never touches the stack
+    pub(crate) frameless: bool,
+    /// Label of the epilogue shared by every return, when there is one
+    pub(crate) epilogue: Option<Rc<String>>,
 }
 
 impl FunctionBody {
@@ -215,6 +261,15 @@ impl FunctionBody {
             current_offset: 8,
             instructions: vec![],
             variable_to_pseudoregister: HashMap::new(),
+            external_functions: HashSet::new(),
+            volatile_slots: HashSet::new(),
+            overflow_trap: None,
+            debug_lines: false,
+            fold_constants: false,
+            short_circuit_branches: false,
+            jump_tables: false,
+            frameless: false,
+            epilogue: None,
         }
     }
 
@@ -222,6 +277,17 @@ impl FunctionBody {
         self.instructions.push(instruction);
     }
 
+    /// The first instruction reading an operand that has no value, which would be emitted
+    /// as an empty operand
+    pub(crate) fn valueless_operand(&self) -> Option<&TACInstruction> {
+        self.instructions.iter().find(|instruction| {
+            instruction
+                .used_operands()
+                .iter()
+                .any(|operand| matches!(operand.as_ref(), Operand::None))
+        })
+    }
+
     pub(crate) fn add_default_return(&mut self) {
         match &self.instructions.last() {
             Some(TACInstruction::ReturnInstruction { .. }) | None => {}
@@ -235,12 +301,48 @@ impl FunctionBody {
 }
 
 impl TACInstruction {
+    /// Operands whose values the instruction reads
+    pub(crate) fn used_operands(&self) -> Vec<&Rc<Operand>> {
+        match self {
+            TACInstruction::StoreValueInstruction { src, .. }
+            | TACInstruction::SignExtend { src, .. }
+            | TACInstruction::Truncate { src, .. }
+            | TACInstruction::ZeroExtend { src, .. } => vec![src],
+            TACInstruction::UnaryOpInstruction { operand, .. }
+            | TACInstruction::JumpIfZero { operand, .. }
+            | TACInstruction::JumpIfNotZero { operand, .. }
+            | TACInstruction::PushArgument(operand)
+            | TACInstruction::JumpTable { index: operand, .. } => vec![operand],
+            TACInstruction::BinaryOpInstruction { left, right, .. }
+            | TACInstruction::JumpIfComparison { left, right, .. }
+            | TACInstruction::DivRem { left, right, .. } => vec![left, right],
+            TACInstruction::ReturnInstruction { val } => vec![val],
+            _ => vec![],
+        }
+    }
+
     pub(crate) fn make_assembly(&self, out: &mut VecDeque<AsmAst>, function_body: &FunctionBody) {
         match &self {
             TACInstruction::FunctionInstruction { name, global } => out.push_back(Function {
                 name: Rc::clone(name),
                 global: *global,
+                frame: !function_body.frameless,
             }),
+            // `!x` is `x == 0`, tested at the operand's own width
+            TACInstruction::UnaryOpInstruction {
+                dest,
+                op: UnaryOperator::LogicalNot,
+                operand,
+            } => {
+                let zero = Const::ConstInt(0).convert_to(&Type::of_size(operand.size()));
+                make_binary_op_instruction(
+                    out,
+                    dest,
+                    &BinaryOperator::Equals,
+                    operand,
+                    &Rc::from(Operand::Immediate(zero)),
+                );
+            }
             TACInstruction::UnaryOpInstruction { dest, op, operand } => {
                 out.push_back(Mov {
                     size: dest.size(),
@@ -258,32 +360,59 @@ impl TACInstruction {
                 op,
                 left,
                 right,
-            } => make_binary_op_instruction(out, dest, op, left, right),
+            } => {
+                make_binary_op_instruction(out, dest, op, left, right);
+                if let Some(trap) = &function_body.overflow_trap
+                    && !dest.is_unsigned()
+                    && matches!(
+                        op,
+                        BinaryOperator::Addition
+                            | BinaryOperator::Subtraction
+                            | BinaryOperator::Multiply
+                    )
+                {
+                    out.push_back(JmpCC {
+                        condition: CondCode::Overflow,
+                        label: Rc::clone(trap),
+                    });
+                }
+            }
             TACInstruction::JumpIfZero { label, operand } => {
-                out.push_back(Mov {
-                    size: 4,
-                    src: Rc::clone(operand),
-                    dest: Rc::from(Register(Reg::DX, Type::Int)),
-                });
-                out.push_back(Testl(Rc::from(Register(Reg::DX, Type::Int))));
+                make_zero_test(out, operand);
                 out.push_back(JmpCC {
                     condition: CondCode::Equal,
                     label: Rc::clone(&label),
                 });
             }
             TACInstruction::JumpIfNotZero { label, operand } => {
-                out.push_back(Mov {
-                    size: 4,
-                    src: Rc::clone(operand),
-                    dest: Rc::from(Register(Reg::DX, Type::Int)),
-                });
-                out.push_back(Testl(Rc::from(Register(Reg::DX, Type::Int))));
+                make_zero_test(out, operand);
                 out.push_back(JmpCC {
                     condition: CondCode::NotEqual,
                     label: Rc::clone(&label),
                 });
             }
+            TACInstruction::JumpIfComparison {
+                op,
+                left,
+                right,
+                label,
+            } => {
+                make_comparison(out, left, right);
+                out.push_back(JmpCC {
+                    condition: comparison_condition(op, left, right),
+                    label: Rc::clone(label),
+                });
+            }
             TACInstruction::Jump { label } => out.push_back(Jmp(Rc::clone(label))),
+            TACInstruction::JumpTable {
+                index,
+                table,
+                targets,
+            } => out.push_back(JumpTable {
+                index: Rc::clone(index),
+                table: Rc::clone(table),
+                targets: targets.clone(),
+            }),
             TACInstruction::Label { label } => out.push_back(Label(Rc::clone(label))),
             TACInstruction::StoreValueInstruction { dest, src } => out.push_back(Mov {
                 size: dest.size(),
@@ -291,18 +420,20 @@ impl TACInstruction {
                 dest: Rc::clone(dest),
             }),
             TACInstruction::ReturnInstruction { val } => {
-                let t = if val.size() == 4 {
-                    Type::Int
-                } else {
-                    Type::Long
-                };
+                let t = Type::of_size(val.size());
                 out.push_back(Mov {
                     size: val.size(),
                     src: Rc::clone(val),
                     dest: Rc::from(Register(Reg::AX, t)),
                 });
-                out.push_back(Ret);
+                match &function_body.epilogue {
+                    Some(epilogue) => out.push_back(Jmp(Rc::clone(epilogue))),
+                    None => out.push_back(Ret {
+                        frame: !function_body.frameless,
+                    }),
+                }
             }
+            TACInstruction::AllocateStackInstruction if function_body.frameless => {}
             TACInstruction::AllocateStackInstruction => {
                 let allocate = (function_body.current_offset + 15) & !15;
                 out.push_back(Binary {
@@ -312,12 +443,17 @@ impl TACInstruction {
                     dest: Rc::from(Register(Reg::SP, Type::Long)),
                 });
             }
-            TACInstruction::FunctionCall(name) => out.push_back(Call(Rc::clone(name))),
+            TACInstruction::FunctionCall(name) => out.push_back(Call {
+                name: Rc::clone(name),
+                external: function_body.external_functions.contains(name),
+            }),
             TACInstruction::PushArgument(value) => {
+                // load the full argument so long values keep their upper half
+                let t = Type::of_size(value.size());
                 out.push_back(Mov {
-                    size: 4,
+                    size: value.size(),
                     src: Rc::clone(value),
-                    dest: Rc::from(Register(Reg::R10, Type::Int)),
+                    dest: Rc::from(Register(Reg::R10, t)),
                 });
                 out.push_back(Push(Rc::from(Operand::Register(Register(
                     Reg::R10,
@@ -325,18 +461,28 @@ impl TACInstruction {
                 )))));
             }
             TACInstruction::AdjustStack(size) => {
+                let operator = if *size < 0 {
+                    BinaryOperator::Subtraction
+                } else {
+                    BinaryOperator::Addition
+                };
                 out.push_back(Binary {
                     size: 8,
-                    operator: BinaryOperator::Addition,
-                    src: Rc::from(Operand::Immediate(ConstLong(*size as i64))),
+                    operator,
+                    src: Rc::from(Operand::Immediate(ConstLong(size.abs()))),
                     dest: Rc::from(Register(Reg::SP, Type::Long)),
                 });
             }
-            TACInstruction::StaticVariable { name, global, init } => {
+            TACInstruction::StaticVariable {
+                name,
+                global,
+                read_only,
+                init,
+            } => {
                 out.push_back(Static {
-                    size: init.size(),
                     name: Rc::clone(name),
                     global: *global,
+                    read_only: *read_only,
                     init: init.clone(),
                 });
             }
@@ -367,10 +513,92 @@ impl TACInstruction {
                     dest: dest.clone(),
                 });
             }
+            TACInstruction::LoadAddress { dest, name } => {
+                // lea needs a register destination
+                out.push_back(Lea {
+                    name: Rc::clone(name),
+                    dest: Rc::from(Register(Reg::R10, Type::Long)),
+                });
+                out.push_back(Mov {
+                    size: 8,
+                    src: Rc::from(Operand::Register(Register(Reg::R10, Type::Long))),
+                    dest: Rc::clone(dest),
+                });
+            }
+            TACInstruction::Line(line) => out.push_back(AsmAst::Loc(*line)),
+            TACInstruction::DivRem {
+                quotient,
+                remainder,
+                left,
+                right,
+            } => {
+                let t = Type::of_size(left.size());
+                make_division(out, left, right);
+                out.push_back(Mov {
+                    size: quotient.size(),
+                    src: Rc::from(Operand::Register(Register(Reg::AX, t))),
+                    dest: Rc::clone(quotient),
+                });
+                out.push_back(Mov {
+                    size: remainder.size(),
+                    src: Rc::from(Operand::Register(Register(Reg::DX, t))),
+                    dest: Rc::clone(remainder),
+                });
+            }
         }
     }
 }
 
+/// Divide `left` by `right`, leaving the quotient in AX and the remainder in DX.
+fn make_division(out: &mut VecDeque<AsmAst>, left: &Rc<Operand>, right: &Rc<Operand>) {
+    let t = Type::of_size(left.size());
+    // Move left operand to AX register
+    out.push_back(Mov {
+        size: left.size(),
+        src: Rc::clone(left),
+        dest: Rc::from(Register(Reg::AX, t)),
+    });
+    if left.is_unsigned() {
+        // Zero-extend AX to DX:AX
+        let c = if left.size() == 4 {
+            Const::ConstUInt(0)
+        } else {
+            Const::ConstULong(0)
+        };
+        out.push_back(Mov {
+            size: left.size(),
+            src: Rc::from(Operand::Immediate(c)),
+            dest: Rc::from(Register(Reg::DX, t)),
+        });
+    } else {
+        // Sign-extend AX to DX:AX
+        out.push_back(Cdq { size: left.size() });
+    }
+    // div and idiv can't take an immediate, so those go through R11
+    let divisor = if right.is_immediate() {
+        out.push_back(Mov {
+            size: right.size(),
+            src: Rc::clone(right),
+            dest: Rc::from(Register(Reg::R11, t)),
+        });
+        Rc::from(Operand::Register(Register(Reg::R11, t)))
+    } else {
+        Rc::clone(right)
+    };
+    // Divide DX:AX by the divisor, result in AX (quotient) and DX (remainder)
+    if left.is_unsigned() {
+        out.push_back(Div {
+            size: left.size(),
+            operand: divisor,
+        });
+    } else {
+        out.push_back(Idiv {
+            size: left.size(),
+            operand: divisor,
+        });
+    }
+}
+
 fn make_binary_op_instruction(
     out: &mut VecDeque<AsmAst>,
     dest: &Rc<Pseudoregister>,
@@ -378,11 +606,7 @@ fn make_binary_op_instruction(
     left: &Rc<Operand>,
     right: &Rc<Operand>,
 ) {
-    let t = if left.size() == 4 {
-        Type::Int
-    } else {
-        Type::Long
-    };
+    let t = Type::of_size(left.size());
     match op {
         BinaryOperator::BitwiseShiftLeft | BinaryOperator::BitwiseShiftRight => {
             // First, move the left operand (value to be shifted) to the destination
@@ -461,60 +685,7 @@ fn make_binary_op_instruction(
             });
         }
         BinaryOperator::Divide | BinaryOperator::Modulo => {
-            if left.is_unsigned() {
-                let c = if left.size() == 4 {
-                    Const::ConstUInt(0)
-                } else {
-                    Const::ConstULong(0)
-                };
-                out.push_back(Mov {
-                    size: left.size(),
-                    src: Rc::clone(left),
-                    dest: Rc::from(Register(Reg::AX, t)),
-                });
-                out.push_back(Mov {
-                    size: left.size(),
-                    src: Rc::from(Operand::Immediate(c)),
-                    dest: Rc::from(Register(Reg::DX, t)),
-                });
-                if right.is_immediate() {
-                    out.push_back(Mov {
-                        size: right.size(),
-                        src: Rc::clone(right),
-                        dest: Rc::from(Register(Reg::R11, t)),
-                    });
-                    out.push_back(Div {
-                        size: left.size(),
-                        operand: Rc::from(Operand::Register(Register(Reg::R11, t))),
-                    });
-                } else {
-                    out.push_back(Div {
-                        size: left.size(),
-                        operand: Rc::clone(right),
-                    });
-                }
-            } else {
-                // Divide/Modulo
-                // Move left operand to AX register
-                out.push_back(Mov {
-                    size: left.size(),
-                    src: Rc::clone(left),
-                    dest: Rc::from(Register(Reg::AX, t)),
-                });
-                // Sign-extend AX to DX:AX
-                out.push_back(Cdq { size: left.size() });
-                // Move right operand to CX register
-                out.push_back(Mov {
-                    size: right.size(),
-                    src: Rc::clone(right),
-                    dest: Rc::from(Register(Reg::CX, t)),
-                });
-                // Divide DX:AX by CX, result in AX (quotient) and DX (remainder)
-                out.push_back(Idiv {
-                    size: right.size(),
-                    operand: Rc::from(Register(Reg::CX, t)),
-                });
-            }
+            make_division(out, left, right);
             // Move quotient (AX) or remainder (DX) to destination
             if *op == BinaryOperator::Divide {
                 out.push_back(Mov {
@@ -537,32 +708,7 @@ fn make_binary_op_instruction(
         | BinaryOperator::GreaterThanOrEquals
         | BinaryOperator::LessThan
         | BinaryOperator::LessThanOrEquals => {
-            // Move left operand to DX register
-            out.push_back(Mov {
-                size: left.size(),
-                src: Rc::clone(left),
-                dest: Rc::from(Register(Reg::DX, t)),
-            });
-
-            // Handle comparison
-            if right.size() == 8 && right.is_immediate() {
-                out.push_back(Mov {
-                    size: right.size(),
-                    src: Rc::clone(right),
-                    dest: Rc::from(Register(Reg::R11, Type::Long)),
-                });
-                out.push_back(Cmp {
-                    size: 8,
-                    left: Rc::from(Operand::Register(Register(Reg::R11, Type::Long))),
-                    right: Rc::from(Operand::Register(Register(Reg::DX, Type::Long))),
-                });
-            } else {
-                out.push_back(Cmp {
-                    size: left.size(),
-                    left: Rc::clone(right),
-                    right: Rc::from(Operand::Register(Register(Reg::DX, t))),
-                });
-            }
+            make_comparison(out, left, right);
 
             // Initialize destination with 0
             out.push_back(Mov {
@@ -572,27 +718,7 @@ fn make_binary_op_instruction(
             });
 
             // Set AL based on comparison
-            let condition = if right.is_unsigned() || left.is_unsigned() {
-                match op {
-                    BinaryOperator::Equals => CondCode::Equal,
-                    BinaryOperator::NotEquals => CondCode::NotEqual,
-                    BinaryOperator::LessThan => CondCode::Below,
-                    BinaryOperator::GreaterThan => CondCode::Above,
-                    BinaryOperator::LessThanOrEquals => CondCode::BelowOrEqual,
-                    BinaryOperator::GreaterThanOrEquals => CondCode::AboveOrEqual,
-                    _ => unreachable!(),
-                }
-            } else {
-                match op {
-                    BinaryOperator::Equals => CondCode::Equal,
-                    BinaryOperator::NotEquals => CondCode::NotEqual,
-                    BinaryOperator::LessThan => CondCode::LessThan,
-                    BinaryOperator::GreaterThan => CondCode::GreaterThan,
-                    BinaryOperator::LessThanOrEquals => CondCode::LessEqual,
-                    BinaryOperator::GreaterThanOrEquals => CondCode::GreaterEqual,
-                    _ => unreachable!(),
-                }
-            };
+            let condition = comparison_condition(op, left, right);
 
             // We'll hardcode to use AL register in the SetCC implementation
             out.push_back(SetCC(condition));
@@ -600,13 +726,145 @@ fn make_binary_op_instruction(
             // Zero-extend AL to R10D
             out.push_back(MovAl(Rc::from(Register(Reg::R10, Type::Int))));
 
-            // Move the result to destination
+            // Move the result to destination at its full width; writing R10D already
+            // cleared the upper half of R10, so a long destination gets 0 or 1 in all 64 bits
+            let dest_type = Type::of_size(dest.size());
             out.push_back(Mov {
-                size: 4,
-                src: Rc::from(Operand::Register(Register(Reg::R10, Type::Int))),
+                size: dest.size(),
+                src: Rc::from(Operand::Register(Register(Reg::R10, dest_type))),
                 dest: Rc::clone(dest),
             })
         }
         _ => unreachable!(),
     }
 }
+
+/// Compare `left` with `right`, leaving the result in the flags
+fn make_comparison(out: &mut VecDeque<AsmAst>, left: &Rc<Operand>, right: &Rc<Operand>) {
+    let t = Type::of_size(left.size());
+    // Move left operand to DX register
+    out.push_back(Mov {
+        size: left.size(),
+        src: Rc::clone(left),
+        dest: Rc::from(Register(Reg::DX, t)),
+    });
+
+    // a 64-bit immediate right operand is loaded into a register by `assembly_fix`
+    out.push_back(Cmp {
+        size: left.size().max(right.size()),
+        left: Rc::clone(right),
+        right: Rc::from(Operand::Register(Register(Reg::DX, t))),
+    });
+}
+
+/// Set the zero flag from `operand` at its full width
+fn make_zero_test(out: &mut VecDeque<AsmAst>, operand: &Rc<Operand>) {
+    let dx: Rc<Pseudoregister> = Rc::from(Register(Reg::DX, Type::of_size(operand.size())));
+    out.push_back(Mov {
+        size: operand.size(),
+        src: Rc::clone(operand),
+        dest: Rc::clone(&dx),
+    });
+    out.push_back(Test(dx));
+}
+
+/// Flags condition under which `left op right` holds after `make_comparison`
+fn comparison_condition(op: &BinaryOperator, left: &Operand, right: &Operand) -> CondCode {
+    if right.is_unsigned() || left.is_unsigned() {
+        match op {
+            BinaryOperator::Equals => CondCode::Equal,
+            BinaryOperator::NotEquals => CondCode::NotEqual,
+            BinaryOperator::LessThan => CondCode::Below,
+            BinaryOperator::GreaterThan => CondCode::Above,
+            BinaryOperator::LessThanOrEquals => CondCode::BelowOrEqual,
+            BinaryOperator::GreaterThanOrEquals => CondCode::AboveOrEqual,
+            _ => unreachable!(),
+        }
+    } else {
+        match op {
+            BinaryOperator::Equals => CondCode::Equal,
+            BinaryOperator::NotEquals => CondCode::NotEqual,
+            BinaryOperator::LessThan => CondCode::LessThan,
+            BinaryOperator::GreaterThan => CondCode::GreaterThan,
+            BinaryOperator::LessThanOrEquals => CondCode::LessEqual,
+            BinaryOperator::GreaterThanOrEquals => CondCode::GreaterEqual,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_sizes_and_alignment() {
+        let matrix = [
+            (Type::Void, 0, 1),
+            (Type::Int, 4, 4),
+            (Type::UInt, 4, 4),
+            (Type::Long, 8, 8),
+            (Type::ULong, 8, 8),
+        ];
+        for (t, size, align) in matrix {
+            assert_eq!((t.size(), t.align()), (size, align), "{:?}", t);
+            if size == 0 {
+                continue;
+            }
+            for register in [
+                Pseudoregister::Pseudoregister(8, t),
+                Register(Reg::AX, t),
+                Pseudoregister::Data(Rc::new("g".to_string()), t),
+            ] {
+                assert_eq!(register.size(), size, "{:?}", register);
+                assert_eq!(Operand::Register(register).size(), size);
+            }
+            assert_eq!(
+                Operand::MemoryReference(0, "rsp".to_string(), t).size(),
+                size
+            );
+            assert_eq!(Const::ConstInt(1).convert_to(&t).size(), size, "{:?}", t);
+            assert_eq!(Type::of_size(size).size(), size);
+        }
+    }
+
+    #[test]
+    fn test_comparison_fills_long_destination() {
+        let mut out = VecDeque::new();
+        make_binary_op_instruction(
+            &mut out,
+            &Rc::from(Pseudoregister::Pseudoregister(8, Type::Long)),
+            &BinaryOperator::LessThan,
+            &Rc::from(Operand::Immediate(Const::ConstLong(1))),
+            &Rc::from(Operand::Immediate(Const::ConstLong(2))),
+        );
+        assert!(matches!(
+            out.back(),
+            Some(Mov {
+                size: 8,
+                src,
+                ..
+            }) if matches!(src.as_ref(), Operand::Register(Register(Reg::R10, Type::Long)))
+        ));
+    }
+
+    #[test]
+    fn test_valueless_operand_is_found() {
+        // what the sum of a void call's result and 1 would lower to
+        let mut body = FunctionBody::new();
+        body.add_instruction(TACInstruction::FunctionCall(Rc::new("f".to_string())));
+        body.add_instruction(TACInstruction::BinaryOpInstruction {
+            dest: Rc::from(Pseudoregister::Pseudoregister(8, Type::Int)),
+            op: BinaryOperator::Addition,
+            left: Rc::from(Operand::None),
+            right: Rc::from(Operand::Immediate(Const::ConstInt(1))),
+        });
+        assert!(matches!(
+            body.valueless_operand(),
+            Some(TACInstruction::BinaryOpInstruction { .. })
+        ));
+        body.instructions.pop();
+        body.add_default_return();
+        assert!(body.valueless_operand().is_none());
+    }
+}
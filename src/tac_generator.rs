@@ -1,16 +1,33 @@
-use crate::ast::{ASTNode, Declaration, Expression, ForInit, Statement, Visitor};
-use crate::common::{Const, Position};
-use crate::errors::CompilerError;
+use crate::ast::{
+    ASTNode, Declaration, Expression, ForInit, FunAttr, InlineBody, Statement, SwitchCases, Visitor,
+};
+use crate::common::{Const, Interner, Position};
 use crate::errors::CompilerError::SemanticError;
+use crate::errors::{CompilerError, Diagnostic};
 use crate::lexer::{BinaryOperator, StorageClass, Type, UnaryOperator};
 use crate::tac::TACInstruction::{
     AdjustStack, AllocateStackInstruction, BinaryOpInstruction, FunctionCall, FunctionInstruction,
-    Jump, JumpIfNotZero, JumpIfZero, Label, PushArgument, ReturnInstruction, SignExtend,
-    StoreValueInstruction, Truncate, UnaryOpInstruction, ZeroExtend,
+    Jump, JumpIfNotZero, JumpIfZero, JumpTable, Label, Line, LoadAddress, PushArgument,
+    ReturnInstruction, SignExtend, StoreValueInstruction, Truncate, UnaryOpInstruction, ZeroExtend,
 };
 use crate::tac::{FunctionBody, Operand, Pseudoregister, Reg};
+use std::collections::HashMap;
 use std::rc::Rc;
 
+/// Fewest cases a switch needs before it dispatches through a jump table
+const JUMP_TABLE_MIN_CASES: usize = 4;
+
+/// Enough cases, spread over few enough values, that a table beats comparing with each.
+fn is_dense(cases: &[(Const, Rc<String>)]) -> bool {
+    let values = || cases.iter().map(|(case, _)| case.value());
+    match (values().min(), values().max()) {
+        (Some(low), Some(high)) => {
+            cases.len() >= JUMP_TABLE_MIN_CASES && high - low < 2 * cases.len() as i128
+        }
+        _ => false,
+    }
+}
+
 const FIRST_SIX_REGISTERS: [Reg; 6] = [
     Reg::DI,
     Reg::SI,
@@ -25,16 +42,256 @@ pub(crate) struct TacVisitor<'a> {
     body: &'a mut FunctionBody,
     result: Rc<Operand>,
     label_count: i32,
+    functions_map: &'a HashMap<String, FunAttr>,
+    inlining: Vec<Rc<String>>,
+    /// Set while visiting a conditional whose value is discarded, so its arms store nothing
+    discard_result: bool,
+    /// Keys of `variable_to_pseudoregister`
+    names: Interner,
 }
 
 impl<'a> TacVisitor<'a> {
-    pub(crate) fn new(name: Rc<String>, body: &'a mut FunctionBody) -> Self {
+    pub(crate) fn new(
+        name: Rc<String>,
+        body: &'a mut FunctionBody,
+        functions_map: &'a HashMap<String, FunAttr>,
+    ) -> Self {
         Self {
             name,
             body,
             result: Rc::new(Operand::None),
             label_count: 0,
+            functions_map,
+            inlining: vec![],
+            discard_result: false,
+            names: Interner::default(),
+        }
+    }
+
+    /// Generate `expression` for its side effects only. A conditional then behaves like
+    /// an if/else, with no slot for its value.
+    fn accept_for_effect(
+        &mut self,
+        expression: &mut ASTNode<Expression>,
+    ) -> Result<(), CompilerError> {
+        self.discard_result = matches!(expression.kind, Expression::Condition { .. });
+        expression.accept(self)
+    }
+
+    /// Compare `value` with every case, then fall back to `default_label`.
+    fn make_comparison_chain(
+        &mut self,
+        value: &Rc<Operand>,
+        cases: Vec<(Const, Rc<String>)>,
+        default_label: Rc<String>,
+    ) {
+        for (case, case_label) in cases {
+            let matches = Rc::new(Pseudoregister::new(self.body.current_offset, &Type::Int));
+            self.body.current_offset += 8;
+            self.body.add_instruction(BinaryOpInstruction {
+                dest: Rc::clone(&matches),
+                op: BinaryOperator::Equals,
+                left: Rc::clone(value),
+                right: Rc::from(Operand::Immediate(case)),
+            });
+            self.body.add_instruction(JumpIfNotZero {
+                label: case_label,
+                operand: Rc::from(Operand::Register((*matches).clone())),
+            });
+        }
+        self.body.add_instruction(Jump {
+            label: default_label,
+        });
+    }
+
+    /*
+    Index a table spanning the lowest to the highest case, whose gaps go to `default_label`.
+    The index is rebased in the unsigned type, so a value below the lowest case wraps past
+    the highest and one bounds check covers both ends.
+     */
+    fn make_jump_table(
+        &mut self,
+        value: &Rc<Operand>,
+        cases: &[(Const, Rc<String>)],
+        default_label: &Rc<String>,
+        table: Rc<String>,
+    ) {
+        let (lowest, _) = cases.iter().min_by_key(|(case, _)| case.value()).unwrap();
+        let span = cases.iter().map(|(case, _)| case.value()).max().unwrap() - lowest.value();
+        let targets = (0..=span)
+            .map(|offset| {
+                cases
+                    .iter()
+                    .find(|(case, _)| case.value() - lowest.value() == offset)
+                    .map_or(default_label, |(_, case_label)| case_label)
+                    .clone()
+            })
+            .collect();
+
+        let unsigned = if value.size() == 4 {
+            Type::UInt
+        } else {
+            Type::ULong
+        };
+        let index = Rc::new(Pseudoregister::new(self.body.current_offset, &unsigned));
+        self.body.current_offset += 8;
+        self.body.add_instruction(BinaryOpInstruction {
+            dest: Rc::clone(&index),
+            op: BinaryOperator::Subtraction,
+            left: Rc::clone(value),
+            right: Rc::from(Operand::Immediate(lowest.convert_to(&unsigned))),
+        });
+        let index = Rc::new(Operand::Register((*index).clone()));
+        let out_of_range = Rc::new(Pseudoregister::new(self.body.current_offset, &Type::Int));
+        self.body.current_offset += 8;
+        self.body.add_instruction(BinaryOpInstruction {
+            dest: Rc::clone(&out_of_range),
+            op: BinaryOperator::GreaterThan,
+            left: Rc::clone(&index),
+            right: Rc::from(Operand::Immediate(
+                Const::ConstULong(span as u64).convert_to(&unsigned),
+            )),
+        });
+        self.body.add_instruction(JumpIfNotZero {
+            label: Rc::clone(default_label),
+            operand: Rc::from(Operand::Register((*out_of_range).clone())),
+        });
+        self.body.add_instruction(JumpTable {
+            index,
+            table,
+            targets,
+        });
+    }
+
+    fn new_label(&mut self, suffix: &str) -> Rc<String> {
+        let label = Rc::from(format!(".{}{}_{}", self.name, self.label_count, suffix));
+        self.label_count += 1;
+        label
+    }
+
+    /// Jump to `target` when `condition` is zero.
+    fn jump_if_false(
+        &mut self,
+        condition: &mut ASTNode<Expression>,
+        target: &Rc<String>,
+    ) -> Result<(), CompilerError> {
+        if self.body.short_circuit_branches {
+            match &mut condition.kind {
+                Expression::Binary {
+                    op: BinaryOperator::LogicalAnd,
+                    left,
+                    right,
+                } => {
+                    self.jump_if_false(left, target)?;
+                    return self.jump_if_false(right, target);
+                }
+                Expression::Binary {
+                    op: BinaryOperator::LogicalOr,
+                    left,
+                    right,
+                } => {
+                    let true_label = self.new_label("true");
+                    self.jump_if_true(left, &true_label)?;
+                    self.jump_if_false(right, target)?;
+                    self.body.add_instruction(Label { label: true_label });
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        condition.accept(self)?;
+        self.body.add_instruction(JumpIfZero {
+            label: Rc::clone(target),
+            operand: Rc::clone(&self.result),
+        });
+        Ok(())
+    }
+
+    /// Jump to `target` when `condition` is nonzero.
+    fn jump_if_true(
+        &mut self,
+        condition: &mut ASTNode<Expression>,
+        target: &Rc<String>,
+    ) -> Result<(), CompilerError> {
+        if self.body.short_circuit_branches {
+            match &mut condition.kind {
+                Expression::Binary {
+                    op: BinaryOperator::LogicalOr,
+                    left,
+                    right,
+                } => {
+                    self.jump_if_true(left, target)?;
+                    return self.jump_if_true(right, target);
+                }
+                Expression::Binary {
+                    op: BinaryOperator::LogicalAnd,
+                    left,
+                    right,
+                } => {
+                    let false_label = self.new_label("false");
+                    self.jump_if_false(left, &false_label)?;
+                    self.jump_if_true(right, target)?;
+                    self.body.add_instruction(Label { label: false_label });
+                    return Ok(());
+                }
+                _ => {}
+            }
         }
+        condition.accept(self)?;
+        self.body.add_instruction(JumpIfNotZero {
+            label: Rc::clone(target),
+            operand: Rc::clone(&self.result),
+        });
+        Ok(())
+    }
+
+    /*
+    Evaluate the arguments into fresh slots standing in for the parameters, then
+    generate the inline function's returned expression in place of the call. The
+    parameters are only bound once every argument is evaluated, since an argument may
+    inline the same function, and the caller's bindings are restored afterwards.
+     */
+    fn inline_call(
+        &mut self,
+        identifier: &Rc<String>,
+        arguments: &mut [ASTNode<Expression>],
+        inline_body: &InlineBody,
+    ) -> Result<(), CompilerError> {
+        let mut param_registers = vec![];
+        for argument in arguments.iter_mut() {
+            argument.accept(self)?;
+            let param_register = Rc::new(Pseudoregister::new(
+                self.body.current_offset,
+                &argument.type_,
+            ));
+            self.body.current_offset += 8;
+            self.body.add_instruction(StoreValueInstruction {
+                dest: Rc::clone(&param_register),
+                src: Rc::clone(&self.result),
+            });
+            param_registers.push(param_register);
+        }
+        let mut shadowed = vec![];
+        for (param, param_register) in inline_body.params.iter().zip(param_registers) {
+            let name = self.names.intern(param);
+            let previous = self
+                .body
+                .variable_to_pseudoregister
+                .insert(Rc::clone(&name), param_register);
+            shadowed.push((name, previous));
+        }
+
+        self.inlining.push(Rc::clone(identifier));
+        let mut expression = inline_body.expression.clone();
+        let generated = expression.accept(self);
+        self.inlining.pop();
+        for (name, previous) in shadowed {
+            match previous {
+                Some(register) => self.body.variable_to_pseudoregister.insert(name, register),
+                None => self.body.variable_to_pseudoregister.remove(&name),
+            };
+        }
+        generated
     }
 }
 
@@ -46,7 +303,10 @@ impl<'a> Visitor for TacVisitor<'a> {
     ) -> Result<(), CompilerError> {
         match declaration {
             Declaration::VariableDeclaration(v) => {
-                if v.storage_class.is_some() {
+                if matches!(
+                    v.storage_class,
+                    Some(StorageClass::Static | StorageClass::Extern)
+                ) {
                     return Ok(());
                 }
                 let (identifier, expression) = (&v.name, &mut v.init);
@@ -54,7 +314,10 @@ impl<'a> Visitor for TacVisitor<'a> {
                     Rc::from(Pseudoregister::new(self.body.current_offset, &v.var_type));
                 self.body
                     .variable_to_pseudoregister
-                    .insert(identifier.as_ref().to_string(), Rc::clone(&pseudoregister));
+                    .insert(self.names.intern(identifier), Rc::clone(&pseudoregister));
+                if v.volatile {
+                    self.body.volatile_slots.insert(self.body.current_offset);
+                }
                 if let Some(expression) = expression {
                     expression.accept(self)?;
                     self.body.add_instruction(StoreValueInstruction {
@@ -65,6 +328,7 @@ impl<'a> Visitor for TacVisitor<'a> {
                 self.body.current_offset += 8;
                 Ok(())
             }
+            Declaration::StaticAssert(_) => Ok(()),
             Declaration::FunctionDeclaration(func) => {
                 if let Some(body) = &mut func.body {
                     self.body.add_instruction(FunctionInstruction {
@@ -82,7 +346,7 @@ impl<'a> Visitor for TacVisitor<'a> {
 
                         self.body
                             .variable_to_pseudoregister
-                            .insert(param.to_string(), Rc::clone(&param_register));
+                            .insert(self.names.intern(param), Rc::clone(&param_register));
 
                         if i < 6 {
                             let reg = &FIRST_SIX_REGISTERS[i];
@@ -122,7 +386,10 @@ impl<'a> Visitor for TacVisitor<'a> {
     ) -> Result<(), CompilerError> {
         left.accept(self)?;
         let dest = Rc::clone(&self.result);
+        // an AssignmentTarget in `right` reads the lvalue through the result left in place
         right.accept(self)?;
+        // `self.result` stays the stored value, already of the left side's type, so an
+        // enclosing assignment like `a = (b = c)` stores it again
         let src = Rc::clone(&self.result);
         match dest.as_ref() {
             Operand::Register(variable) => {
@@ -133,13 +400,32 @@ impl<'a> Visitor for TacVisitor<'a> {
                 });
                 Ok(())
             }
-            _ => Err(SemanticError(format!(
-                "Expected lvalue but got {:?} at {:?}",
-                src, line_number
+            _ => Err(SemanticError(Diagnostic::at(
+                format!("Expected lvalue but got {:?} at {:?}", src, line_number),
+                line_number,
             ))),
         }
     }
 
+    fn visit_line(&mut self, line_number: &Rc<Position>) -> Result<(), CompilerError> {
+        if self.body.debug_lines {
+            // a statement that emitted no code shares its marker with the next one
+            if let Some(Line(_)) = self.body.instructions.last() {
+                self.body.instructions.pop();
+            }
+            self.body.add_instruction(Line(line_number.0));
+        }
+        Ok(())
+    }
+
+    fn visit_expression_statement(
+        &mut self,
+        _line_number: &Rc<Position>,
+        expression: &mut ASTNode<Expression>,
+    ) -> Result<(), CompilerError> {
+        self.accept_for_effect(expression)
+    }
+
     fn visit_return(
         &mut self,
         _line_number: &Rc<Position>,
@@ -160,7 +446,8 @@ impl<'a> Visitor for TacVisitor<'a> {
         type_: &mut Type,
     ) -> Result<(), CompilerError> {
         expression.accept(self)?;
-        if *op == UnaryOperator::UnaryAdd {
+        if *op == UnaryOperator::UnaryAdd || *op == UnaryOperator::AddressOf {
+            // a function designator already evaluates to its address
             return Ok(());
         }
         let src = Rc::clone(&self.result);
@@ -208,6 +495,7 @@ impl<'a> Visitor for TacVisitor<'a> {
                 }); // goto false
 
                 let dest = Rc::new(Pseudoregister::new(self.body.current_offset, type_));
+                self.body.current_offset += 8;
                 self.body.add_instruction(StoreValueInstruction {
                     dest: Rc::clone(&dest),
                     src: Rc::new(Operand::Immediate(1u32.into())),
@@ -256,6 +544,7 @@ impl<'a> Visitor for TacVisitor<'a> {
                 }); // goto true
 
                 let dest = Rc::new(Pseudoregister::new(self.body.current_offset, type_));
+                self.body.current_offset += 8;
                 self.body.add_instruction(StoreValueInstruction {
                     dest: Rc::clone(&dest),
                     src: Rc::new(Operand::Immediate(0u32.into())),
@@ -311,37 +600,58 @@ impl<'a> Visitor for TacVisitor<'a> {
         if_false: &mut Box<ASTNode<Expression>>,
         type_: &mut Type,
     ) -> Result<(), CompilerError> {
+        let discard = std::mem::take(&mut self.discard_result);
         condition.accept(self)?;
         let else_label: Rc<String> = Rc::from(format!(".{}{}_else", self.name, self.label_count));
         self.label_count += 1;
         let end_label: Rc<String> = Rc::from(format!(".{}{}_end", self.name, self.label_count));
         self.label_count += 1;
-        let dest = Rc::new(Pseudoregister::new(self.body.current_offset, type_));
+        let dest = if discard {
+            None
+        } else {
+            let dest = Rc::new(Pseudoregister::new(self.body.current_offset, type_));
+            self.body.current_offset += 8;
+            Some(dest)
+        };
         self.body.add_instruction(JumpIfZero {
             // if false goto else
             label: Rc::clone(&else_label),
             operand: Rc::clone(&self.result),
         });
-        if_true.accept(self)?;
-        self.body.add_instruction(StoreValueInstruction {
-            dest: Rc::clone(&dest),
-            src: Rc::clone(&self.result),
-        });
+        // a ConditionValue middle leaves the condition's result in place, so it is used as is
+        match &dest {
+            Some(dest) => {
+                if_true.accept(self)?;
+                self.body.add_instruction(StoreValueInstruction {
+                    dest: Rc::clone(dest),
+                    src: Rc::clone(&self.result),
+                });
+            }
+            None => self.accept_for_effect(if_true)?,
+        }
         self.body.add_instruction(Jump {
             label: Rc::clone(&end_label),
         }); // goto end
         self.body.add_instruction(Label {
             label: Rc::clone(&else_label),
         }); // else
-        if_false.accept(self)?;
-        self.body.add_instruction(StoreValueInstruction {
-            dest: Rc::clone(&dest),
-            src: Rc::clone(&self.result),
-        });
+        match &dest {
+            Some(dest) => {
+                if_false.accept(self)?;
+                self.body.add_instruction(StoreValueInstruction {
+                    dest: Rc::clone(dest),
+                    src: Rc::clone(&self.result),
+                });
+            }
+            None => self.accept_for_effect(if_false)?,
+        }
         self.body.add_instruction(Label {
             label: Rc::clone(&end_label),
         });
-        self.result = Rc::from(Operand::Register((*dest).clone()));
+        self.result = match dest {
+            Some(dest) => Rc::from(Operand::Register((*dest).clone())),
+            None => Rc::new(Operand::None),
+        };
         Ok(())
     }
 
@@ -354,20 +664,34 @@ impl<'a> Visitor for TacVisitor<'a> {
         is_do_while: &mut bool,
     ) -> Result<(), CompilerError> {
         let start_label: Rc<String> = Rc::from(format!(".{}{}_start.loop", self.name, label));
+        let continue_label: Rc<String> = Rc::from(format!(".{}{}_continue.loop", self.name, label));
         let end_label: Rc<String> = Rc::from(format!(".{}{}_end.loop", self.name, label));
+        // a constant condition needs no test: the loop either always continues or never does
+        let constant = condition
+            .fold_constant()
+            .ok()
+            .flatten()
+            .filter(|_| self.body.fold_constants);
+        let always = constant.as_ref().is_some_and(|c| !c.is_zero());
+        let never = constant.as_ref().is_some_and(Const::is_zero);
         if !*is_do_while {
+            if never && !body.has_case_label() {
+                self.result = Rc::from(Operand::None);
+                return Ok(());
+            }
             self.body.add_instruction(
                 // start
                 Label {
                     label: Rc::clone(&start_label),
                 },
             );
-            condition.accept(self)?;
-            self.body.add_instruction(JumpIfZero {
+            self.body.add_instruction(Label {
+                label: Rc::clone(&continue_label),
+            }); // continue re-tests the condition
+            if !always {
                 // if false goto end
-                label: Rc::clone(&end_label),
-                operand: Rc::clone(&self.result),
-            });
+                self.jump_if_false(condition, &end_label)?;
+            }
             body.accept(self)?;
             self.body.add_instruction(Jump {
                 label: Rc::clone(&start_label),
@@ -381,14 +705,18 @@ impl<'a> Visitor for TacVisitor<'a> {
                 label: Rc::clone(&start_label),
             }); // start
             body.accept(self)?;
-            condition.accept(self)?;
-            self.body.add_instruction(JumpIfZero {
-                label: Rc::clone(&end_label),
-                operand: Rc::clone(&self.result),
-            }); // if false goto end
-            self.body.add_instruction(Jump {
-                label: Rc::clone(&start_label),
-            }); // goto start
+            self.body.add_instruction(Label {
+                label: Rc::clone(&continue_label),
+            }); // continue
+            if constant.is_none() {
+                // if false goto end
+                self.jump_if_false(condition, &end_label)?;
+            }
+            if !never {
+                self.body.add_instruction(Jump {
+                    label: Rc::clone(&start_label),
+                }); // goto start
+            }
             self.body.add_instruction(Label {
                 label: Rc::clone(&end_label),
             });
@@ -412,17 +740,10 @@ impl<'a> Visitor for TacVisitor<'a> {
         &mut self,
         _line_number: &Rc<Position>,
         label: &mut Rc<String>,
-        is_for: &mut bool,
     ) -> Result<(), CompilerError> {
-        if *is_for {
-            self.body.add_instruction(Jump {
-                label: format!(".{}{}_increment.loop", self.name, label).into(),
-            });
-        } else {
-            self.body.add_instruction(Jump {
-                label: format!(".{}{}_start.loop", self.name, label).into(),
-            });
-        }
+        self.body.add_instruction(Jump {
+            label: format!(".{}{}_continue.loop", self.name, label).into(),
+        });
         self.result = Rc::from(Operand::None);
         Ok(())
     }
@@ -438,29 +759,35 @@ impl<'a> Visitor for TacVisitor<'a> {
     ) -> Result<(), CompilerError> {
         let start_label: Rc<String> = Rc::from(format!(".{}{}_start.loop", self.name, label));
         let end_label: Rc<String> = Rc::from(format!(".{}{}_end.loop", self.name, label));
-        let increment_label: Rc<String> =
-            Rc::from(format!(".{}{}_increment.loop", self.name, label));
+        let continue_label: Rc<String> = Rc::from(format!(".{}{}_continue.loop", self.name, label));
         init.accept(self)?;
+        let constant = condition
+            .as_ref()
+            .and_then(|condition| condition.fold_constant().ok().flatten())
+            .filter(|_| self.body.fold_constants);
+        let always = constant.as_ref().is_some_and(|c| !c.is_zero());
+        if constant.is_some() && !always && !body.has_case_label() {
+            self.result = Rc::from(Operand::None);
+            return Ok(());
+        }
         self.body.add_instruction(
             // start
             Label {
                 label: Rc::clone(&start_label),
             },
         );
-        if let Some(condition) = condition {
-            condition.accept(self)?;
-            self.body.add_instruction(JumpIfZero {
-                // if false goto end
-                label: Rc::clone(&end_label),
-                operand: Rc::clone(&self.result),
-            });
+        if let Some(condition) = condition
+            && !always
+        {
+            // if false goto end
+            self.jump_if_false(condition, &end_label)?;
         }
         body.accept(self)?;
         self.body.add_instruction(Label {
-            label: Rc::clone(&increment_label),
-        }); // increment
+            label: Rc::clone(&continue_label),
+        }); // continue runs the increment, then re-tests the condition
         if let Some(increment) = increment {
-            increment.accept(self)?;
+            self.accept_for_effect(increment)?;
         }
         self.body.add_instruction(Jump {
             label: Rc::clone(&start_label),
@@ -472,6 +799,55 @@ impl<'a> Visitor for TacVisitor<'a> {
         Ok(())
     }
 
+    fn visit_switch(
+        &mut self,
+        _line_number: &Rc<Position>,
+        condition: &mut ASTNode<Expression>,
+        body: &mut Box<ASTNode<Statement>>,
+        label: &mut Rc<String>,
+        cases: &mut SwitchCases,
+    ) -> Result<(), CompilerError> {
+        let end_label: Rc<String> = Rc::from(format!(".{}{}_end.loop", self.name, label));
+        condition.accept(self)?;
+        let value = Rc::clone(&self.result);
+
+        let mut default_label = None;
+        let mut labelled = vec![];
+        for (case, case_label) in cases.iter() {
+            let case_label: Rc<String> = Rc::from(format!(".{}{}", self.name, case_label));
+            match case {
+                Some(case) => labelled.push((case.clone(), case_label)),
+                None => default_label = Some(case_label),
+            }
+        }
+        let default_label = default_label.unwrap_or_else(|| Rc::clone(&end_label));
+        if self.body.jump_tables && is_dense(&labelled) {
+            let table = Rc::from(format!(".{}{}_table", self.name, label));
+            self.make_jump_table(&value, &labelled, &default_label, table);
+        } else {
+            self.make_comparison_chain(&value, labelled, default_label);
+        }
+
+        // case bodies are laid out in source order, so a case without a break falls through
+        body.accept(self)?;
+        self.body.add_instruction(Label { label: end_label });
+        self.result = Rc::from(Operand::None);
+        Ok(())
+    }
+
+    fn visit_case(
+        &mut self,
+        _line_number: &Rc<Position>,
+        _value: &mut Option<ASTNode<Expression>>,
+        body: &mut Box<ASTNode<Statement>>,
+        label: &mut Rc<String>,
+    ) -> Result<(), CompilerError> {
+        self.body.add_instruction(Label {
+            label: format!(".{}{}", self.name, label).into(),
+        });
+        body.accept(self)
+    }
+
     fn visit_const(
         &mut self,
         _line_number: &Rc<Position>,
@@ -491,12 +867,24 @@ impl<'a> Visitor for TacVisitor<'a> {
         if let Some(pseudoregister) = self
             .body
             .variable_to_pseudoregister
-            .get(&identifier.to_string())
+            .get(identifier.as_str())
         {
             self.result = Rc::from(Operand::Register((**pseudoregister).clone()));
             return Ok(());
         }
 
+        // function designator
+        if self.functions_map.contains_key(identifier.as_ref()) {
+            let dest = Rc::new(Pseudoregister::new(self.body.current_offset, type_));
+            self.body.current_offset += 8;
+            self.body.add_instruction(LoadAddress {
+                dest: Rc::clone(&dest),
+                name: Rc::clone(identifier),
+            });
+            self.result = Rc::from(Operand::Register((*dest).clone()));
+            return Ok(());
+        }
+
         // static
         self.result = Rc::from(Operand::Register(Pseudoregister::Data(
             Rc::clone(&identifier),
@@ -512,27 +900,53 @@ impl<'a> Visitor for TacVisitor<'a> {
         arguments: &mut Box<Vec<ASTNode<Expression>>>,
         ret_type: &mut Type,
     ) -> Result<(), CompilerError> {
-        for i in (6..arguments.len()).rev() {
-            arguments[i].accept(self)?;
-            self.body
-                .add_instruction(PushArgument(Rc::clone(&self.result)));
+        if let Some(inline_body) = self
+            .functions_map
+            .get(identifier.as_ref())
+            .and_then(|function| function.inline_body.clone())
+            && !self.inlining.contains(identifier)
+        {
+            return self.inline_call(identifier, arguments, &inline_body);
+        }
+
+        // evaluate every argument before passing any, since one that makes a call of its
+        // own would clobber the registers and stack already holding earlier arguments
+        let mut values = vec![];
+        for argument in arguments.iter_mut() {
+            argument.accept(self)?;
+            values.push((Rc::clone(&self.result), argument.type_));
         }
 
-        for i in 0..arguments.len().min(6) {
-            let reg = &FIRST_SIX_REGISTERS[i];
-            arguments[i].accept(self)?;
+        // every stack argument takes a full 8-byte slot, and an odd count gets another
+        // slot of padding so %rsp stays 16-byte aligned at the call
+        let stack_arguments = arguments.len().saturating_sub(6) as i64;
+        let padding = stack_arguments % 2 * 8;
+        if padding != 0 {
+            self.body.add_instruction(AdjustStack(-padding));
+        }
+        for (value, _) in values.iter().skip(6).rev() {
+            self.body.add_instruction(PushArgument(Rc::clone(value)));
+        }
+        for ((value, type_), reg) in values.into_iter().zip(FIRST_SIX_REGISTERS.iter()) {
             self.body.add_instruction(StoreValueInstruction {
-                dest: Rc::from(Pseudoregister::Register(reg.clone(), arguments[i].type_)),
-                src: Rc::clone(&self.result),
+                dest: Rc::from(Pseudoregister::Register(reg.clone(), type_)),
+                src: value,
             });
         }
 
+        if !self
+            .functions_map
+            .get(identifier.as_ref())
+            .is_some_and(|function| function.defined)
+        {
+            self.body.external_functions.insert(Rc::clone(identifier));
+        }
         self.body
             .add_instruction(FunctionCall(Rc::clone(&identifier)));
 
-        if arguments.len() > 6 {
-            let stack_cleanup_size = (arguments.len() - 6) * 8; // 8 bytes per arg
-            self.body.add_instruction(AdjustStack(stack_cleanup_size));
+        if stack_arguments > 0 {
+            self.body
+                .add_instruction(AdjustStack(stack_arguments * 8 + padding));
         }
 
         let result_register = Rc::new(Pseudoregister::new(self.body.current_offset, ret_type));
@@ -578,9 +992,9 @@ impl<'a> Visitor for TacVisitor<'a> {
                 self.body.current_offset += 8;
                 Ok(())
             }
-            _ => Err(SemanticError(format!(
-                "Expected lvalue at {:?}",
-                line_number
+            _ => Err(SemanticError(Diagnostic::at(
+                format!("Expected lvalue at {:?}", line_number),
+                line_number,
             ))),
         }
     }
@@ -601,9 +1015,9 @@ impl<'a> Visitor for TacVisitor<'a> {
         let dest = match &*self.result {
             Operand::Register(pseudoregister) => Rc::from((*pseudoregister).clone()),
             _ => {
-                return Err(SemanticError(format!(
-                    "Expected lvalue at {:?}",
-                    line_number
+                return Err(SemanticError(Diagnostic::at(
+                    format!("Expected lvalue at {:?}", line_number),
+                    line_number,
                 )));
             }
         };
@@ -637,33 +1051,25 @@ impl<'a> Visitor for TacVisitor<'a> {
     ) -> Result<(), CompilerError> {
         match if_false {
             None => {
-                condition.accept(self)?;
                 let end_label: Rc<String> =
                     Rc::from(format!(".{}{}_end", self.name, self.label_count));
                 self.label_count += 1;
-                self.body.add_instruction(JumpIfZero {
-                    // if false goto end
-                    label: Rc::clone(&end_label),
-                    operand: Rc::clone(&self.result),
-                });
+                // if false goto end
+                self.jump_if_false(condition, &end_label)?;
                 if_true.accept(self)?;
                 self.body.add_instruction(Label {
                     label: Rc::clone(&end_label),
                 });
             }
             Some(if_false) => {
-                condition.accept(self)?;
                 let else_label: Rc<String> =
                     Rc::from(format!(".{}{}_else", self.name, self.label_count));
                 self.label_count += 1;
                 let end_label: Rc<String> =
                     Rc::from(format!(".{}{}_end", self.name, self.label_count));
                 self.label_count += 1;
-                self.body.add_instruction(JumpIfZero {
-                    // if false goto else
-                    label: Rc::clone(&else_label),
-                    operand: Rc::clone(&self.result),
-                });
+                // if false goto else
+                self.jump_if_false(condition, &else_label)?;
                 if_true.accept(self)?;
 
                 self.body.add_instruction(Jump {
@@ -709,4 +1115,35 @@ impl<'a> Visitor for TacVisitor<'a> {
         }
         Ok(())
     }
+
+    fn visit_compound_literal(
+        &mut self,
+        _line_number: &Rc<Position>,
+        literal_type: &mut Type,
+        init: &mut Box<ASTNode<Expression>>,
+        _type_: &mut Type,
+    ) -> Result<(), CompilerError> {
+        init.accept(self)?;
+        // the literal is its own object, so later writes to it can't touch `init`
+        let object = Rc::new(Pseudoregister::new(self.body.current_offset, literal_type));
+        self.body.current_offset += 8;
+        self.body.add_instruction(StoreValueInstruction {
+            dest: Rc::clone(&object),
+            src: Rc::clone(&self.result),
+        });
+        self.result = Rc::from(Operand::Register((*object).clone()));
+        Ok(())
+    }
+
+    fn visit_size_of(
+        &mut self,
+        _line_number: &Rc<Position>,
+        exp: &mut Box<ASTNode<Expression>>,
+        _type_: &mut Type,
+    ) -> Result<(), CompilerError> {
+        // only the operand's type matters, so none of its side effects happen
+        let size = Const::ConstULong(exp.type_.size() as u64);
+        self.result = Rc::from(Operand::Immediate(size));
+        Ok(())
+    }
 }
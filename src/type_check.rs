@@ -1,16 +1,23 @@
 use crate::CompilerError;
-use crate::CompilerError::SemanticError;
-use crate::ast::{ASTNode, Declaration, Expression, FunAttr, StaticAttr, Visitor};
+use crate::CompilerError::{SemanticError, TypeError};
+use crate::ast::{
+    ASTNode, Declaration, Expression, FunAttr, Statement, StaticAttr, SwitchCases, Visitor,
+};
 use crate::common::{Const, Position};
+use crate::errors::Diagnostic;
 use crate::lexer::{BinaryOperator, Type, UnaryOperator};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 pub(crate) struct TypeCheckVisitor<'map> {
     variables_map: HashMap<String, Type>,
+    /// Local variables declared `const`
+    const_variables: HashSet<String>,
     functions_map: &'map HashMap<String, FunAttr>,
     global_variables_map: &'map HashMap<String, StaticAttr>,
     current_return_type: Type,
+    /// Constants changed by the implicit conversions of the function
+    pub(crate) warnings: Vec<Diagnostic>,
 }
 
 /*
@@ -27,7 +34,7 @@ get_common_type(type1, type2):
  else:
     return type2
  */
-fn get_common_type(type1: &Type, type2: &Type) -> Type {
+pub(crate) fn get_common_type(type1: &Type, type2: &Type) -> Type {
     if type1 == type2 {
         return *type1;
     }
@@ -49,6 +56,24 @@ fn get_common_type(type1: &Type, type2: &Type) -> Type {
     }
 }
 
+/// Warning for a constant that an implicit conversion to a narrower `t` changes.
+pub(crate) fn truncation_warning(
+    value: &Const,
+    t: &Type,
+    position: &Position,
+) -> Option<Diagnostic> {
+    let converted = value.convert_to(t);
+    (t.size() < value.size() && converted.value() != value.value()).then(|| {
+        Diagnostic::at(
+            format!(
+                "Implicit conversion of {} to {:?} truncates it to {} at {:?}",
+                value, t, converted, position
+            ),
+            position,
+        )
+    })
+}
+
 fn convert_to(line_number: &Rc<Position>, e: &mut ASTNode<Expression>, t: &Type) {
     if e.type_ == *t {
         return;
@@ -73,15 +98,93 @@ fn convert_to(line_number: &Rc<Position>, e: &mut ASTNode<Expression>, t: &Type)
 }
 
 impl<'map> TypeCheckVisitor<'map> {
+    fn is_function_designator(&self, expression: &ASTNode<Expression>) -> bool {
+        match &expression.kind {
+            Expression::Variable(name) => self.functions_map.contains_key(name.as_ref()),
+            _ => false,
+        }
+    }
+
+    /// `expression` is the address of a function, by its name or through `&`.
+    fn is_function_address(&self, expression: &ASTNode<Expression>) -> bool {
+        match &expression.kind {
+            Expression::Unary(UnaryOperator::AddressOf, operand) => {
+                self.is_function_designator(operand)
+            }
+            _ => self.is_function_designator(expression),
+        }
+    }
+
+    /*
+    Conversions of assignment, initialization, return and argument passing.
+    Only a cast may cut a function address down to less than 64 bits, and a constant
+    that doesn't fit its narrower target is truncated with a warning.
+     */
+    fn check_implicit_conversion(
+        &mut self,
+        line_number: &Rc<Position>,
+        expression: &ASTNode<Expression>,
+        t: &Type,
+    ) -> Result<(), CompilerError> {
+        if t.size() < 8 && self.is_function_address(expression) {
+            return Err(TypeError(Diagnostic::at(
+                format!(
+                    "Implicit conversion of function address {:?} to {:?} at {:?}",
+                    expression.kind, t, line_number
+                ),
+                line_number,
+            )));
+        }
+        if let Ok(Some(value)) = expression.fold_constant() {
+            self.warnings
+                .extend(truncation_warning(&value, t, line_number));
+        }
+        Ok(())
+    }
+
+    fn check_assignable(
+        &self,
+        line_number: &Rc<Position>,
+        expression: &ASTNode<Expression>,
+    ) -> Result<(), CompilerError> {
+        if self.is_function_designator(expression) {
+            return Err(TypeError(Diagnostic::at(
+                format!(
+                    "Function {:?} is not assignable at {:?}",
+                    expression.kind, line_number
+                ),
+                line_number,
+            )));
+        }
+        if let Expression::Variable(name) = &expression.kind
+            && (self.const_variables.contains(name.as_ref())
+                || self
+                    .global_variables_map
+                    .get(name.as_ref())
+                    .is_some_and(|attr| attr.constant))
+        {
+            return Err(TypeError(Diagnostic::at(
+                format!(
+                    "Cannot assign to const variable {} at {:?}",
+                    name, line_number
+                ),
+                line_number,
+            )));
+        }
+        Ok(())
+    }
+
     pub(crate) fn new(
         functions_map: &'map HashMap<String, FunAttr>,
         global_variables_map: &'map HashMap<String, StaticAttr>,
     ) -> Self {
         Self {
             variables_map: HashMap::new(),
+            const_variables: HashSet::new(),
             functions_map,
             global_variables_map,
             current_return_type: Type::Void,
+            warnings: vec![],
         }
     }
 }
@@ -95,20 +198,44 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
         match declaration {
             Declaration::VariableDeclaration(decl) => {
                 if decl.var_type == Type::Void {
-                    return Err(SemanticError(format!(
-                        "Cannot declare variable {} of type 'void' at {:?}",
-                        decl.name, line_number
+                    return Err(TypeError(Diagnostic::at(
+                        format!(
+                            "Cannot declare variable {} of type 'void' at {:?}",
+                            decl.name, line_number
+                        ),
+                        line_number,
                     )));
                 }
                 self.variables_map
                     .insert(decl.name.to_string(), decl.var_type);
+                if decl.constant {
+                    self.const_variables.insert(decl.name.to_string());
+                }
                 if let Some(init) = &mut decl.init {
                     init.accept(self)?;
                     let common = get_common_type(&decl.var_type, &init.type_);
+                    self.check_implicit_conversion(line_number, init, &decl.var_type)?;
                     convert_to(line_number, init, &common);
                 }
                 Ok(())
             }
+            Declaration::StaticAssert(assertion) => {
+                assertion.condition.accept(self)?;
+                match assertion.condition.fold_constant()? {
+                    Some(value) if value.is_zero() => Err(SemanticError(Diagnostic::at(
+                        format!(
+                            "Static assertion failed: {} at {:?}",
+                            assertion.message, line_number
+                        ),
+                        line_number,
+                    ))),
+                    Some(_) => Ok(()),
+                    None => Err(SemanticError(Diagnostic::at(
+                        format!("Non-constant static assertion at {:?}", line_number),
+                        line_number,
+                    ))),
+                }
+            }
             Declaration::FunctionDeclaration(decl) => {
                 for (param_name, param_type) in decl.params.iter().zip(decl.func_type.params.iter())
                 {
@@ -133,8 +260,15 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
         type_: &mut Type,
     ) -> Result<(), CompilerError> {
         left.accept(self)?;
+        if let Expression::Binary { left: target, .. } = &mut right.kind
+            && let Expression::AssignmentTarget = target.kind
+        {
+            target.type_ = left.type_;
+        }
         right.accept(self)?;
+        self.check_assignable(line_number, left)?;
         let left_type = &left.type_;
+        self.check_implicit_conversion(line_number, right, left_type)?;
         convert_to(line_number, right, left_type);
         *type_ = left_type.clone();
         Ok(())
@@ -146,18 +280,29 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
         expression: &mut ASTNode<Expression>,
     ) -> Result<(), CompilerError> {
         expression.accept(self)?;
-        convert_to(line_number, expression, &self.current_return_type);
+        let return_type = self.current_return_type;
+        self.check_implicit_conversion(line_number, expression, &return_type)?;
+        convert_to(line_number, expression, &return_type);
         Ok(())
     }
 
     fn visit_unary(
         &mut self,
-        _line_number: &Rc<Position>,
+        line_number: &Rc<Position>,
         op: &mut UnaryOperator,
         expression: &mut Box<ASTNode<Expression>>,
         type_: &mut Type,
     ) -> Result<(), CompilerError> {
         expression.accept(self)?;
+        if *op == UnaryOperator::AddressOf && !self.is_function_designator(expression) {
+            return Err(TypeError(Diagnostic::at(
+                format!(
+                    "Cannot take the address of {:?} at {:?}",
+                    expression.kind, line_number
+                ),
+                line_number,
+            )));
+        }
         *type_ = match op {
             UnaryOperator::LogicalNot => Type::Int,
             _ => expression.type_,
@@ -206,6 +351,9 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
         type_: &mut Type,
     ) -> Result<(), CompilerError> {
         condition.accept(self)?;
+        if let Expression::ConditionValue = if_true.kind {
+            if_true.type_ = condition.type_;
+        }
         if_true.accept(self)?;
         if_false.accept(self)?;
         let common_type = get_common_type(&if_true.type_, &if_false.type_);
@@ -215,6 +363,49 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
         Ok(())
     }
 
+    fn visit_compound_literal(
+        &mut self,
+        line_number: &Rc<Position>,
+        literal_type: &mut Type,
+        init: &mut Box<ASTNode<Expression>>,
+        type_: &mut Type,
+    ) -> Result<(), CompilerError> {
+        init.accept(self)?;
+        convert_to(line_number, init, literal_type);
+        *type_ = *literal_type;
+        Ok(())
+    }
+
+    fn visit_switch(
+        &mut self,
+        line_number: &Rc<Position>,
+        condition: &mut ASTNode<Expression>,
+        body: &mut Box<ASTNode<Statement>>,
+        _label: &mut Rc<String>,
+        cases: &mut SwitchCases,
+    ) -> Result<(), CompilerError> {
+        condition.accept(self)?;
+        // case values compare at the type of the controlling expression
+        for (value, _) in cases.iter_mut() {
+            if let Some(value) = value {
+                *value = value.convert_to(&condition.type_);
+            }
+        }
+        for (i, (value, _)) in cases.iter().enumerate() {
+            if let Some(value) = value
+                && cases[..i]
+                    .iter()
+                    .any(|(other, _)| other.as_ref() == Some(value))
+            {
+                return Err(SemanticError(Diagnostic::at(
+                    format!("Duplicate case {} in switch at {:?}", value, line_number),
+                    line_number,
+                )));
+            }
+        }
+        body.accept(self)
+    }
+
     fn visit_const(
         &mut self,
         _line_number: &Rc<Position>,
@@ -238,6 +429,9 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
     ) -> Result<(), CompilerError> {
         if let Some(attr) = self.global_variables_map.get(&identifier.to_string()) {
             *node = attr.type_;
+        } else if self.functions_map.contains_key(identifier.as_ref()) {
+            // function designators decay to their address
+            *node = Type::ULong;
         } else {
             *node = self
                 .variables_map
@@ -263,16 +457,20 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
                 .func_type,
         );
         if func_type.params.len() != arguments.len() {
-            return Err(SemanticError(format!(
-                "Function {} called with {} arguments but expected {} at {:?}",
-                identifier,
-                arguments.len(),
-                func_type.params.len(),
-                line_number
+            return Err(TypeError(Diagnostic::at(
+                format!(
+                    "Function {} called with {} arguments but expected {} at {:?}",
+                    identifier,
+                    arguments.len(),
+                    func_type.params.len(),
+                    line_number
+                ),
+                line_number,
             )));
         }
         for (arg, param_type) in arguments.iter_mut().zip(func_type.params.iter()) {
             arg.accept(self)?;
+            self.check_implicit_conversion(line_number, arg, param_type)?;
             convert_to(line_number, arg, param_type);
         }
         *ret_type = func_type.ret.clone();
@@ -281,24 +479,26 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
 
     fn visit_prefix(
         &mut self,
-        _line_number: &Rc<Position>,
+        line_number: &Rc<Position>,
         variable: &mut Box<ASTNode<Expression>>,
         _operator: &mut UnaryOperator,
         type_: &mut Type,
     ) -> Result<(), CompilerError> {
         variable.accept(self)?;
+        self.check_assignable(line_number, variable)?;
         *type_ = variable.type_;
         Ok(())
     }
 
     fn visit_postfix(
         &mut self,
-        _line_number: &Rc<Position>,
+        line_number: &Rc<Position>,
         variable: &mut Box<ASTNode<Expression>>,
         _operator: &mut UnaryOperator,
         type_: &mut Type,
     ) -> Result<(), CompilerError> {
         variable.accept(self)?;
+        self.check_assignable(line_number, variable)?;
         *type_ = variable.type_;
         Ok(())
     }
@@ -314,4 +514,15 @@ impl<'map> Visitor for TypeCheckVisitor<'map> {
         *type_ = target_type.clone();
         Ok(())
     }
+
+    fn visit_size_of(
+        &mut self,
+        _line_number: &Rc<Position>,
+        exp: &mut Box<ASTNode<Expression>>,
+        type_: &mut Type,
+    ) -> Result<(), CompilerError> {
+        exp.accept(self)?;
+        *type_ = Type::ULong;
+        Ok(())
+    }
 }
@@ -1,10 +1,10 @@
 use crate::ast::{
     ASTNode, Block, Declaration, Expression, ForInit, FunAttr, InitialValue, Statement, StaticAttr,
-    VariableDeclaration, Visitor,
+    SwitchCases, VariableDeclaration, Visitor,
 };
-use crate::common::Position;
-use crate::errors::CompilerError;
+use crate::common::{Const, Interner, Position};
 use crate::errors::CompilerError::SemanticError;
+use crate::errors::{CompilerError, Diagnostic};
 use crate::lexer::{StorageClass, Type};
 use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
@@ -18,9 +18,16 @@ struct ScopeEntry {
 
 pub(crate) struct VariableResolutionVisitor<'map> {
     layer: i32,
+    declaration_count: i32,
     function: Rc<String>,
-    variable_scopes: HashMap<String, VecDeque<ScopeEntry>>,
-    loop_labels: VecDeque<(Rc<String>, bool)>,
+    variable_scopes: HashMap<Rc<str>, VecDeque<ScopeEntry>>,
+    /// Keys of `variable_scopes`
+    names: Interner,
+    loop_labels: VecDeque<Rc<String>>,
+    /// Loops and switches a `break` can leave, innermost last
+    break_labels: VecDeque<Rc<String>>,
+    /// Label and collected cases of each enclosing `switch`, innermost last
+    switches: VecDeque<(Rc<String>, SwitchCases)>,
     functions_map: &'map HashMap<String, FunAttr>,
     global_variables_map: &'map mut HashMap<String, StaticAttr>,
 }
@@ -33,14 +40,29 @@ impl<'map> VariableResolutionVisitor<'map> {
     ) -> Self {
         Self {
             layer: 0,
+            declaration_count: 0,
             function,
             variable_scopes: HashMap::new(),
+            names: Interner::default(),
             loop_labels: VecDeque::new(),
+            break_labels: VecDeque::new(),
+            switches: VecDeque::new(),
             functions_map,
             global_variables_map,
         }
     }
 
+    /*
+    Every declaration gets its own name, so shadowing and sibling blocks never share storage
+     */
+    fn make_unique_name(&mut self, original_name: &str, separator: &str) -> Rc<String> {
+        self.declaration_count += 1;
+        Rc::new(format!(
+            "{}{}{}{}{}",
+            self.function, separator, original_name, separator, self.declaration_count
+        ))
+    }
+
     fn pop_stack(&mut self) {
         for scopes in self.variable_scopes.values_mut() {
             while !scopes.is_empty() && scopes.back().unwrap().layer == self.layer {
@@ -77,13 +99,11 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
     ) -> Result<(), CompilerError> {
         match declaration {
             Declaration::VariableDeclaration(d) => self.handle_variable_declaration(line_number, d),
+            Declaration::StaticAssert(assertion) => assertion.condition.accept(self),
             Declaration::FunctionDeclaration(f) => {
-                for param in &mut f.params {
-                    let original_name = param.clone();
-                    let unique_name = Rc::new(format!(
-                        "{}::{}::{}",
-                        self.function, original_name, self.layer
-                    ));
+                for param in f.params.iter_mut().filter(|param| !param.is_empty()) {
+                    let original_name = self.names.intern(param);
+                    let unique_name = self.make_unique_name(&original_name, "::");
 
                     let entry = ScopeEntry {
                         layer: self.layer,
@@ -135,10 +155,12 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
         label: &mut Rc<String>,
         _is_do_while: &mut bool,
     ) -> Result<(), CompilerError> {
-        self.loop_labels.push_back((Rc::clone(&label), false));
+        self.loop_labels.push_back(Rc::clone(label));
+        self.break_labels.push_back(Rc::clone(label));
         condition.accept(self)?;
         body.accept(self)?;
         self.loop_labels.pop_back();
+        self.break_labels.pop_back();
         Ok(())
     }
 
@@ -147,13 +169,13 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
         line_number: &Rc<Position>,
         label: &mut Rc<String>,
     ) -> Result<(), CompilerError> {
-        if self.loop_labels.is_empty() {
-            Err(SemanticError(format!(
-                "Break outside loop at {:?}",
-                line_number
+        if self.break_labels.is_empty() {
+            Err(SemanticError(Diagnostic::at(
+                format!("Break outside loop or switch at {:?}", line_number),
+                line_number,
             )))
         } else {
-            *label = Rc::clone(&self.loop_labels.back().unwrap().0);
+            *label = Rc::clone(self.break_labels.back().unwrap());
             Ok(())
         }
     }
@@ -162,16 +184,14 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
         &mut self,
         line_number: &Rc<Position>,
         label: &mut Rc<String>,
-        is_for: &mut bool,
     ) -> Result<(), CompilerError> {
         if self.loop_labels.is_empty() {
-            Err(SemanticError(format!(
-                "Continue outside loop at {:?}",
-                line_number
+            Err(SemanticError(Diagnostic::at(
+                format!("Continue outside loop at {:?}", line_number),
+                line_number,
             )))
         } else {
-            *label = Rc::clone(&self.loop_labels.back().unwrap().0);
-            *is_for = self.loop_labels.back().unwrap().1;
+            *label = Rc::clone(self.loop_labels.back().unwrap());
             Ok(())
         }
     }
@@ -190,7 +210,8 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
             self.layer += 1;
             init.accept(self)?;
         }
-        self.loop_labels.push_back((Rc::clone(&label), true));
+        self.loop_labels.push_back(Rc::clone(label));
+        self.break_labels.push_back(Rc::clone(label));
         if let Some(condition) = condition {
             condition.accept(self)?;
         }
@@ -200,6 +221,7 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
         body.accept(self)?;
 
         self.loop_labels.pop_back();
+        self.break_labels.pop_back();
         if !matches!(init.kind, ForInit::InitExp(None)) {
             self.pop_stack();
             self.layer -= 1;
@@ -207,23 +229,80 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
         Ok(())
     }
 
+    fn visit_switch(
+        &mut self,
+        _line_number: &Rc<Position>,
+        condition: &mut ASTNode<Expression>,
+        body: &mut Box<ASTNode<Statement>>,
+        label: &mut Rc<String>,
+        cases: &mut SwitchCases,
+    ) -> Result<(), CompilerError> {
+        condition.accept(self)?;
+        self.break_labels.push_back(Rc::clone(label));
+        self.switches.push_back((Rc::clone(label), vec![]));
+        body.accept(self)?;
+        self.break_labels.pop_back();
+        *cases = self.switches.pop_back().unwrap().1;
+        Ok(())
+    }
+
+    fn visit_case(
+        &mut self,
+        line_number: &Rc<Position>,
+        value: &mut Option<ASTNode<Expression>>,
+        body: &mut Box<ASTNode<Statement>>,
+        label: &mut Rc<String>,
+    ) -> Result<(), CompilerError> {
+        let Some((switch_label, cases)) = self.switches.back_mut() else {
+            return Err(SemanticError(Diagnostic::at(
+                format!("Case outside switch at {:?}", line_number),
+                line_number,
+            )));
+        };
+        let case_value = match value {
+            Some(value) => match value.fold_constant()? {
+                Some(c) => Some(c),
+                None => {
+                    return Err(SemanticError(Diagnostic::at(
+                        format!("Non-constant case value at {:?}", line_number),
+                        line_number,
+                    )));
+                }
+            },
+            None if cases.iter().any(|(value, _)| value.is_none()) => {
+                return Err(SemanticError(Diagnostic::at(
+                    format!("Duplicate default in switch at {:?}", line_number),
+                    line_number,
+                )));
+            }
+            None => None,
+        };
+        *label = Rc::from(match case_value {
+            Some(_) => format!("{}_case{}", switch_label, cases.len()),
+            None => format!("{}_default", switch_label),
+        });
+        cases.push((case_value, Rc::clone(label)));
+        body.accept(self)
+    }
+
     fn visit_variable(
         &mut self,
         line_number: &Rc<Position>,
         identifier: &mut Rc<String>,
         _node: &mut Type,
     ) -> Result<(), CompilerError> {
-        let original_name = identifier.as_ref().to_string();
-
         // Try to resolve the variable
-        if let Some(resolved_name) = self.resolve_variable(&original_name) {
+        if let Some(resolved_name) = self.resolve_variable(identifier) {
             *identifier = resolved_name;
             Ok(())
+        } else if self.functions_map.contains_key(identifier.as_str()) {
+            // function designator, resolved to its address later
+            Ok(())
         } else {
             // Variable not found in any scope
-            Err(SemanticError(format!(
-                "Undefined variable {} at {:?}",
-                original_name, line_number
+            Err(SemanticError(Diagnostic::at(
+                format!("Undefined variable {} at {:?}", identifier, line_number),
+                line_number,
             )))
         }
     }
@@ -235,15 +314,17 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
         arguments: &mut Box<Vec<ASTNode<Expression>>>,
         _ret_type: &mut Type,
     ) -> Result<(), CompilerError> {
-        let original_name = identifier.as_ref().to_string();
-        if let Some(func) = self.functions_map.get(&original_name) {
+        if let Some(func) = self.functions_map.get(identifier.as_str()) {
             if arguments.len() != (*func.func_type).params.len() {
-                return Err(SemanticError(format!(
-                    "Function {} called with {} parameters but expected {} at {:?}",
-                    original_name,
-                    arguments.len(),
-                    (*func.func_type).params.len(),
-                    line_number
+                return Err(SemanticError(Diagnostic::at(
+                    format!(
+                        "Function {} called with {} parameters but expected {} at {:?}",
+                        identifier,
+                        arguments.len(),
+                        (*func.func_type).params.len(),
+                        line_number
+                    ),
+                    line_number,
                 )));
             }
             for arg in (*arguments).iter_mut() {
@@ -251,9 +332,12 @@ impl<'map> Visitor for VariableResolutionVisitor<'map> {
             }
             Ok(())
         } else {
-            Err(SemanticError(format!(
-                "Undefined function {} called at {:?}",
-                original_name, line_number
+            Err(SemanticError(Diagnostic::at(
+                format!(
+                    "Undefined function {} called at {:?}",
+                    identifier, line_number
+                ),
+                line_number,
             )))
         }
     }
@@ -265,49 +349,59 @@ impl<'map> VariableResolutionVisitor<'map> {
         line_number: &Rc<Position>,
         d: &mut VariableDeclaration,
     ) -> Result<(), CompilerError> {
-        let original_name = d.name.as_ref().to_string();
-
-        if self.functions_map.contains_key(&original_name) {
-            return Err(SemanticError(format!(
-                "Function {} redeclared as variable at {:?}",
-                original_name, line_number
+        let original_name = self.names.intern(&d.name);
+
+        if self.functions_map.contains_key(original_name.as_ref()) {
+            return Err(SemanticError(Diagnostic::at(
+                format!(
+                    "Function {} redeclared as variable at {:?}",
+                    original_name, line_number
+                ),
+                line_number,
             )));
         }
 
         let scopes = self
             .variable_scopes
-            .entry(original_name.clone())
+            .entry(Rc::clone(&original_name))
             .or_insert_with(VecDeque::new);
 
         if !scopes.is_empty() && scopes.back().unwrap().layer == self.layer {
-            return Err(SemanticError(format!(
-                "Duplicate variable declaration {} at {:?}",
-                original_name, line_number
+            return Err(SemanticError(Diagnostic::at(
+                format!(
+                    "Duplicate variable declaration {} at {:?}",
+                    original_name, line_number
+                ),
+                line_number,
             )));
         }
         match d.storage_class {
             Some(StorageClass::Extern) => {
                 if d.init.is_some() {
-                    return Err(SemanticError(format!(
-                        "Extern variable cannot be initialized at {:?}",
-                        line_number
+                    return Err(SemanticError(Diagnostic::at(
+                        format!("Extern variable cannot be initialized at {:?}", line_number),
+                        line_number,
                     )));
                 }
 
-                if let Some(attr) = self.global_variables_map.get(&original_name) {
-                    if attr.type_ != d.var_type {
-                        return Err(SemanticError(format!(
-                            "Extern variable {} redeclared with incompatible type at {:?}",
-                            d.name, line_number
+                if let Some(attr) = self.global_variables_map.get(original_name.as_ref()) {
+                    if attr.type_ != d.var_type || attr.constant != d.constant {
+                        return Err(SemanticError(Diagnostic::at(
+                            format!(
+                                "Extern variable {} redeclared with incompatible type at {:?}",
+                                d.name, line_number
+                            ),
+                            line_number,
                         )));
                     }
                 } else {
                     self.global_variables_map.insert(
-                        original_name.clone(),
+                        original_name.to_string(),
                         StaticAttr {
                             init: InitialValue::NoInitializer,
                             global: true,
-                            type_: Type::Int,
+                            type_: d.var_type,
+                            constant: d.constant,
                         },
                     );
                 }
@@ -328,19 +422,22 @@ impl<'map> VariableResolutionVisitor<'map> {
 
             Some(StorageClass::Static) => {
                 let initial_value = if let Some(init) = &d.init {
-                    if let Expression::Constant(i) = &init.kind {
-                        InitialValue::Initial(i.clone())
+                    if let Some(i) = init.fold_constant()? {
+                        InitialValue::Initial(i.convert_to(&d.var_type))
                     } else {
-                        return Err(SemanticError(format!(
-                            "Non-constant initializer of static variable {} at {:?}",
-                            original_name, line_number
+                        return Err(SemanticError(Diagnostic::at(
+                            format!(
+                                "Non-constant initializer of static variable {} at {:?}",
+                                original_name, line_number
+                            ),
+                            line_number,
                         )));
                     }
                 } else {
-                    InitialValue::Initial(0u32.into())
+                    InitialValue::Initial(Const::ConstInt(0).convert_to(&d.var_type))
                 };
 
-                let unique_name = Rc::from(format!("{}.{}", self.function, d.name));
+                let unique_name = self.make_unique_name(&original_name, ".");
                 d.name = Rc::clone(&unique_name);
 
                 self.global_variables_map.insert(
@@ -348,7 +445,8 @@ impl<'map> VariableResolutionVisitor<'map> {
                     StaticAttr {
                         init: initial_value,
                         global: false,
-                        type_: Type::Int,
+                        type_: d.var_type,
+                        constant: d.constant,
                     },
                 );
 
@@ -366,11 +464,8 @@ impl<'map> VariableResolutionVisitor<'map> {
                 Ok(())
             }
 
-            None => {
-                let unique_name = Rc::new(format!(
-                    "{}::{}::{}",
-                    self.function, original_name, self.layer
-                ));
+            Some(StorageClass::Auto | StorageClass::Register) | None => {
+                let unique_name = self.make_unique_name(&original_name, "::");
 
                 d.name = Rc::clone(&unique_name);
 
@@ -379,7 +474,10 @@ impl<'map> VariableResolutionVisitor<'map> {
                     is_extern: false,
                     unique_name,
                 };
-                scopes.push_back(entry);
+                self.variable_scopes
+                    .entry(original_name)
+                    .or_default()
+                    .push_back(entry);
 
                 if let Some(expr) = &mut d.init {
                     expr.accept(self)?;
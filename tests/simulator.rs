@@ -1,100 +1,221 @@
 use rstest::*;
 // tests/test_helpers/simulator.rs
+use compiler::{CompilerError, compile};
 use regex::Regex;
 use std::ffi::CString;
+#[cfg(not(windows))]
+use std::ffi::{c_char, c_int, c_void};
 use std::fs;
+#[cfg(not(windows))]
+use std::io::Write;
 use std::io::{self};
+#[cfg(not(windows))]
+use std::os::unix::process::ExitStatusExt;
 use std::path::PathBuf;
 use std::process::Command;
-// Windows-specific imports
-use compiler::{CompilerError, compile};
+#[cfg(not(windows))]
+use std::process::Stdio;
 use uuid::Uuid;
+// Windows-specific imports
+#[cfg(windows)]
 use winapi::shared::minwindef::{DWORD, HMODULE};
+#[cfg(windows)]
 use winapi::um::errhandlingapi::GetLastError;
+#[cfg(windows)]
 use winapi::um::fileapi::GetTempPathA;
+#[cfg(windows)]
 use winapi::um::libloaderapi::{FreeLibrary, GetProcAddress, LoadLibraryA};
-use winapi::um::processthreadsapi::GetCurrentProcessId;
 
+#[cfg(windows)]
 const MAX_PATH: usize = 260; // Windows MAX_PATH constant
 
+/// Extension of the linked program: a DLL loaded in-process on Windows, an executable
+/// run as a child process everywhere else.
+#[cfg(windows)]
+const BINARY_EXTENSION: &str = "dll";
+#[cfg(not(windows))]
+const BINARY_EXTENSION: &str = "out";
+
+/// Entry point linked against the program on non-Windows hosts. It prints the result of
+/// `_runAsm` on its own line so the full `int` survives, unlike an 8-bit exit status.
+/// The weak reference lets a program without `main` link and then fail when run, as
+/// `GetProcAddress` does for the DLL. The program's `main` receives the driver's own
+/// `argc` and `argv`.
+#[cfg(not(windows))]
+const DRIVER_SOURCE: &str = r#"
+#include <stdio.h>
+int _runAsm(int argc, char **argv) __attribute__((weak));
+int main(int argc, char **argv) {
+    if (!_runAsm) {
+        fputs("Missing _runAsm entry point\n", stderr);
+        return 1;
+    }
+    printf("\n%d\n", _runAsm(argc, argv));
+    return 0;
+}
+"#;
+
+#[cfg(not(windows))]
+const RTLD_NOW: c_int = 2;
+
+// The dynamic loader, for programs that call host functions and so must run in-process
+#[cfg(not(windows))]
+unsafe extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlclose(handle: *mut c_void) -> c_int;
+}
+
+/// Set to `1` to keep the `.s`, `.o` and `.dll` (or executable) files of every simulator run.
+pub const KEEP_ARTIFACTS_VAR: &str = "COMPILER_KEEP_ASM";
+
+/// Assembles, links and runs compiled programs from temporary files.
+/// On Windows the program is linked into a DLL and called in-process; on other hosts it is
+/// linked into an executable and run as a child process, so a crash such as SIGSEGV or SIGFPE
+/// is reported as an `Err` from `execute` instead of taking down the test. Programs that call
+/// functions added with `register_symbol` are loaded in-process on every host.
+/// The files are deleted on drop unless `keep_artifacts` is enabled, either with
+/// `Simulator::keep_artifacts(true)` or by setting `COMPILER_KEEP_ASM=1`.
 #[derive(Debug)]
 pub struct Simulator {
     temp_asm_file: PathBuf,
     temp_obj_file: PathBuf,
-    temp_dll_file: PathBuf,
+    temp_bin_file: PathBuf,
+    #[cfg(windows)]
     dll_handle: Option<HMODULE>,
+    keep_artifacts: bool,
+    /// Host functions the program can call, by C name
+    host_symbols: Vec<(String, usize)>,
+    /// Command-line arguments the executable is run with
+    args: Vec<String>,
+}
+
+#[cfg(windows)]
+fn temp_path() -> String {
+    // Get Windows temp path
+    let mut temp_path_buf = [0u8; MAX_PATH];
+    let temp_path_len =
+        unsafe { GetTempPathA(MAX_PATH as DWORD, temp_path_buf.as_mut_ptr() as *mut i8) };
+
+    if temp_path_len == 0 {
+        let error = unsafe { GetLastError() };
+        panic!("Failed to get temp path: {}", error);
+    }
+
+    // Convert the Windows temp path to a Rust string
+    String::from_utf8_lossy(&temp_path_buf[..temp_path_len as usize]).to_string()
+}
+
+#[cfg(not(windows))]
+fn temp_path() -> String {
+    format!("{}/", std::env::temp_dir().display())
+}
+
+// Helper function to execute a command and get its output
+fn execute_command(command: &str, args: &[&str]) -> Result<(bool, String, String), io::Error> {
+    let output = Command::new(command).args(args).output()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    Ok((output.status.success(), stdout, stderr))
 }
 
 impl Simulator {
     pub fn new() -> Self {
         // Create unique filenames using process ID
         let pid = Uuid::new_v4().to_string();
-
-        // Get Windows temp path
-        let mut temp_path_buf = [0u8; MAX_PATH];
-        let temp_path_len =
-            unsafe { GetTempPathA(MAX_PATH as DWORD, temp_path_buf.as_mut_ptr() as *mut i8) };
-
-        if temp_path_len == 0 {
-            let error = unsafe { GetLastError() };
-            panic!("Failed to get temp path: {}", error);
-        }
-
-        // Convert the Windows temp path to a Rust string
-        let temp_path = String::from_utf8_lossy(&temp_path_buf[..temp_path_len as usize]);
+        let temp_path = temp_path();
 
         // Create the file paths
         let temp_asm_file = PathBuf::from(format!("{}asm_{}.s", temp_path, pid));
         let temp_obj_file = PathBuf::from(format!("{}asm_{}.o", temp_path, pid));
-        let temp_dll_file = PathBuf::from(format!("{}asm_{}.dll", temp_path, pid));
+        let temp_bin_file = PathBuf::from(format!("{}asm_{}.{}", temp_path, pid, BINARY_EXTENSION));
 
         Simulator {
             temp_asm_file,
             temp_obj_file,
-            temp_dll_file,
+            temp_bin_file,
+            #[cfg(windows)]
             dll_handle: None,
+            keep_artifacts: std::env::var(KEEP_ARTIFACTS_VAR).is_ok_and(|value| value == "1"),
+            host_symbols: vec![],
+            args: vec![],
         }
     }
 
+    /// Let the program call `function` as the C function `name`. Calls go through a stub
+    /// that jumps to a pointer slot, which is filled in once the program is loaded, so
+    /// `function` must use the System V calling convention (`extern "sysv64"`).
+    #[allow(dead_code)]
+    pub fn register_symbol(&mut self, name: &str, function: *const ()) -> &mut Self {
+        self.host_symbols
+            .push((name.to_string(), function as usize));
+        self
+    }
+
+    /// Definitions of the registered host functions: each jumps through its own slot.
+    fn host_stubs(&self) -> String {
+        let prefix = if cfg!(target_os = "macos") { "_" } else { "" };
+        let mut stubs = String::new();
+        for (name, _) in &self.host_symbols {
+            stubs += &format!(
+                r#"
+.global {prefix}{name}
+.text
+{prefix}{name}:
+jmp *{prefix}__host_{name}(%rip)
+.global {prefix}__host_{name}
+.data
+.balign 8
+{prefix}__host_{name}:
+.quad 0
+"#
+            );
+        }
+        stubs
+    }
+
+    /// Run the program with these command-line arguments after its name. Only the
+    /// executable receives them; the DLL and in-process runs call `main` without any.
+    #[allow(dead_code)]
+    pub fn program_args(&mut self, args: &[&str]) -> &mut Self {
+        self.args = args.iter().map(|arg| arg.to_string()).collect();
+        self
+    }
+
+    /// Keep the temporary files after the simulator is dropped.
+    #[allow(dead_code)]
+    pub fn keep_artifacts(&mut self, keep: bool) -> &mut Self {
+        self.keep_artifacts = keep;
+        self
+    }
+
+    /// Paths of the assembly, object and DLL (or executable) files, in that order.
+    #[allow(dead_code)]
+    pub fn artifact_paths(&self) -> [&PathBuf; 3] {
+        [
+            &self.temp_asm_file,
+            &self.temp_obj_file,
+            &self.temp_bin_file,
+        ]
+    }
+
     pub fn load_program(&self, asm_code: &str) -> Result<(), io::Error> {
         println!("Compiling assembly code:\n{}", asm_code);
 
-        // Clean the code if in debug mode
-        let cleaned_code = if cfg!(debug_assertions) {
-            asm_code
-                .lines()
-                .filter(|line| {
-                    let trimmed = line.trim();
-                    !trimmed.is_empty() && !trimmed.contains(';')
-                })
-                .collect::<Vec<&str>>()
-                .join("\n")
-        } else {
-            asm_code.to_string()
-        };
-
-        // Modify the code to rename main to _runAsm for Windows
-        let modified_code = cleaned_code
+        // Rename main to _runAsm so the DLL or driver can call it (`_main` on macOS).
+        // Comments are left for the assembler, which understands `#` on every target.
+        let modified_code = asm_code
+            .replace(".global _main", ".global __runAsm")
             .replace(".global main", ".global _runAsm")
-            .replace("main:", "_runAsm:");
+            .replace("main:", "_runAsm:")
+            + &self.host_stubs();
 
         // Write the assembly code to a temporary file
         fs::write(&self.temp_asm_file, &modified_code)?;
         println!("Wrote assembly to temporary file: {:?}", self.temp_asm_file);
 
-        // Helper function to execute a command and get its output
-        fn execute_command(
-            command: &str,
-            args: &[&str],
-        ) -> Result<(bool, String, String), io::Error> {
-            let output = Command::new(command).args(args).output()?;
-
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-
-            Ok((output.status.success(), stdout, stderr))
-        }
-
         // Compile assembly to object file
         let asm_path = self.temp_asm_file.to_str().ok_or_else(|| {
             io::Error::new(io::ErrorKind::InvalidInput, "Invalid assembly file path")
@@ -119,15 +240,8 @@ impl Simulator {
             );
 
             // Save the assembly file for debugging (in case it gets deleted)
-            let pid = unsafe { GetCurrentProcessId() };
-
-            // Get Windows temp path again for debug file
-            let mut temp_path_buf = [0u8; MAX_PATH];
-            let temp_path_len =
-                unsafe { GetTempPathA(MAX_PATH as DWORD, temp_path_buf.as_mut_ptr() as *mut i8) };
-            let temp_path = String::from_utf8_lossy(&temp_path_buf[..temp_path_len as usize]);
-
-            let debug_file_name = format!("{}asm_debug_{}.s", temp_path, pid);
+            let pid = std::process::id();
+            let debug_file_name = format!("{}asm_debug_{}.s", temp_path(), pid);
             if let Ok(_) = fs::write(&debug_file_name, &modified_code) {
                 error_msg += &format!("Assembly code saved to: {}", debug_file_name);
             }
@@ -135,9 +249,17 @@ impl Simulator {
             return Err(io::Error::new(io::ErrorKind::Other, error_msg));
         }
 
+        self.link(obj_path)?;
+
+        println!("Successfully compiled and linked assembly");
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn link(&self, obj_path: &str) -> Result<(), io::Error> {
         // Link object file to create DLL
         let dll_path = self
-            .temp_dll_file
+            .temp_bin_file
             .to_str()
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid DLL file path"))?;
 
@@ -166,15 +288,87 @@ impl Simulator {
 
             return Err(io::Error::new(io::ErrorKind::Other, error_msg));
         }
+        Ok(())
+    }
 
-        println!("Successfully compiled and linked assembly");
+    #[cfg(not(windows))]
+    fn link(&self, obj_path: &str) -> Result<(), io::Error> {
+        if !self.host_symbols.is_empty() {
+            return self.link_shared(obj_path);
+        }
+
+        // Link object file and driver to create an executable
+        let exe_path = self.temp_bin_file.to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Invalid executable file path")
+        })?;
+
+        let mut link = Command::new("gcc")
+            .args(["-x", "c", "-", "-x", "none", obj_path, "-o", exe_path])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        link.stdin
+            .take()
+            .expect("gcc stdin is piped")
+            .write_all(DRIVER_SOURCE.as_bytes())?;
+        let output = link.wait_with_output()?;
+
+        if !output.status.success() {
+            let error_msg = format!(
+                "Failed to create executable (status: failed)\n\
+                Command: gcc -x c - -x none \"{}\" -o \"{}\"\n\
+                Output: {}\n",
+                obj_path,
+                exe_path,
+                String::from_utf8_lossy(&output.stderr)
+            );
+
+            return Err(io::Error::other(error_msg));
+        }
         Ok(())
     }
 
+    /// Link a shared object that `execute_in_process` can load next to the host functions.
+    #[cfg(not(windows))]
+    fn link_shared(&self, obj_path: &str) -> Result<(), io::Error> {
+        let so_path = self.temp_bin_file.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid shared object file path",
+            )
+        })?;
+
+        // the compiler addresses its own symbols directly, which -Bsymbolic keeps valid
+        let args = [
+            "-shared",
+            "-Wl,-Bsymbolic",
+            "-Wl,-z,notext",
+            obj_path,
+            "-o",
+            so_path,
+        ];
+        let (link_success, _, link_stderr) = execute_command("gcc", &args)?;
+
+        if !link_success {
+            let error_msg = format!(
+                "Failed to create shared object (status: failed)\n\
+                Command: gcc {}\n\
+                Output: {}\n",
+                args.join(" "),
+                link_stderr
+            );
+
+            return Err(io::Error::other(error_msg));
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
     pub fn execute(&mut self) -> Result<i32, io::Error> {
         // Load the DLL
         let dll_path = self
-            .temp_dll_file
+            .temp_bin_file
             .to_str()
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid DLL file path"))?;
 
@@ -191,6 +385,16 @@ impl Simulator {
 
         self.dll_handle = Some(dll_handle);
 
+        // Point the stubs of the registered host functions at them
+        for (name, function) in &self.host_symbols {
+            let slot_name = CString::new(format!("__host_{}", name))?;
+            let slot = unsafe { GetProcAddress(dll_handle, slot_name.as_ptr()) };
+            if slot.is_null() {
+                return Err(io::Error::other(format!("Missing slot for {}", name)));
+            }
+            unsafe { *(slot as *mut usize) = *function };
+        }
+
         // Get the function pointer
         type AsmFunction = unsafe extern "sysv64" fn() -> i32;
         let run_asm_name = CString::new("_runAsm")?;
@@ -225,6 +429,12 @@ impl Simulator {
             }
             self.dll_handle = None;
 
+            if self.keep_artifacts {
+                println!("Kept assembly file: {:?}", self.temp_asm_file);
+                println!("Kept object file: {:?}", self.temp_obj_file);
+                println!("Kept DLL file: {:?}", self.temp_bin_file);
+            }
+
             Ok(result)
         } else {
             let error_code = unsafe { GetLastError() };
@@ -239,16 +449,112 @@ impl Simulator {
             ))
         }
     }
+
+    /// Runs the linked executable. A program killed by a signal, such as SIGFPE on
+    /// division by zero, is an `Err` naming the signal.
+    #[cfg(not(windows))]
+    pub fn execute(&mut self) -> Result<i32, io::Error> {
+        if !self.host_symbols.is_empty() {
+            return self.execute_in_process();
+        }
+
+        let output = Command::new(&self.temp_bin_file)
+            .args(&self.args)
+            .output()?;
+
+        if let Some(signal) = output.status.signal() {
+            return Err(io::Error::other(format!(
+                "Program was killed by signal {}",
+                signal
+            )));
+        }
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "Program failed with {}",
+                output.status
+            )));
+        }
+
+        // The driver prints the result last, after anything the program wrote itself
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let result = stdout
+            .lines()
+            .last()
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Missing result in program output: {:?}", stdout),
+                )
+            })?;
+
+        #[cfg(debug_assertions)]
+        println!("Program returned: {}", result);
+
+        if self.keep_artifacts {
+            println!("Kept assembly file: {:?}", self.temp_asm_file);
+            println!("Kept object file: {:?}", self.temp_obj_file);
+            println!("Kept executable: {:?}", self.temp_bin_file);
+        }
+
+        Ok(result)
+    }
+
+    /// Load the shared object, fill in the host function slots and call `_runAsm`.
+    /// Unlike the executable, a crash here takes the test process down with it.
+    #[cfg(not(windows))]
+    fn execute_in_process(&mut self) -> Result<i32, io::Error> {
+        let so_path = self.temp_bin_file.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid shared object file path",
+            )
+        })?;
+        let so_path_c = CString::new(so_path)?;
+        let handle = unsafe { dlopen(so_path_c.as_ptr(), RTLD_NOW) };
+        if handle.is_null() {
+            return Err(io::Error::other(format!(
+                "Failed to load shared object {}",
+                so_path
+            )));
+        }
+
+        let lookup = |name: &str| -> Result<*mut c_void, io::Error> {
+            let name_c = CString::new(name)?;
+            let address = unsafe { dlsym(handle, name_c.as_ptr()) };
+            if address.is_null() {
+                return Err(io::Error::other(format!("Missing symbol {}", name)));
+            }
+            Ok(address)
+        };
+        let result = (|| {
+            for (name, function) in &self.host_symbols {
+                let slot = lookup(&format!("__host_{}", name))?;
+                unsafe { *(slot as *mut usize) = *function };
+            }
+            type AsmFunction = unsafe extern "sysv64" fn() -> i32;
+            let run_asm: AsmFunction = unsafe { std::mem::transmute(lookup("_runAsm")?) };
+            Ok(unsafe { run_asm() })
+        })();
+
+        unsafe {
+            dlclose(handle);
+        }
+        result
+    }
 }
 
 impl Drop for Simulator {
     fn drop(&mut self) {
         // Clean up temporary files
-        let _ = fs::remove_file(&self.temp_asm_file);
-        let _ = fs::remove_file(&self.temp_obj_file);
-        let _ = fs::remove_file(&self.temp_dll_file);
+        if !self.keep_artifacts {
+            let _ = fs::remove_file(&self.temp_asm_file);
+            let _ = fs::remove_file(&self.temp_obj_file);
+            let _ = fs::remove_file(&self.temp_bin_file);
+        }
 
         // Free the library if it's loaded
+        #[cfg(windows)]
         if let Some(handle) = self.dll_handle {
             unsafe {
                 FreeLibrary(handle);
@@ -271,6 +577,7 @@ impl CompilerTest {
 
     /// Compiles source code, loads it into the simulator, and executes it.
     /// Returns the exit code or TestError on compiler/simulator failure.
+    #[allow(dead_code)]
     pub fn compile_and_run(&mut self, source: &str) -> Result<i32, CompilerError> {
         let asm = compile(source.to_string())?;
         Ok(self.load_and_run_asm(&*asm))
@@ -289,6 +596,7 @@ impl CompilerTest {
 
     /// Compiles source code and asserts that it runs successfully with the expected exit code.
     /// Panics on compiler/simulator error or if the exit code doesn't match.
+    #[allow(dead_code)]
     pub fn assert_runs_ok(&mut self, source: &str, expected_code: i32) {
         match self.compile_and_run(source) {
             Ok(actual_code) => {
@@ -358,6 +666,20 @@ impl CompilerTest {
         }
     }
 
+    /// Compiles source code and asserts that running it crashes, e.g. with SIGFPE.
+    /// Only available where the program runs in a child process.
+    #[cfg(not(windows))]
+    #[allow(dead_code)]
+    pub fn expect_death(&mut self, source: &str) {
+        match compile(source.to_string()) {
+            Ok(asm) => self.assert_asm_execution_fails(&asm),
+            Err(e) => panic!(
+                "Test failed: Expected a runnable program, but got error: {}",
+                e
+            ),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn assert_is_global(&self, asm_source: &str, name: &str) {
         if !Regex::new(format!("\\.global\\s+\\b{}\\b", name).as_str())
@@ -18,6 +18,60 @@ fn test_declare_then_assign(mut harness: CompilerTest) {
     harness.assert_runs_ok(source, 5);
 }
 
+#[rstest]
+fn test_initializers_stop_at_comma(mut harness: CompilerTest) {
+    // each comma starts a new declarator, while an assignment can still be an initializer
+    let source = "int main() { int a = 1, b = 2, c = b = a + 4; return a * 100 + b * 10 + c; }";
+    harness.assert_runs_ok(source, 155);
+}
+
+#[rstest]
+fn test_chained_assignment(mut harness: CompilerTest) {
+    let source = "int main() { int a, b, c; a = b = c = 7; return a + b + c; }";
+    harness.assert_runs_ok(source, 21);
+}
+
+#[rstest]
+fn test_chained_assignment_converts_at_each_step(mut harness: CompilerTest) {
+    // the value stored in each lvalue is what the next one to the left receives
+    let source = r#"
+int main() {
+    long l;
+    unsigned u;
+    int i;
+    l = u = i = -1;
+    return (i == -1) + (u == 4294967295u) * 2 + (l == 4294967295l) * 4;
+}
+"#;
+    harness.assert_runs_ok(source, 7);
+}
+
+#[rstest]
+fn test_parenthesized_declarator(mut harness: CompilerTest) {
+    let source = "int main() { int (y) = 7; long ((z)); z = 2; return y * 10 + z; }";
+    harness.assert_runs_ok(source, 72);
+}
+
+#[rstest]
+fn test_parenthesized_file_scope_declarators(mut harness: CompilerTest) {
+    let source = r#"
+        int (g) = 5;
+        int (twice)(int x) {
+            return 2 * x;
+        }
+        int main() {
+            return twice(g);
+        }
+    "#;
+    harness.assert_runs_ok(source, 10);
+}
+
+#[rstest]
+fn test_unbalanced_declarator_parens(harness: CompilerTest) {
+    let source = "int main() { int (x = 1; return x; }";
+    assert_compile_err!(harness, source, CompilerError::SyntaxError(_));
+}
+
 #[rstest]
 fn test_non_short_circuit(mut harness: CompilerTest) {
     let source = r#"
@@ -461,4 +515,93 @@ fn test_decrement_overflow(mut harness: CompilerTest) {
 fn test_prefix_as_lvalue_for_compound_assign(mut harness: CompilerTest) {
     let source = "int main() { int a = 5; return ++a += 2; }";
     harness.assert_runs_ok(source, 8);
-}
\ No newline at end of file
+}
+fn assert_not_assignable(harness: &CompilerTest, source: &str) {
+    harness.assert_compile_error(source, |e| {
+        matches!(e, CompilerError::SemanticError(message) if message.contains("is not assignable"))
+    });
+}
+
+#[rstest]
+fn test_assign_to_conditional(harness: CompilerTest) {
+    let source = "int main() { int a = 1; int b = 2; int c = 3; (a ? b : c) = 5; return b; }";
+    assert_not_assignable(&harness, source);
+}
+
+#[rstest]
+fn test_assign_to_constant(harness: CompilerTest) {
+    assert_not_assignable(&harness, "int main() { 3 = 4; return 0; }");
+}
+
+#[rstest]
+fn test_assign_to_cast(harness: CompilerTest) {
+    let source = "int main() { long a = 1; (int) a = 2; return 0; }";
+    assert_not_assignable(&harness, source);
+}
+
+#[rstest]
+fn test_assign_to_function_call(harness: CompilerTest) {
+    let source = "int f() { return 1; } int main() { f() = 2; return 0; }";
+    assert_not_assignable(&harness, source);
+}
+
+#[rstest]
+fn test_assign_to_binary(harness: CompilerTest) {
+    assert_not_assignable(&harness, "int main() { int a = 1; (a + 1) = 2; return a; }");
+}
+
+#[rstest]
+fn test_compound_assign_to_binary(harness: CompilerTest) {
+    let source = "int main() { int a = 1; (a * 2) += 2; return a; }";
+    assert_not_assignable(&harness, source);
+}
+
+#[rstest]
+fn test_increment_constant(harness: CompilerTest) {
+    assert_not_assignable(&harness, "int main() { return ++3; }");
+}
+
+#[rstest]
+fn test_compound_literal(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        int a = (int){7} + 1;
+        long b = (long){3000000000u} * 2;
+        int c = ((int){1} = 5) + 1;
+        int d = ++(int){41};
+        if (a != 8) return 1;
+        if (b != 6000000000l) return 2;
+        if (c != 6) return 3;
+        if (d != 42) return 4;
+        return 0;
+    }"#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+fn test_compound_literal_is_not_constant(harness: CompilerTest) {
+    let source = "int x = (int){1}; int main() { return x; }";
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_compound_assignment_evaluates_lvalue_once(mut harness: CompilerTest) {
+    let source = r#"
+    int calls = 0;
+    int next() {
+        calls = calls + 1;
+        return 10 * calls;
+    }
+    int main() {
+        int a = ((int){next()} += 5);
+        long b = ((long){next()} <<= 33);
+        int x = 3;
+        ++x *= 2;
+        if (a != 15) return 1;
+        if (b != 171798691840l) return 2;
+        if (calls != 2) return 3;
+        if (x != 8) return 4;
+        return 0;
+    }"#;
+    harness.assert_runs_ok(source, 0);
+}
@@ -1,7 +1,7 @@
 // tests/test_binary.rs
 mod simulator;
 
-use compiler::CompilerError;
+use compiler::{CompileOptions, CompilerError, compile_with_warnings};
 use rstest::*;
 use simulator::{CompilerTest, harness};
 
@@ -76,6 +76,19 @@ fn test_divide_negative(mut harness: CompilerTest) {
     harness.assert_runs_ok(source, -2);
 }
 
+#[rstest]
+fn test_divide_by_negative_immediate(mut harness: CompilerTest) {
+    let source = r#"int main() {
+    int a = -100;
+    int b = 7;
+    if (a / -4 != 25) return 1;
+    if (a % -7 != -2) return 2;
+    if (b / -2 != -3) return 3;
+    return 0;
+}"#;
+    harness.assert_runs_ok(source, 0);
+}
+
 #[rstest]
 fn test_unary_and_binary(mut harness: CompilerTest) {
     let source = r#"int main() {
@@ -103,18 +116,43 @@ fn test_complicated(mut harness: CompilerTest) {
     );
 }
 
-// #[rstest]
-// fn test_divide_by_zero() {
-//     let source = r#"int main() {
-//     return 1 / 0;
-//     }"#;
-//     expect_death(source);
-// }
-//
-// #[rstest]
-// fn test_mod_by_zero() {
-//     let source = r#"int main() {
-//     return 1 % 0;
-// }"#;
-//     expect_death(source);
-// }
+#[cfg(not(windows))]
+#[rstest]
+fn test_divide_by_zero(mut harness: CompilerTest) {
+    let source = r#"int main() {
+    return 1 / 0;
+    }"#;
+    harness.expect_death(source);
+}
+
+#[cfg(not(windows))]
+#[rstest]
+fn test_mod_by_zero(mut harness: CompilerTest) {
+    let source = r#"int main() {
+    return 1 % 0;
+}"#;
+    harness.expect_death(source);
+}
+
+#[rstest]
+fn test_chained_comparison_warns(mut harness: CompilerTest) {
+    let source = r#"int main() {
+    long big = 5000000000l;
+    if ((3 > 2 > 1) != 0) return 1;
+    if ((big > 4 > 0l) != 1) return 2;
+    return 1 < 2 < 3;
+}"#;
+    let (asm, warnings) =
+        compile_with_warnings(source.to_string(), &CompileOptions::default()).unwrap();
+    assert_eq!(warnings.len(), 3, "{:?}", warnings);
+    assert!(warnings[0].contains("Chained comparison"));
+    assert_eq!(harness.load_and_run_asm(&asm), 1);
+}
+
+#[rstest]
+fn test_parenthesized_comparison_does_not_warn() {
+    let source = "int main() { return (1 < 2) < 3 && 1 < 2; }";
+    let (_, warnings) =
+        compile_with_warnings(source.to_string(), &CompileOptions::default()).unwrap();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+}
@@ -402,4 +402,68 @@ fn test_different_scope(harness: CompilerTest) {
         }
     "#;
     assert_compile_err!(harness, source, CompilerError::SemanticError(_));
-}
\ No newline at end of file
+}
+#[rstest]
+fn test_inner_shadow(mut harness: CompilerTest) {
+    let source = r#"
+        int main() {
+            int x = 1;
+            {
+                int x = 2;
+                return x;
+            }
+        }
+    "#;
+    harness.assert_runs_ok(source, 2);
+}
+
+#[rstest]
+fn test_outer_restored_after_shadow(mut harness: CompilerTest) {
+    let source = r#"
+        int main() {
+            int x = 1;
+            {
+                int x = 2;
+                x = x + 5;
+            }
+            return x;
+        }
+    "#;
+    harness.assert_runs_ok(source, 1);
+}
+
+#[rstest]
+fn test_redeclaration_after_shadow(harness: CompilerTest) {
+    let source = r#"
+        int main() {
+            int x = 1;
+            {
+                int x = 2;
+            }
+            int x = 3;
+            return x;
+        }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_shadowed_statics_are_distinct(mut harness: CompilerTest) {
+    let source = r#"
+        int main() {
+            static int x = 1;
+            {
+                static int x = 2;
+                x = x + 10;
+            }
+            {
+                static int y = 3;
+            }
+            {
+                static int y = 4;
+                return x * 10 + y;
+            }
+        }
+    "#;
+    harness.assert_runs_ok(source, 14);
+}
@@ -2,8 +2,10 @@
 mod simulator;
 
 use rstest::*;
-use simulator::{CompilerTest, harness};
-use compiler::CompilerError;
+use simulator::{CompilerTest, KEEP_ARTIFACTS_VAR, Simulator, harness};
+use compiler::{CompilerError, ErrorKind, compile, compile_to_writer, dump_cfg_dot};
+use std::process::Command;
+use std::sync::Mutex;
 
 #[rstest]
 fn test_success(mut harness: CompilerTest) {
@@ -117,4 +119,404 @@ int main() {
 }
 "#;
     harness.assert_runs_ok(source, 0);
-}
\ No newline at end of file
+}
+
+#[rstest]
+fn test_stray_semicolons_at_file_scope(mut harness: CompilerTest) {
+    let source = r#"
+;
+int g = 2;;
+int main() {
+    if (g) ;;
+    { ; }
+    return g;
+};
+;;
+"#;
+    harness.assert_runs_ok(source, 2);
+}
+
+#[rstest]
+fn test_keep_artifacts_env_var() {
+    let source = compile("int main() { return 3; }".to_string()).unwrap();
+
+    unsafe { std::env::set_var(KEEP_ARTIFACTS_VAR, "1") };
+    let mut kept = Simulator::new();
+    unsafe { std::env::remove_var(KEEP_ARTIFACTS_VAR) };
+    let mut cleaned = Simulator::new();
+
+    for simulator in [&mut kept, &mut cleaned] {
+        simulator.load_program(&source).unwrap();
+        assert_eq!(simulator.execute().unwrap(), 3);
+    }
+    let kept_paths: Vec<_> = kept.artifact_paths().into_iter().cloned().collect();
+    let cleaned_paths: Vec<_> = cleaned.artifact_paths().into_iter().cloned().collect();
+    drop(kept);
+    drop(cleaned);
+
+    for path in &kept_paths {
+        assert!(path.exists(), "{:?} was not kept", path);
+        std::fs::remove_file(path).unwrap();
+    }
+    for path in &cleaned_paths {
+        assert!(!path.exists(), "{:?} was not cleaned up", path);
+    }
+}
+
+#[rstest]
+fn test_simulator_runs_on_host(mut harness: CompilerTest) {
+    // results outside 0..=255 and output written by the program must both survive
+    let source = r#"
+int putchar(int c);
+int main() {
+    putchar(52);
+    putchar(50);
+    return -1000;
+}
+"#;
+    harness.assert_runs_ok(source, -1000);
+}
+
+static RECORDED: Mutex<Vec<(i32, i64)>> = Mutex::new(vec![]);
+
+extern "sysv64" fn record(value: i32, extra: i64) -> i32 {
+    RECORDED.lock().unwrap().push((value, extra));
+    value * 2
+}
+
+#[rstest]
+fn test_call_registered_host_function(mut harness: CompilerTest) {
+    let source = r#"
+int record(int value, long extra);
+int main() {
+    int total = 0;
+    for (int i = 1; i <= 3; i = i + 1)
+        total = total + record(i * 10, 5000000000l + i);
+    return total;
+}
+"#;
+    let asm = compile(source.to_string()).unwrap();
+    harness
+        .simulator
+        .register_symbol("record", record as *const ());
+    assert_eq!(harness.load_and_run_asm(&asm), 120);
+    assert_eq!(
+        *RECORDED.lock().unwrap(),
+        [(10, 5000000001), (20, 5000000002), (30, 5000000003)]
+    );
+}
+
+#[rstest]
+fn test_error_kind_and_position() {
+    let err = compile("int main() {\n    return 0\n}".to_string()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Syntax);
+    assert!(err.line().is_some());
+    assert!(err.to_string().starts_with("Syntax Error:"));
+
+    let err = compile("int main() {\n    return x;\n}".to_string()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Semantic);
+    let (_, function) = err.position().expect("missing position");
+    assert_eq!(function, "main");
+    assert!(err.message().contains('x'));
+}
+
+#[rstest]
+#[case::string("int main() {\n    _Static_assert(1, \"abc);\n}\n", 2, 23)]
+#[case::comment("int main() {\n    return 0;\n}\n/* never\nclosed\n", 4, 1)]
+#[case::comment_after_code("int main() { return 0; } /* never\nclosed\n", 1, 26)]
+fn test_unterminated_reported_at_start(
+    #[case] source: &str,
+    #[case] line: i32,
+    #[case] column: usize,
+) {
+    let err = compile(source.to_string()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Syntax);
+    assert_eq!(err.line(), Some(line), "{}", err);
+    assert_eq!(err.column(), Some(column), "{}", err);
+    assert!(err.message().contains("Unterminated"), "{}", err);
+}
+
+#[rstest]
+fn test_block_comments(mut harness: CompilerTest) {
+    let source = "int main() {\n    /* spans\n lines */ return /**/ __LINE__ /*/ * */;\n}\n";
+    harness.assert_runs_ok(source, 3);
+}
+
+#[rstest]
+fn test_compile_to_writer_matches_string(mut harness: CompilerTest) {
+    let source = r#"
+static long total = 40;
+int add(int x) {
+    return total + x;
+}
+int main() {
+    return add(2);
+}
+"#;
+    let mut bytes: Vec<u8> = Vec::new();
+    compile_to_writer(source, &mut bytes).unwrap();
+    let asm = String::from_utf8(bytes).unwrap();
+    assert_eq!(asm, compile(source.to_string()).unwrap());
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+
+    let err = compile_to_writer(source, &mut [0u8; 8].as_mut_slice()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io);
+}
+
+#[rstest]
+fn test_compile_to_writer_with_options() {
+    let source = "static int unused() { return 1; }\nint main() { return 2; }\n";
+    let options = compiler::CompileOptions {
+        target: compiler::Target::MacOs,
+        opt_level: compiler::OptLevel::O0,
+        ..compiler::CompileOptions::default()
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    compiler::compile_to_writer_with(source, &options, &mut bytes).unwrap();
+    let asm = String::from_utf8(bytes).unwrap();
+    assert_eq!(
+        asm,
+        compiler::compile_with(source.to_string(), &options).unwrap()
+    );
+    // -O0 keeps the unused static function, and macOS names get an underscore
+    assert!(asm.contains("_unused:"), "{}", asm);
+}
+
+#[rstest]
+#[case::o0(compiler::OptLevel::O0, ["helper:", "main:", "later:", ".data", "total:"])]
+#[case::o2(compiler::OptLevel::O2, ["main:", "later:", "helper:", ".data", "total:"])]
+fn test_compile_to_writer_order(
+    mut harness: CompilerTest,
+    #[case] opt_level: compiler::OptLevel,
+    #[case] order: [&str; 5],
+) {
+    let source = r#"
+static long total = 40;
+static int helper(int x) {
+    return total + x;
+}
+int main() {
+    return helper(2);
+}
+int later() {
+    return 0;
+}
+"#;
+    let options = compiler::CompileOptions {
+        opt_level,
+        ..compiler::CompileOptions::default()
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    compiler::compile_to_writer_with(source, &options, &mut bytes).unwrap();
+    let asm = String::from_utf8(bytes).unwrap();
+    assert_eq!(
+        asm,
+        compiler::compile_with(source.to_string(), &options).unwrap()
+    );
+    // with optimizations on the static function waits until the end, just before the data
+    let positions: Vec<_> = order.iter().map(|label| asm.find(label).unwrap()).collect();
+    assert!(positions.is_sorted(), "{:?} out of order in {}", order, asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+}
+
+#[rstest]
+#[case::o0(compiler::OptLevel::O0)]
+#[case::o2(compiler::OptLevel::O2)]
+fn test_compile_to_writer_streams_functions(#[case] opt_level: compiler::OptLevel) {
+    let source = "int first() { return 1; }\nint second() { return missing; }\n";
+    let options = compiler::CompileOptions {
+        opt_level,
+        ..compiler::CompileOptions::default()
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    let err = compiler::compile_to_writer_with(source, &options, &mut bytes).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Semantic);
+    // first was written before second was even checked
+    let asm = String::from_utf8(bytes).unwrap();
+    assert!(asm.contains("first:"), "{}", asm);
+}
+
+#[rstest]
+#[case::empty("")]
+#[case::comments_only("// nothing here\n\n// or here\n")]
+#[case::prototypes_only("int f(int x);\nextern long g;\n")]
+#[case::semicolons_only(";\n;;\n")]
+fn test_translation_unit_without_definitions(#[case] source: &str) {
+    assert_eq!(compile(source.to_string()).unwrap(), "");
+}
+
+#[rstest]
+fn test_lone_global_emits_only_data() {
+    let asm = compile("int g = 5;".to_string()).unwrap();
+    assert!(asm.contains(".data"), "{}", asm);
+    assert!(asm.contains("g:"), "{}", asm);
+    assert!(asm.contains(".long 5"), "{}", asm);
+    assert!(!asm.contains(".text"), "{}", asm);
+}
+
+#[rstest]
+fn test_line_macro(mut harness: CompilerTest) {
+    let source = r#"
+int line() {
+    return __LINE__;
+}
+int main() {
+    // comments still count as lines
+
+    return __LINE__ * 10 + line();
+}
+"#;
+    harness.assert_runs_ok(source, 83);
+}
+
+#[rstest]
+#[case::lone_brace("{")]
+#[case::unterminated_function("int main() { return 1")]
+#[case::truncated_expression("int main() { return (1 + ; }")]
+#[case::oversized_literal("int main() { return 999999999999999999999999; }")]
+#[case::compound_assign_to_rvalue("int main() { int a = 1; (a + 1) += 2; return a; }")]
+#[case::garbage("@#$ int ) main ( 1 {")]
+fn test_malformed_input_is_an_error(#[case] source: &str) {
+    assert!(compile(source.to_string()).is_err());
+}
+
+/// Deep inputs need more than the 2 MiB test threads get, so run them on a stack the
+/// size of a default main thread.
+fn compile_on_main_sized_stack(source: String) -> Result<String, CompilerError> {
+    std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(move || compile(source))
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[rstest]
+fn test_deep_nesting_is_an_error() {
+    let parentheses = format!(
+        "int main() {{ return {}1{}; }}",
+        "(".repeat(10_000),
+        ")".repeat(10_000)
+    );
+    let err = compile_on_main_sized_stack(parentheses).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Syntax);
+
+    let blocks = format!(
+        "int main() {{ {} return 0; {} }}",
+        "{".repeat(10_000),
+        "}".repeat(10_000)
+    );
+    let err = compile_on_main_sized_stack(blocks).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Syntax);
+
+    let negations = format!("int main() {{ return {}1; }}", "- ".repeat(10_000));
+    let err = compile_on_main_sized_stack(negations).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Syntax);
+}
+
+#[rstest]
+fn test_nesting_within_limit(mut harness: CompilerTest) {
+    let blocks = format!(
+        "int main() {{ {} return 42; {} }}",
+        "{".repeat(127),
+        "}".repeat(127)
+    );
+    let asm = compile_on_main_sized_stack(blocks).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+
+    let parentheses = format!(
+        "int main() {{ return {}42{}; }}",
+        "(".repeat(63),
+        ")".repeat(63)
+    );
+    let asm = compile_on_main_sized_stack(parentheses).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+}
+
+#[rstest]
+fn test_verbose_pass_summaries() {
+    let dir = std::env::temp_dir().join(format!("verbose_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("main.c");
+    std::fs::write(&input, "int main() { return 42; }\n").unwrap();
+    let run = |flags: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_compiler"))
+            .args(flags)
+            .arg(&input)
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap()
+    };
+
+    let quiet = run(&[]);
+    let verbose = run(&["--verbose"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(quiet.status.success());
+    assert_eq!(String::from_utf8_lossy(&quiet.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&quiet.stderr), "");
+
+    assert!(verbose.status.success());
+    let summaries = String::from_utf8_lossy(&verbose.stderr);
+    for pass in ["lex:", "parse:", "tac:", "emit:"] {
+        assert!(summaries.contains(pass), "{}", summaries);
+    }
+}
+
+#[test]
+fn test_dump_cfg_dot() {
+    let source = r#"
+int pick(int x) {
+    int r;
+    if (x)
+        r = 1;
+    else
+        r = 2;
+    return r;
+}
+"#;
+    let dot = dump_cfg_dot(source.to_string()).unwrap();
+    assert!(dot.starts_with("digraph \"pick\" {"), "{}", dot);
+    let (edges, blocks): (Vec<&str>, Vec<&str>) = dot
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('b'))
+        .partition(|line| line.contains("->"));
+    assert_eq!(blocks.len(), 4, "{}", dot);
+    assert_eq!(
+        edges,
+        [
+            "b0 -> b2 [label=\"taken\"];",
+            "b0 -> b1 [label=\"fallthrough\"];",
+            "b1 -> b3;",
+            "b2 -> b3;",
+        ],
+        "{}",
+        dot
+    );
+}
+
+#[rstest]
+fn test_large_generated_function(mut harness: CompilerTest) {
+    // thousands of locals, each read back and branched on, to keep name lookups honest
+    let count = 3000;
+    let mut source = String::from("int main() {\n    int v0 = 0;\n");
+    for i in 1..count {
+        source += &format!("    int v{} = v{} + 1;\n", i, i - 1);
+        source += &format!("    if (v{} < 0) return {};\n", i, i);
+    }
+    source += &format!("    return v{} - {};\n}}\n", count - 1, count - 43);
+    let asm = compile(source).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+}
+
+#[rstest]
+fn test_assembly_with_comments_runs(mut harness: CompilerTest) {
+    let asm = compile("int main() { return 42; }".to_string()).unwrap();
+    // comments are the assembler's to skip, even ones that contain a `;`
+    let commented: String = asm
+        .lines()
+        .map(|line| format!("# before: {}; keep\n{}\n", line.trim(), line))
+        .collect();
+    assert_eq!(harness.load_and_run_asm(&commented), 42);
+}
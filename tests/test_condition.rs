@@ -364,4 +364,40 @@ fn test_chained_else_if(mut harness: CompilerTest) {
         }
     "#;
     harness.assert_runs_ok(source, 30);
-}
\ No newline at end of file
+}
+
+#[rstest]
+fn test_conditional_results_keep_their_own_slot(mut harness: CompilerTest) {
+    let source = r#"
+        int main() {
+            int s = 5;
+            int t = 0;
+            return s + (t ? 100 : 1) + (s && t) * 10 + (t || s) * 20;
+        }
+    "#;
+    harness.assert_runs_ok(source, 26);
+}
+
+#[rstest]
+fn test_elvis_evaluates_condition_once(mut harness: CompilerTest) {
+    let source = r#"
+        int calls = 0;
+        long next(long v) {
+            calls = calls + 1;
+            return v;
+        }
+        int main() {
+            int i = 3;
+            int a = i++ ?: 0;
+            int zero = 0;
+            int b = zero++ ?: 7;
+            long c = next(5l) ?: 9;
+            if (a != 3 || i != 4) return 1;
+            if (b != 7 || zero != 1) return 2;
+            if (c != 5l || calls != 1) return 3;
+            if ((0 ?: zero - 1 ?: 4) != 4) return 4;
+            return 0;
+        }
+    "#;
+    harness.assert_runs_ok(source, 0);
+}
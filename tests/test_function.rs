@@ -1,7 +1,9 @@
 mod simulator;
 
 use crate::simulator::{CompilerTest, harness};
-use compiler::{CompilerError, compile};
+use compiler::{
+    CompileOptions, CompilerError, OptLevel, compile, compile_with, compile_with_warnings,
+};
 use rstest::rstest;
 
 #[rstest]
@@ -92,6 +94,54 @@ fn test_many_parameters(mut harness: CompilerTest) {
     harness.assert_runs_ok(source, 5);
 }
 
+#[rstest]
+fn test_long_stack_arguments(mut harness: CompilerTest) {
+    let source = r#"
+    long last(long a, long b, long c, long d, long e, long f, long g, long h, long i, long j) {
+        if (g != -4294967303l || h != 8589934600l || i != -9l) return 1;
+        return j - a;
+    }
+    long seventh(long a, long b, long c, long d, long e, long f, long g) {
+        return g;
+    }
+    int main() {
+        long big = 4294967296l;
+        if (last(big + 1, 2, 3, 4, 5, 6, -big - 7, 2 * big + 8, -9, big * 4 + 10) != 3 * big + 9)
+            return 2;
+        return seventh(1, 2, 3, 4, 5, 6, big + 42) == big + 42;
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    let instructions: Vec<&str> = asm.lines().filter(|line| !line.is_empty()).collect();
+    // the single stack argument of seventh is padded to keep %rsp 16-byte aligned
+    assert!(
+        instructions
+            .windows(2)
+            .any(|pair| pair == ["movabsq $16, %r10", "addq %r10, %rsp"]),
+        "{}",
+        asm
+    );
+    assert_eq!(harness.load_and_run_asm(&asm), 1);
+}
+
+#[rstest]
+fn test_calls_in_arguments(mut harness: CompilerTest) {
+    // each inner call runs before any argument of the outer one is passed
+    let source = r#"
+    int add(int a, int b) {
+        return a + b;
+    }
+    int weigh(int a, int b, int c, int d, int e, int f, int g, int h) {
+        return a + b * 2 + c * 3 + d * 4 + e * 5 + f * 6 + g * 7 + h * 8;
+    }
+    int main() {
+        int inner = weigh(add(1, 0), 2, 3, 4, 5, 6, add(3, 4), add(add(4, 4), 0));
+        return add(1, add(2, 3)) * 1000 + weigh(1, add(1, 1), 3, 4, 5, 6, 7, inner) - 8 * inner;
+    }
+    "#;
+    harness.assert_runs_ok(source, 6140);
+}
+
 #[rstest]
 fn test_passing_too_few_args(harness: CompilerTest) {
     let source = r#"
@@ -226,7 +276,7 @@ fn test_assign_variable_to_function(harness: CompilerTest) {
         f = 1;
         return 0;
     }"#;
-    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+    assert_compile_err!(harness, source, CompilerError::TypeError(_));
 }
 
 #[rstest]
@@ -289,3 +339,355 @@ fn test_declaration_with_no_definition() {
         Err(_) => panic!("Expected compilation to succeed"),
     };
 }
+
+#[rstest]
+fn test_function_address(mut harness: CompilerTest) {
+    let source = r#"
+    int helper() {
+        return 1;
+    }
+    int other() {
+        return 2;
+    }
+    int main() {
+        unsigned long fp = helper;
+        unsigned long fp2 = &helper;
+        return fp != 0 && fp == fp2 && other != fp;
+    }
+    "#;
+    harness.assert_runs_ok(source, 1);
+}
+
+#[rstest]
+fn test_address_of_variable(harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        int a = 1;
+        unsigned long p = &a;
+        return 0;
+    }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::TypeError(_));
+}
+
+#[rstest]
+fn test_assign_to_function(harness: CompilerTest) {
+    let source = r#"
+    int helper() {
+        return 1;
+    }
+    int main() {
+        helper = 0;
+        return 0;
+    }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::TypeError(_));
+}
+
+#[rstest]
+fn test_attribute_on_declaration(mut harness: CompilerTest) {
+    let source = r#"
+    int helper(int a) __attribute__((unused, format(printf, 1, 2)));
+    int counter __attribute__((unused)) = 3;
+    __attribute__((unused)) int helper(int a) {
+        return a + counter;
+    }
+    int main() {
+        return helper(2);
+    }
+    "#;
+    harness.assert_runs_ok(source, 5);
+}
+
+#[rstest]
+fn test_noreturn_call_trims_following_code(mut harness: CompilerTest) {
+    let source = r#"
+    int spin() __attribute__((noreturn));
+    int spin() {
+        while (1) {}
+    }
+    int main() {
+        if (0) {
+            spin();
+            return 12345;
+        }
+        return 0;
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(!asm.contains("12345"));
+    assert_eq!(harness.load_and_run_asm(&*asm), 0);
+}
+
+#[rstest]
+fn test_prototype_unnamed_params(mut harness: CompilerTest) {
+    let source = r#"
+    int foo(int, long);
+    int foo(int a, long b) {
+        return a - b;
+    }
+    int main() {
+        return foo(10, 3);
+    }
+    "#;
+    harness.assert_runs_ok(source, 7);
+}
+
+#[rstest]
+fn test_definition_unnamed_param(harness: CompilerTest) {
+    let source = r#"
+    int foo(int a, int) {
+        return a;
+    }
+    int main() {
+        return foo(1, 2);
+    }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_inline_function_has_no_call(mut harness: CompilerTest) {
+    let source = r#"
+    inline int sq(int x) {
+        return x * x;
+    }
+    int main() {
+        return sq(5);
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(!asm.contains("call"));
+    assert_eq!(harness.load_and_run_asm(&*asm), 25);
+}
+
+#[rstest]
+fn test_inline_function_called_at_o0(mut harness: CompilerTest) {
+    let source = r#"
+    inline int sq(int x) {
+        return x * x;
+    }
+    int main() {
+        return sq(5);
+    }
+    "#;
+    let options = CompileOptions {
+        opt_level: OptLevel::O0,
+        ..CompileOptions::default()
+    };
+    let asm = compile_with(source.to_string(), &options).unwrap();
+    assert!(asm.contains("call sq"), "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 25);
+}
+
+#[rstest]
+fn test_nested_inline_calls(mut harness: CompilerTest) {
+    let source = r#"
+    static inline long sq(int x) {
+        return x * x;
+    }
+    static inline int add(int a, int b) {
+        return a + b;
+    }
+    int main() {
+        int x = 2;
+        return add(sq(sq(x)), x);
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(!asm.contains("call"));
+    assert_eq!(harness.load_and_run_asm(&*asm), 18);
+}
+
+#[rstest]
+fn test_inline_call_in_argument_of_itself(
+    mut harness: CompilerTest,
+    #[values(OptLevel::O0, OptLevel::O1, OptLevel::O2)] opt_level: OptLevel,
+) {
+    // the inner expansions must not rebind the outer call's already evaluated parameters
+    let source = r#"
+    inline int add(int a, int b) {
+        return a + b;
+    }
+    int main() {
+        return add(1, add(2, 3)) * 100 + add(add(1, 2), add(3, add(4, 5)));
+    }
+    "#;
+    let options = CompileOptions {
+        opt_level,
+        ..CompileOptions::default()
+    };
+    let asm = compile_with(source.to_string(), &options).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 615);
+}
+
+#[rstest]
+fn test_recursive_inline_function_still_called(mut harness: CompilerTest) {
+    let source = r#"
+    inline int countdown(int n) {
+        return n ? countdown(n - 1) + 1 : 0;
+    }
+    inline int twice(int n) {
+        int result = n * 2;
+        return result;
+    }
+    int main() {
+        return countdown(4) + twice(3);
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(asm.contains("call countdown"));
+    assert!(asm.contains("call twice"));
+    assert_eq!(harness.load_and_run_asm(&*asm), 10);
+}
+
+#[rstest]
+fn test_inline_variable(harness: CompilerTest) {
+    let source = r#"
+    inline int x = 3;
+    int main() {
+        return x;
+    }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_call_before_definition(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        return countdown(5) + helper();
+    }
+
+    int countdown(int n) {
+        if (n == 0) return 0;
+        return 1 + countdown(n - 1);
+    }
+
+    int helper() {
+        return 37;
+    }
+    "#;
+    harness.assert_runs_ok(source, 42);
+}
+
+#[rstest]
+fn test_mutual_recursion_without_prototypes(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        return is_even(7) * 10 + is_odd(7);
+    }
+
+    int is_odd(int n) {
+        if (n == 0) return 0;
+        return is_even(n - 1);
+    }
+
+    int is_even(int n) {
+        if (n == 0) return 1;
+        return is_odd(n - 1);
+    }
+    "#;
+    harness.assert_runs_ok(source, 1);
+}
+
+#[rstest]
+fn test_too_few_args_before_definition(harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        return add(1);
+    }
+
+    int add(int a, int b) {
+        return a + b;
+    }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_too_many_args_before_definition(harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        return add(1, 2, 3);
+    }
+
+    int add(int a, int b) {
+        return a + b;
+    }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
+
+#[cfg(not(windows))]
+#[rstest]
+fn test_main_receives_argc_and_argv(mut harness: CompilerTest) {
+    // argv is held as a long until pointer types exist
+    let source = r#"
+    int main(int argc, long argv) {
+        return argc * 10 + (argv != 0);
+    }
+    "#;
+    harness.simulator.program_args(&["one", "two", "three"]);
+    harness.assert_runs_ok(source, 41);
+}
+
+#[rstest]
+fn test_missing_return_warns(mut harness: CompilerTest) {
+    let source = r#"
+    int sign(int x) {
+        if (x < 0) {
+            return -1;
+        } else {
+            x = 1;
+        }
+    }
+    int main() {
+        return sign(-5) + sign(5);
+    }
+    "#;
+    let (asm, warnings) =
+        compile_with_warnings(source.to_string(), &CompileOptions::default()).unwrap();
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(warnings[0].contains("sign"), "{:?}", warnings);
+    // the missing return still returns 0
+    assert_eq!(harness.load_and_run_asm(&asm), -1);
+}
+
+#[rstest]
+#[case::both_branches("int sign(int x) { if (x < 0) return -1; else return 1; }")]
+#[case::infinite_loop("int sign(int x) { while (1) { if (x < 0) return -1; x = x - 1; } }")]
+#[case::main_only("int main() { int x = 1; }")]
+fn test_every_path_returns(#[case] source: &str) {
+    let (_, warnings) =
+        compile_with_warnings(source.to_string(), &CompileOptions::default()).unwrap();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+}
+
+#[rstest]
+#[case::initializer("int main() { int fp = helper; return 0; }")]
+#[case::assignment("int main() { unsigned int fp; fp = &helper; return 0; }")]
+#[case::return_value("int main() { return helper; }")]
+#[case::argument("int take(int x) { return x; } int main() { return take(helper); }")]
+fn test_function_address_narrowed_implicitly(harness: CompilerTest, #[case] body: &str) {
+    let source = format!("int helper() {{ return 1; }} {}", body);
+    assert_compile_err!(harness, &source, CompilerError::TypeError(_));
+    let err = compile(source).unwrap_err();
+    assert_eq!(err.kind(), compiler::ErrorKind::Type);
+    assert!(err.to_string().starts_with("Type Error:"), "{}", err);
+}
+
+#[rstest]
+fn test_function_address_narrowed_by_cast(mut harness: CompilerTest) {
+    let source = r#"
+    int helper() {
+        return 1;
+    }
+    int main() {
+        long wide = helper;
+        int narrow = (int) helper;
+        return narrow == (int) wide;
+    }
+    "#;
+    harness.assert_runs_ok(source, 1);
+}
@@ -2,6 +2,7 @@ mod simulator;
 
 use crate::simulator::{CompilerTest, harness};
 use compiler::CompilerError::{SemanticError, SyntaxError};
+use compiler::{CompileOptions, compile_with_warnings};
 use rstest::rstest;
 
 #[rstest]
@@ -78,6 +79,38 @@ fn test_long_division(mut harness: CompilerTest) {
     harness.assert_runs_ok(source, 1);
 }
 
+#[rstest]
+fn test_long_division_by_wide_immediate(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        long a = 25769803776l; // 6 * 2^32
+        long b = -a;
+        if (a / 8589934592l != 3l) return 1;
+        if (b / -8589934592l != 3l) return 2;
+        if ((a + 5l) % 8589934592l != 5l) return 3;
+        if (a / -4l != -6442450944l) return 4;
+        return 0;
+    }
+    "#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+fn test_compare_wide_immediate_on_left(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        long x = 5000000000l;
+        if (!(5000000000l == x)) return 1;
+        if (5000000001l == x) return 2;
+        if (!(4999999999l < x)) return 3;
+        if (-5000000000l > x) return 4;
+        if (!(5000000000l >= x)) return 5;
+        return 0;
+    }
+    "#;
+    harness.assert_runs_ok(source, 0);
+}
+
 #[rstest]
 fn test_long_subtraction(mut harness: CompilerTest) {
     let source = r#"
@@ -790,3 +823,149 @@ fn test_multiple_casts(mut harness: CompilerTest) {
     }"#;
     harness.assert_runs_ok(source, 0);
 }
+
+#[rstest]
+fn test_comparison_into_long(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        long a = 4294967296l;
+        long b = -1l;
+        long lt = -1l;
+        lt = b < a;
+        if (lt != 1l) return 1;
+
+        long ge = -1l;
+        ge = b >= a;
+        if (ge != 0l) return 2;
+
+        long sum = (a == a) + 4294967295l;
+        if (sum != 4294967296l) return 3;
+
+        return 0;
+    }"#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+fn test_int_argument_sign_extended_to_long_param(mut harness: CompilerTest) {
+    let source = r#"
+    long widen(long a, long b, long c, long d, long e, long f, long g, long h) {
+        if (a != -1l || b != -2l || c != -3l || d != -4l) return 1;
+        if (e != -5l || f != -6l || g != -7l || h != -8l) return 2;
+        return a + h;
+    }
+
+    int main() {
+        int a = -1;
+        int h = -8;
+        if (widen(a, -2, -3, -4, -5, -6, -7, h) != -9l) return 3;
+        return 0;
+    }"#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+fn test_call_arity_mismatch(harness: CompilerTest) {
+    let source = r#"
+    long foo(int a, int b, int c) {
+        return a + b + c;
+    }
+
+    int main() {
+        return foo(1, 2);
+    }"#;
+    assert_compile_err!(harness, source, SemanticError(_));
+}
+
+#[rstest]
+fn test_return_converts_to_declared_type(mut harness: CompilerTest) {
+    let source = r#"
+    int narrow(long x) {
+        return x + 1l;
+    }
+    long widen(int x) {
+        return x * 2;
+    }
+    long widen_unsigned(unsigned int x) {
+        return x;
+    }
+    unsigned long reinterpret(int x) {
+        return x;
+    }
+
+    int main() {
+        if (narrow(4294967296l) != 1) return 1;
+        if (narrow(4294967294l) != -1) return 2;
+        if (widen(-3) != -6l) return 3;
+        if (widen_unsigned(4294967295u) != 4294967295l) return 4;
+        if (reinterpret(-1) != 18446744073709551615ul) return 5;
+        return 0;
+    }"#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+fn test_truncating_constant_warns(mut harness: CompilerTest) {
+    let source = r#"
+int g = 5000000000l;
+int main() {
+    int i = 4294967298l;
+    return g - 705032704 + i;
+}"#;
+    let (asm, warnings) =
+        compile_with_warnings(source.to_string(), &CompileOptions::default()).unwrap();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(
+        warnings[0].contains("truncates it to 705032704"),
+        "{:?}",
+        warnings
+    );
+    assert!(warnings[1].contains("truncates it to 2"), "{:?}", warnings);
+    assert_eq!(harness.load_and_run_asm(&asm), 2);
+}
+
+#[rstest]
+fn test_fitting_constant_does_not_warn() {
+    let source =
+        "int main() { int i = 100l; unsigned u = -1; return i - 100 + (u == 4294967295u) - 1; }";
+    let (_, warnings) =
+        compile_with_warnings(source.to_string(), &CompileOptions::default()).unwrap();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+}
+
+#[rstest]
+fn test_long_function_returns_widened_comparison(mut harness: CompilerTest) {
+    // the wide product leaves high bits in RAX before the 4-byte comparison result
+    let source = r#"
+long less(long a, long b) {
+    long wide = a * 4294967296l;
+    return wide < b;
+}
+long both(long a) {
+    return a * -4294967296l && 1;
+}
+long f() { return 1 < 2; }
+int main() {
+    return (f() == 1l) + (less(-3l, 1l) == 1l) * 2 + (both(5l) == 1l) * 4;
+}
+"#;
+    harness.assert_runs_ok(source, 7);
+}
+
+#[rstest]
+fn test_long_condition_tests_all_bits(mut harness: CompilerTest) {
+    // only the upper half of each condition is set
+    let source = r#"
+int main() {
+    long x = 4294967296l;
+    int r = 0;
+    if (x) r = r + 1;
+    while (x) {
+        r = r + 2;
+        x = 0;
+    }
+    return r + (8589934592l || 0) * 4 + (x || 0) * 8;
+}
+"#;
+    harness.assert_runs_ok(source, 7);
+}
@@ -1,7 +1,7 @@
 // tests/test_loops.rs
 mod simulator;
 
-use compiler::CompilerError;
+use compiler::{CompilerError, compile};
 use rstest::*;
 use simulator::{CompilerTest, harness};
 
@@ -135,6 +135,34 @@ fn test_continue_in_for(mut harness: CompilerTest) {
     harness.assert_runs_ok(source, 30);
 }
 
+#[rstest]
+fn test_continue_in_for_tests_condition_once(mut harness: CompilerTest) {
+    // a continue runs the increment, then the condition once, like reaching the body's end
+    let source = r#"
+        int tests = 0;
+        int steps = 0;
+        int below(int i, int n) {
+            tests++;
+            return i < n;
+        }
+        int step(int i) {
+            steps++;
+            return i + 1;
+        }
+        int main() {
+            int i;
+            int sum = 0;
+            for (i = 0; below(i, 5); i = step(i)) {
+                if (i == 2)
+                    continue;
+                sum += i;
+            }
+            return sum * 1000 + tests * 100 + steps * 10 + i;
+        }
+    "#;
+    harness.assert_runs_ok(source, 8655);
+}
+
 #[rstest]
 fn test_break_in_for(mut harness: CompilerTest) {
     let source = r#"
@@ -615,3 +643,220 @@ fn test_do_while_empty_body(mut harness: CompilerTest) {
     "#;
     harness.assert_runs_ok(code, 5);
 }
+
+#[rstest]
+fn test_continue_in_for_still_increments(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int count = 0;
+            int i;
+            for (i = 0; i < 10; i = i + 1) {
+                continue;
+                count = count + 100;
+            }
+            return i + count;
+        }
+    "#;
+    harness.assert_runs_ok(code, 10);
+}
+
+#[rstest]
+fn test_continue_in_while_terminates(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int i = 0;
+            int skipped = 0;
+            while (i < 10) {
+                i = i + 1;
+                if (i > 5) {
+                    skipped = skipped + 1;
+                    continue;
+                }
+            }
+            return skipped;
+        }
+    "#;
+    harness.assert_runs_ok(code, 5);
+}
+
+#[rstest]
+fn test_continue_in_do_while_tests_condition(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int i = 0;
+            do {
+                i = i + 1;
+                continue;
+            } while (i < 3);
+            return i;
+        }
+    "#;
+    harness.assert_runs_ok(code, 3);
+}
+
+#[rstest]
+fn test_for_init_not_visible_after_loop(harness: CompilerTest) {
+    let source = r#"
+        int main() {
+            for (int i = 0; i < 3; i = i + 1) {}
+            return i;
+        }
+    "#;
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_sequential_for_loops_declare_same_name(mut harness: CompilerTest) {
+    let source = r#"
+        int main() {
+            int sum = 0;
+            for (int i = 0; i < 3; i = i + 1) sum = sum + i;
+            for (int i = 10; i < 12; i = i + 1) {
+                int i = 1;
+                sum = sum + i;
+            }
+            return sum;
+        }
+    "#;
+    harness.assert_runs_ok(source, 5);
+}
+
+#[rstest]
+fn test_constant_true_loop_has_no_condition_test(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int i = 0;
+            while (1) {
+                i = i + 1;
+                if (i == 10)
+                    break;
+            }
+            return i;
+        }
+    "#;
+    let asm = compile(code.to_string()).unwrap();
+    let tests = asm
+        .lines()
+        .filter(|line| line.starts_with("cmp") || line.starts_with("test"))
+        .count();
+    // only the if (i == 10) compares anything
+    assert_eq!(tests, 1, "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 10);
+}
+
+#[rstest]
+fn test_constant_false_loops_emit_no_body(mut harness: CompilerTest) {
+    let code = r#"
+        int marker() {
+            return 1;
+        }
+        int main() {
+            int j = 0;
+            while (0)
+                j = marker();
+            for (j = 3; 0; j = marker())
+                j = marker();
+            do
+                j = j + 1;
+            while (0);
+            return j;
+        }
+    "#;
+    let asm = compile(code.to_string()).unwrap();
+    assert!(!asm.contains("call marker"), "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 4);
+}
+
+#[rstest]
+fn test_constant_false_loop_reached_by_case(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int j = 0;
+            switch (2) {
+                case 1:
+                    while (0) {
+                        case 2:
+                            j = j + 100;
+                    }
+            }
+            return j;
+        }
+    "#;
+    harness.assert_runs_ok(code, 100);
+}
+
+#[rstest]
+fn test_do_while_zero_runs_body_once(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int runs = 0;
+            int skipped = 0;
+            do {
+                runs = runs + 1;
+            } while (0);
+            do {
+                runs = runs + 10;
+                continue;
+                skipped = 1;
+            } while (0);
+            do {
+                runs = runs + 100;
+                if (runs > 0)
+                    break;
+                skipped = 1;
+            } while (0);
+            return runs + skipped;
+        }
+    "#;
+    harness.assert_runs_ok(code, 111);
+}
+
+#[rstest]
+fn test_nested_do_while_continue_tests_own_condition(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int outer = 0;
+            int inner_total = 0;
+            do {
+                int inner = 0;
+                do {
+                    inner = inner + 1;
+                    if (inner % 2)
+                        continue;
+                    inner_total = inner_total + 1;
+                } while (inner < 4);
+                outer = outer + 1;
+                if (outer == 2)
+                    continue;
+                inner_total = inner_total + 100;
+            } while (outer < 3);
+            return inner_total;
+        }
+    "#;
+    harness.assert_runs_ok(code, 206);
+}
+
+#[rstest]
+fn test_sibling_do_while_labels_are_unique(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int a = 0;
+            int b = 0;
+            do {
+                a = a + 1;
+            } while (a < 3);
+            do {
+                b = b + a;
+            } while (b < 9);
+            return a * 10 + b;
+        }
+    "#;
+    let asm = compile(code.to_string()).unwrap();
+    let labels: Vec<&str> = asm
+        .lines()
+        .filter(|line| line.ends_with("_start.loop:"))
+        .collect();
+    assert_eq!(labels.len(), 2, "{}", asm);
+    assert_ne!(labels[0], labels[1]);
+    assert_eq!(harness.load_and_run_asm(&asm), 39);
+}
@@ -0,0 +1,436 @@
+mod simulator;
+
+use crate::simulator::{CompilerTest, harness};
+use compiler::{CompileOptions, OptLevel, compile, compile_with};
+use rstest::rstest;
+
+fn count_instructions(asm: &str) -> usize {
+    asm.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.ends_with(':') && !line.starts_with('.'))
+        .count()
+}
+
+#[rstest]
+fn test_dead_store_elimination(mut harness: CompilerTest) {
+    let source = r#"
+    int f(int a, int b) {
+        int x = a ? 1 : 2;
+        x = 5;
+        int y = b ? 3 : 4;
+        y = x + 1;
+        return a ? x : y;
+    }
+    int main() {
+        return f(1, 0) + f(0, 1);
+    }"#;
+    // same function with the overwritten stores removed by hand; the casts keep the
+    // conditionals' values, which are still computed before being overwritten
+    let trimmed = r#"
+    int f(int a, int b) {
+        int x;
+        (int)(a ? 1 : 2);
+        x = 5;
+        int y;
+        (int)(b ? 3 : 4);
+        y = x + 1;
+        return a ? x : y;
+    }
+    int main() {
+        return f(1, 0) + f(0, 1);
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    let trimmed_asm = compile(trimmed.to_string()).unwrap();
+    assert_eq!(count_instructions(&asm), count_instructions(&trimmed_asm));
+    assert_eq!(harness.load_and_run_asm(&*asm), 11);
+}
+
+#[rstest]
+fn test_store_read_before_overwrite_kept(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        int x = 3;
+        int y = x;
+        x = 4;
+        return x + y;
+    }"#;
+    harness.assert_runs_ok(source, 7);
+}
+
+#[rstest]
+fn test_tail_recursion_runs_in_constant_stack(mut harness: CompilerTest) {
+    let source = r#"
+    int count(int n, int acc) {
+        if (n == 0) {
+            return acc;
+        }
+        return count(n - 1, acc + 1);
+    }
+    int main() {
+        return count(10000000, 0) == 10000000;
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    assert_eq!(asm.matches("call count").count(), 1);
+    assert_eq!(harness.load_and_run_asm(&*asm), 1);
+}
+
+#[rstest]
+fn test_non_tail_recursion_kept(mut harness: CompilerTest) {
+    let source = r#"
+    int factorial(int n) {
+        if (n <= 1) {
+            return 1;
+        }
+        return n * factorial(n - 1);
+    }
+    int main() {
+        return factorial(5);
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(asm.contains("call factorial"));
+    assert_eq!(harness.load_and_run_asm(&*asm), 120);
+}
+
+#[rstest]
+fn test_volatile_stores_kept(mut harness: CompilerTest) {
+    let volatile_source = r#"
+    int main() {
+        volatile int x = 1;
+        x = 2;
+        x = 3;
+        return x;
+    }"#;
+    let plain_source = r#"
+    int main() {
+        int x = 1;
+        x = 2;
+        x = 3;
+        return x;
+    }"#;
+    let volatile_asm = compile(volatile_source.to_string()).unwrap();
+    let plain_asm = compile(plain_source.to_string()).unwrap();
+    assert_eq!(
+        count_instructions(&volatile_asm),
+        count_instructions(&plain_asm) + 2
+    );
+    assert_eq!(harness.load_and_run_asm(&*volatile_asm), 3);
+}
+
+#[rstest]
+fn test_qualifiers_parsed(mut harness: CompilerTest) {
+    let source = r#"
+    static volatile long counter;
+    int add(volatile int a, int restrict b) {
+        return a + b;
+    }
+    int main() {
+        for (volatile int i = 0; i < 3; i = i + 1) {
+            counter = counter + add(i, 1);
+        }
+        unsigned volatile int total = counter;
+        return total;
+    }"#;
+    harness.assert_runs_ok(source, 6);
+}
+
+#[rstest]
+fn test_comparison_branches_directly(mut harness: CompilerTest) {
+    let source = r#"
+    int less(int a, int b) {
+        if (a < b)
+            return 1;
+        return 0;
+    }
+    int main() {
+        return less(1, 2) * 10 + less(2, 1);
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    let less = asm
+        .split("less:")
+        .nth(1)
+        .and_then(|body| body.split("main:").next())
+        .unwrap();
+    let count = |prefix: &str| {
+        less.lines()
+            .filter(|line| line.trim().starts_with(prefix))
+            .count()
+    };
+    assert_eq!(count("cmp"), 1, "{}", less);
+    assert_eq!(count("jge"), 1, "{}", less);
+    assert_eq!(count("set"), 0, "{}", less);
+    assert_eq!(count("test"), 0, "{}", less);
+    assert_eq!(harness.load_and_run_asm(&*asm), 10);
+}
+
+#[rstest]
+fn test_comparison_result_still_read(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        unsigned int big = 4294967295u;
+        int small = 1;
+        int both = big > 2u && small <= 1;
+        while (small < 5)
+            small = small + 1;
+        return both * 10 + small + (big < 3u ? 100 : 0);
+    }"#;
+    harness.assert_runs_ok(source, 15);
+}
+
+#[rstest]
+fn test_conditional_statement_stores_no_result(mut harness: CompilerTest) {
+    let program = |statement: &str| {
+        format!(
+            r#"
+    int main() {{
+        int a = 1;
+        int b = 10;
+        for (int i = 0; i < 3; i = i + 1) {{
+            {}
+        }}
+        return a * 100 + b;
+    }}"#,
+            statement
+        )
+    };
+    let conditional = compile(program("i == 1 ? a++ : b++;")).unwrap();
+    let if_else = compile(program("if (i == 1) a++; else b++;")).unwrap();
+    assert_eq!(conditional, if_else);
+    assert_eq!(harness.load_and_run_asm(&conditional), 212);
+}
+
+#[rstest]
+fn test_division_and_modulo_share_idiv(mut harness: CompilerTest) {
+    let source = r#"
+    int split(int a, int b) {
+        int q;
+        int r;
+        q = a / b;
+        r = a % b;
+        return q * 100 + r;
+    }
+    int main() {
+        return split(-47, 5) + split(47, 5) * 2;
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    let split = asm
+        .split("split:")
+        .nth(1)
+        .and_then(|body| body.split("main:").next())
+        .unwrap();
+    assert_eq!(split.matches("idiv").count(), 1, "{}", split);
+    assert_eq!(harness.load_and_run_asm(&*asm), 902);
+}
+
+#[rstest]
+fn test_opt_levels(mut harness: CompilerTest) {
+    let source = r#"
+    static int unused() {
+        return 7;
+    }
+    int sum(int n, int acc) {
+        if (n == 0)
+            return acc;
+        return sum(n - 1, acc + n);
+    }
+    int main() {
+        int x = 5;
+        x = sum(10, 0);
+        while (0) {
+            x = x + 1;
+        }
+        return x / 3 * 10 + x % 3;
+    }"#;
+    let asm: Vec<String> = [OptLevel::O0, OptLevel::O1, OptLevel::O2]
+        .into_iter()
+        .map(|opt_level| {
+            let options = CompileOptions {
+                opt_level,
+                ..CompileOptions::default()
+            };
+            compile_with(source.to_string(), &options).unwrap()
+        })
+        .collect();
+    for level in &asm {
+        assert_eq!(harness.load_and_run_asm(level), 181);
+    }
+    let counts: Vec<usize> = asm.iter().map(|level| count_instructions(level)).collect();
+    assert!(counts[0] > counts[1], "{:?}", counts);
+    assert!(counts[1] > counts[2], "{:?}", counts);
+    assert!(asm[0].contains("unused:") && !asm[1].contains("unused:"));
+    // the default is unchanged, every pass on
+    assert_eq!(compile(source.to_string()).unwrap(), asm[2]);
+}
+
+#[rstest]
+fn test_loop_invariant_computed_once(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        int a = 6;
+        int b = 7;
+        int total = 0;
+        int x = 0;
+        for (int i = 0; i < 10; i = i + 1) {
+            x = a * b;
+            total = total + x + i;
+        }
+        int j = 0;
+        while (j < 3) {
+            x = a + b;
+            j = j + x;
+        }
+        return total + j;
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    let loops: Vec<&str> = asm
+        .split("_start.loop:")
+        .skip(1)
+        .map(|rest| rest.split("_end.loop:").next().unwrap())
+        .collect();
+    assert_eq!(asm.matches("imul").count(), 1, "{}", asm);
+    assert_eq!(loops[0].matches("imul").count(), 0, "{}", asm);
+    // only j + x is left in the while loop
+    assert_eq!(loops[1].matches("addl").count(), 1, "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 478);
+}
+
+#[rstest]
+fn test_loop_variant_not_hoisted(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        int a = 1;
+        int x = 0;
+        int total = 0;
+        while (total < 100) {
+            x = a * 3;
+            a = a + 1;
+            total = total + x;
+        }
+        return total * 10 + x;
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    let (_, rest) = asm.split_once("_start.loop:").unwrap();
+    let (inside, _) = rest.split_once("_end.loop:").unwrap();
+    assert_eq!(inside.matches("imul").count(), 1, "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 1104);
+}
+
+#[rstest]
+fn test_logical_condition_branches_directly(mut harness: CompilerTest) {
+    let source = r#"
+    int both(int a, int b) {
+        if (a && b)
+            return 7;
+        return 3;
+    }
+    int either(int a, int b) {
+        int n = 0;
+        while (a < 5 || b > 0) {
+            a = a + 1;
+            b = b - 1;
+            n = n + 1;
+        }
+        return n;
+    }
+    int main() {
+        return both(1, 2) * 100 + both(0, 1) * 10 + either(0, 8);
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    let both = asm
+        .split("both:")
+        .nth(1)
+        .and_then(|body| body.split("either:").next())
+        .unwrap();
+    let instructions: Vec<&str> = both.lines().map(str::trim).collect();
+    // returns jumping to the shared epilogue aren't part of the condition
+    let jumps = instructions
+        .iter()
+        .filter(|line| line.starts_with('j') && !line.ends_with("_epilogue"));
+    assert_eq!(jumps.count(), 2, "{}", both);
+    // no 0/1 result is stored for the condition
+    let boolean_stores = instructions
+        .iter()
+        .filter(|line| line.starts_with("movl $0, -") || line.starts_with("movl $1, -"));
+    assert_eq!(boolean_stores.count(), 0, "{}", both);
+    assert_eq!(harness.load_and_run_asm(&*asm), 738);
+}
+
+#[rstest]
+fn test_leaf_function_omits_frame(mut harness: CompilerTest) {
+    let source = r#"
+    int f() {
+        return 5;
+    }
+    int main() {
+        return f() + 1;
+    }"#;
+    let compile_at = |opt_level| {
+        let options = CompileOptions {
+            opt_level,
+            ..CompileOptions::default()
+        };
+        compile_with(source.to_string(), &options).unwrap()
+    };
+    let (framed, frameless) = (compile_at(OptLevel::O0), compile_at(OptLevel::O1));
+    let body = |asm: &str| {
+        asm.split_once("f:")
+            .unwrap()
+            .1
+            .split_once("main:")
+            .unwrap()
+            .0
+            .to_string()
+    };
+    assert!(body(&framed).contains("%rbp"), "{}", framed);
+    assert!(!body(&frameless).contains("rbp"), "{}", frameless);
+    assert!(!body(&frameless).contains("rsp"), "{}", frameless);
+    // main calls f, so it keeps its frame
+    assert!(
+        frameless
+            .split_once("main:")
+            .unwrap()
+            .1
+            .contains("pushq %rbp")
+    );
+    assert_eq!(harness.load_and_run_asm(&frameless), 6);
+}
+
+#[rstest]
+fn test_returns_share_one_epilogue(mut harness: CompilerTest) {
+    let source = r#"
+    int twice(int x) {
+        return x * 2;
+    }
+    int classify(int x) {
+        int y = twice(x);
+        if (y < 0)
+            return 1;
+        if (y == 0)
+            return 2;
+        return 3 + y;
+    }
+    int main() {
+        return classify(-5) * 100 + classify(0) * 10 + classify(4);
+    }"#;
+    let compile_at = |opt_level| {
+        let options = CompileOptions {
+            opt_level,
+            ..CompileOptions::default()
+        };
+        compile_with(source.to_string(), &options).unwrap()
+    };
+    let epilogues = |asm: &str| {
+        asm.split_once("classify:")
+            .unwrap()
+            .1
+            .split_once("main:")
+            .unwrap()
+            .0
+            .matches("popq %rbp")
+            .count()
+    };
+    let (separate, shared) = (compile_at(OptLevel::O0), compile_at(OptLevel::O1));
+    assert_eq!(epilogues(&separate), 3, "{}", separate);
+    assert_eq!(epilogues(&shared), 1, "{}", shared);
+    assert_eq!(harness.load_and_run_asm(&shared), 131);
+    assert_eq!(harness.load_and_run_asm(&separate), 131);
+}
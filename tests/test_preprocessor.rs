@@ -0,0 +1,162 @@
+// tests/test_preprocessor.rs
+mod simulator;
+
+use compiler::{CompileOptions, CompilerError, compile, compile_with};
+use rstest::*;
+use simulator::{CompilerTest, harness};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use uuid::Uuid;
+
+/// Fresh directory holding the given files, removed again on drop.
+struct SourceDir(PathBuf);
+
+impl SourceDir {
+    fn new(files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("include_{}", Uuid::new_v4()));
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        SourceDir(dir)
+    }
+
+    fn options(&self) -> CompileOptions {
+        CompileOptions {
+            source_dir: self.0.clone(),
+            ..CompileOptions::default()
+        }
+    }
+}
+
+impl Drop for SourceDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[rstest]
+fn test_include_local_header(mut harness: CompilerTest) {
+    let dir = SourceDir::new(&[("util.h", "int twice(int x);\n")]);
+    let source = r#"
+#include "util.h"
+int main() {
+    return twice(21);
+}
+int twice(int x) {
+    return x * 2;
+}
+"#;
+    let asm = compile_with(source.to_string(), &dir.options()).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+}
+
+#[rstest]
+fn test_nested_include_relative_to_header(mut harness: CompilerTest) {
+    let dir = SourceDir::new(&[
+        (
+            "lib/outer.h",
+            "#include \"inner.h\"\nint outer() { return inner() + 1; }\n",
+        ),
+        ("lib/inner.h", "int inner() { return 6; }\n"),
+    ]);
+    let source = r#"
+#include "lib/outer.h"
+int main() {
+    return outer() * inner();
+}
+"#;
+    let asm = compile_with(source.to_string(), &dir.options()).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+}
+
+#[rstest]
+fn test_angle_include_searches_include_dirs(mut harness: CompilerTest) {
+    let dir = SourceDir::new(&[("sys/answer.h", "int answer() { return 42; }\n")]);
+    let options = CompileOptions {
+        include_dirs: vec![dir.0.join("sys")],
+        ..CompileOptions::default()
+    };
+    let source = "#include <answer.h>\nint main() { return answer(); }\n";
+    let asm = compile_with(source.to_string(), &options).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+}
+
+#[rstest]
+fn test_line_after_include(mut harness: CompilerTest) {
+    let dir = SourceDir::new(&[("two_lines.h", "int one();\nint two();\n")]);
+    let source = "#include \"two_lines.h\"\nint main() {\n    return __LINE__;\n}\n";
+    let asm = compile_with(source.to_string(), &dir.options()).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 3);
+}
+
+#[test]
+fn test_missing_include() {
+    let source = "#include \"does_not_exist.h\"\nint main() { return 0; }\n";
+    let err = compile(source.to_string()).unwrap_err();
+    assert!(matches!(err, CompilerError::SyntaxError(_)));
+    assert!(err.message().contains("does_not_exist.h"), "{}", err);
+}
+
+#[test]
+fn test_recursive_include() {
+    let dir = SourceDir::new(&[("self.h", "#include \"self.h\"\n")]);
+    let source = "#include \"self.h\"\nint main() { return 0; }\n";
+    let err = compile_with(source.to_string(), &dir.options()).unwrap_err();
+    assert!(matches!(err, CompilerError::SyntaxError(_)));
+}
+
+#[test]
+fn test_unsupported_directive() {
+    let source = "#define X 1\nint main() { return 0; }\n";
+    let err = compile(source.to_string()).unwrap_err();
+    assert!(matches!(err, CompilerError::SyntaxError(_)));
+    assert_eq!(err.line(), Some(1));
+    assert_eq!(err.column(), Some(1));
+}
+
+#[rstest]
+#[case::include("/*\n#include \"nope.h\"\n*/\nint main() { return 4; }\n")]
+#[case::indented("/*\n # notes\n*/\nint main() { return 4; }\n")]
+#[case::after_code("int x; /* starts here\n#define X\n */\nint main() { return 4; }\n")]
+fn test_hash_inside_block_comment(mut harness: CompilerTest, #[case] source: &str) {
+    let asm = compile(source.to_string()).unwrap();
+    assert_eq!(harness.load_and_run_asm(&asm), 4);
+}
+
+#[test]
+fn test_comment_opener_in_string_literal() {
+    let source = "_Static_assert(1, \"/*\");\n#include \"nope.h\"\nint main() { return 0; }\n";
+    let err = compile(source.to_string()).unwrap_err();
+    assert!(err.message().contains("nope.h"), "{}", err);
+}
+
+#[test]
+fn test_cli_include_dirs() {
+    let dir = SourceDir::new(&[
+        ("first/answer.h", "int answer() { return 42; }\n"),
+        ("second/answer.h", "int wrong_header();\n"),
+        (
+            "main.c",
+            "#include <answer.h>\nint main() { return answer(); }\n",
+        ),
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .arg("-I")
+        .arg(dir.0.join("missing"))
+        .arg(format!("-I{}", dir.0.join("first").display()))
+        .arg("-I")
+        .arg(dir.0.join("second"))
+        .arg(dir.0.join("main.c"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let asm = fs::read_to_string(dir.0.join("main.asm")).unwrap();
+    assert!(asm.contains("answer:"));
+}
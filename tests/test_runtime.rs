@@ -0,0 +1,141 @@
+// Programs that call into the C standard library, linked and run as real executables
+#![cfg(target_os = "linux")]
+
+use compiler::{CompileOptions, compile, compile_with};
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+fn compile_and_run(source: &str, options: &CompileOptions) -> Output {
+    let asm = compile_with(source.to_string(), options).unwrap();
+    let base = std::env::temp_dir().join(format!("runtime_{}", Uuid::new_v4()));
+    let asm_file = base.with_extension("s");
+    let exe_file = base.with_extension("out");
+    fs::write(&asm_file, &asm).unwrap();
+
+    let link = Command::new("gcc")
+        .arg(&asm_file)
+        .arg("-o")
+        .arg(&exe_file)
+        .output()
+        .unwrap();
+    assert!(
+        link.status.success(),
+        "gcc failed: {}",
+        String::from_utf8_lossy(&link.stderr)
+    );
+    let run = Command::new(&exe_file).output().unwrap();
+
+    let _ = fs::remove_file(&asm_file);
+    let _ = fs::remove_file(&exe_file);
+    run
+}
+
+fn compile_and_capture_stdout(source: &str) -> String {
+    let run = compile_and_run(source, &CompileOptions::default());
+    String::from_utf8_lossy(&run.stdout).to_string()
+}
+
+#[test]
+fn test_putchar_hello() {
+    let source = r#"
+    int putchar(int c);
+    int print(int c) {
+        return putchar(c);
+    }
+    int main() {
+        print(104);
+        print(101);
+        print(108);
+        print(108);
+        print(111);
+        putchar(10);
+        return 0;
+    }
+    "#;
+    assert!(compile_and_capture_stdout(source).contains("hello"));
+}
+
+#[test]
+fn test_external_calls_use_plt() {
+    let source = r#"
+    int putchar(int c);
+    int local() {
+        return 1;
+    }
+    int main() {
+        local();
+        return putchar(10);
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(asm.contains("call putchar@PLT"));
+    assert!(asm.contains("call local\n"));
+}
+
+#[test]
+fn test_trap_overflow() {
+    let source = r#"
+    int main() {
+        int a = 2000000000;
+        int b = 2000000000;
+        return a + b < 0;
+    }
+    "#;
+    let wrapped = compile_and_run(source, &CompileOptions::default());
+    assert_eq!(wrapped.status.code(), Some(1));
+
+    let options = CompileOptions {
+        trap_overflow: true,
+        ..CompileOptions::default()
+    };
+    let trapped = compile_and_run(source, &options);
+    // ud2 raises SIGILL
+    assert_eq!(trapped.status.signal(), Some(4));
+}
+
+#[test]
+fn test_trap_overflow_ignores_unsigned_and_in_range() {
+    let source = r#"
+    int main() {
+        unsigned int a = 4000000000u;
+        unsigned int b = a + a;
+        long c = 2000000000;
+        long d = c + c - c * 2;
+        int e = -5 * 3;
+        return (b == 3705032704u) + (d == 0) + (e == -15);
+    }
+    "#;
+    let options = CompileOptions {
+        trap_overflow: true,
+        ..CompileOptions::default()
+    };
+    assert_eq!(compile_and_run(source, &options).status.code(), Some(3));
+}
+
+#[test]
+fn test_debug_line_table() {
+    let source = r#"int twice(int x) {
+    return x * 2;
+}
+int main() {
+    int a = 3;
+    a = twice(a);
+    if (a > 5)
+        return a;
+    return 0;
+}
+"#;
+    let options = CompileOptions {
+        debug_source: Some("prog.c".into()),
+        ..CompileOptions::default()
+    };
+    let asm = compile_with(source.to_string(), &options).unwrap();
+    assert!(asm.contains(".file 1 \"prog.c\"\n"), "{}", asm);
+    for line in [2, 5, 6, 7, 8, 9] {
+        assert!(asm.contains(&format!(".loc 1 {}\n", line)), "{}", asm);
+    }
+    assert!(!compile(source.to_string()).unwrap().contains(".loc"));
+    assert_eq!(compile_and_run(source, &options).status.code(), Some(6));
+}
@@ -1,8 +1,9 @@
 mod simulator;
 
 use crate::simulator::{CompilerTest, harness};
-use compiler::CompilerError::{SemanticError, SyntaxError};
-use compiler::compile;
+use compiler::CompilerError::{SemanticError, SyntaxError, TypeError};
+use compiler::{Target, compile, compile_for};
+use regex::Regex;
 use rstest::rstest;
 
 #[rstest]
@@ -296,3 +297,220 @@ fn test_static_in_for_loop(harness: CompilerTest) {
     }"#;
     assert_compile_err!(harness, source, SyntaxError(_));
 }
+
+#[rstest]
+fn test_register_local(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        register int x = 5;
+        auto int y = 2;
+        x += y;
+        return x;
+    }"#;
+    harness.assert_runs_ok(source, 7);
+}
+
+#[rstest]
+fn test_static_register(harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        static register int x;
+        return x;
+    }"#;
+    assert_compile_err!(harness, source, SyntaxError(_));
+}
+
+#[rstest]
+fn test_register_file_scope(harness: CompilerTest) {
+    let source = r#"
+    register int x = 1;
+    int main() {
+        return x;
+    }"#;
+    assert_compile_err!(harness, source, SemanticError(_));
+}
+
+#[rstest]
+fn test_constant_expression_initializers(mut harness: CompilerTest) {
+    let source = r#"
+    int a = -1;
+    long b = (1 + 2) * 3l;
+    unsigned int c = 0u - 1;
+    int main() {
+        static long d = -1 ? 10 : 20;
+        return a == -1 && b == 9 && c == 4294967295u && d == 10;
+    }"#;
+    harness.assert_runs_ok(source, 1);
+}
+
+#[rstest]
+fn test_constant_initializer_division_by_zero(harness: CompilerTest) {
+    let source = r#"
+    int a = 1 / 0;
+    int main() {
+        return a;
+    }"#;
+    assert_compile_err!(harness, source, SemanticError(_));
+}
+
+#[rstest]
+fn test_static_directives_follow_type(mut harness: CompilerTest) {
+    let source = r#"
+    static int a = 5;
+    long b = 7;
+    static long c;
+    unsigned int d = 3;
+    int main() {
+        return a + b + c + d;
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    // each section is named once, with its variables in name order after it
+    for pattern in [
+        r"\.data\s+\.align 4\s+a:\s+\.long 5\s+\.global b\s+\.align 8\s+b:\s+\.quad 7",
+        r"\.global d\s+\.align 4\s+d:\s+\.long 3\s+\.bss\s+\.align 8\s+c:\s+\.zero 8",
+    ] {
+        assert!(Regex::new(pattern).unwrap().is_match(&asm), "{}", pattern);
+    }
+    assert_eq!(harness.load_and_run_asm(&*asm), 15);
+}
+
+#[rstest]
+fn test_globals_read_and_modified_at_their_width(mut harness: CompilerTest) {
+    let source = r#"
+    int i = -1;
+    long l = 4294967296l;
+    unsigned int u = 4294967295u;
+    unsigned long ul = 18446744073709551615ul;
+    int after_i = 7;
+
+    int main() {
+        i = i * 3;
+        l += 1;
+        u++;
+        ul = ul / 2;
+        --after_i;
+        if (i != -3) return 1;
+        if (l != 4294967297l) return 2;
+        if (u != 0u) return 3;
+        if (ul != 9223372036854775807ul) return 4;
+        if (after_i != 6) return 5;
+        return 0;
+    }"#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+fn test_global_declaration_list(mut harness: CompilerTest) {
+    let source = r#"
+    static long a = 40, b = 2, unset;
+
+    int main() {
+        return a + b + unset;
+    }"#;
+    harness.assert_runs_ok(source, 42);
+}
+
+#[rstest]
+fn test_function_in_global_declaration_list(harness: CompilerTest) {
+    let source = r#"
+    int a = 1, f();
+
+    int main() {
+        return a;
+    }"#;
+    assert_compile_err!(harness, source, SyntaxError(_));
+}
+
+#[rstest]
+fn test_unused_static_function_is_not_emitted(mut harness: CompilerTest) {
+    let source = r#"
+    static int leaf() {
+        return 2;
+    }
+    static int unused() {
+        return leaf() + 1;
+    }
+    static int recursive(int n) {
+        return n ? recursive(n - 1) : 0;
+    }
+    static int used() {
+        return leaf() * 21;
+    }
+    static int address_taken() {
+        return 0;
+    }
+    int main() {
+        long address = (long)&address_taken;
+        return used() + (address == 0);
+    }"#;
+    let asm = compile(source.to_string()).unwrap();
+    let label = |name: &str| Regex::new(&format!(r"(?m)^_?{}:", name)).unwrap();
+    assert!(label("used").is_match(&asm));
+    assert!(label("leaf").is_match(&asm));
+    assert!(!label("unused").is_match(&asm));
+    assert!(!label("recursive").is_match(&asm));
+    assert!(label("address_taken").is_match(&asm));
+    assert_eq!(harness.load_and_run_asm(&asm), 42);
+}
+
+#[rstest]
+fn test_const_globals_are_read_only(mut harness: CompilerTest) {
+    let source = r#"
+const int limit = 40;
+const long zero;
+int counter = 1;
+int main() {
+    static const int offset = -1;
+    return limit + zero + counter + offset + 2;
+}
+"#;
+    let asm = compile_for(source.to_string(), Target::Linux).unwrap();
+    // the section directive in effect where the label starting with `name` is defined
+    let section = |name: &str| {
+        asm.lines()
+            .take_while(|line| !(line.starts_with(name) && line.ends_with(':')))
+            .filter(|line| {
+                [".text", ".data", ".bss", ".section"]
+                    .iter()
+                    .any(|s| line.starts_with(s))
+            })
+            .last()
+            .unwrap()
+    };
+    assert_eq!(section("limit"), ".section .rodata", "{}", asm);
+    assert_eq!(section("zero"), ".section .rodata", "{}", asm);
+    assert_eq!(section("main.offset"), ".section .rodata", "{}", asm);
+    assert_eq!(section("counter"), ".data", "{}", asm);
+    harness.assert_runs_ok(source, 42);
+}
+
+#[rstest]
+#[case::global("const int g = 1; int main() { g = 2; return g; }")]
+#[case::local("int main() { const int x = 1; x += 1; return x; }")]
+#[case::increment("int main() { static const int x = 1; return ++x; }")]
+fn test_assign_to_const(harness: CompilerTest, #[case] source: &str) {
+    assert_compile_err!(harness, source, TypeError(_));
+}
+
+#[rstest]
+fn test_functions_precede_data(mut harness: CompilerTest) {
+    let source = r#"
+int total = 5;
+static long scratch;
+int add(int x) {
+    scratch = x;
+    return total + x;
+}
+int main() {
+    return add(4) + scratch;
+}
+"#;
+    let asm = compile_for(source.to_string(), Target::Linux).unwrap();
+    let position = |line: &str| asm.lines().position(|l| l == line).unwrap();
+    assert_eq!(asm.lines().filter(|l| *l == ".text").count(), 1, "{}", asm);
+    assert!(position("add:") < position(".data"), "{}", asm);
+    assert!(position("main:") < position(".data"), "{}", asm);
+    assert!(position(".data") < position(".bss"), "{}", asm);
+    harness.assert_runs_ok(source, 13);
+}
@@ -0,0 +1,273 @@
+// tests/test_switch.rs
+mod simulator;
+
+use compiler::{CompileOptions, CompilerError, OptLevel, compile, compile_with};
+use rstest::*;
+use simulator::{CompilerTest, harness};
+
+#[rstest]
+fn test_stacked_cases(mut harness: CompilerTest) {
+    let code = r#"
+        int pick(int x) {
+            switch (x) {
+                case 1:
+                case 2:
+                    return 12;
+                case 3:
+                    return 3;
+            }
+            return 0;
+        }
+        int main() {
+            return pick(1) + pick(2) + pick(3) + pick(4);
+        }
+    "#;
+    harness.assert_runs_ok(code, 27);
+}
+
+#[rstest]
+fn test_fallthrough(mut harness: CompilerTest) {
+    let code = r#"
+        int run(int x) {
+            int r = 0;
+            switch (x) {
+                case 1:
+                    r = r + 1;
+                case 2:
+                    r = r + 10;
+                    break;
+                case 3:
+                    r = 100;
+            }
+            return r;
+        }
+        int main() {
+            return run(1) * 100 + run(2) * 10 + run(4);
+        }
+    "#;
+    harness.assert_runs_ok(code, 1200);
+}
+
+#[rstest]
+fn test_default_in_middle(mut harness: CompilerTest) {
+    let code = r#"
+        int run(long x) {
+            int r = 0;
+            switch (x) {
+                case 1:
+                    r = 1;
+                    break;
+                default:
+                    r = 20;
+                case 4294967296l:
+                    r = r + 3;
+            }
+            return r;
+        }
+        int main() {
+            return run(1) * 100 + run(4294967296l) * 10 + run(7);
+        }
+    "#;
+    harness.assert_runs_ok(code, 153);
+}
+
+#[rstest]
+fn test_break_leaves_switch_not_loop(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int total = 0;
+            for (int i = 0; i < 5; i = i + 1) {
+                switch (i % 2) {
+                    case 0:
+                        break;
+                    default:
+                        continue;
+                }
+                total = total + i;
+            }
+            return total;
+        }
+    "#;
+    harness.assert_runs_ok(code, 6);
+}
+
+#[rstest]
+fn test_break_and_continue_through_nested_switches(mut harness: CompilerTest) {
+    // each break leaves its innermost switch or loop, each continue its innermost loop
+    let code = r#"
+        int main() {
+            int total = 0;
+            for (int i = 0; i < 6; i = i + 1) {
+                switch (i % 3) {
+                    case 0:
+                        for (int j = 0; j < 3; j = j + 1) {
+                            switch (j) {
+                                case 1:
+                                    continue;
+                                default:
+                                    break;
+                            }
+                            total = total + 100;
+                        }
+                        break;
+                    case 1:
+                        continue;
+                    default:
+                        do {
+                            switch (i) {
+                                case 2:
+                                    break;
+                            }
+                            total = total + 10;
+                        } while (0);
+                }
+                total = total + 1;
+            }
+            return total;
+        }
+    "#;
+    harness.assert_runs_ok(code, 424);
+}
+
+#[rstest]
+fn test_case_outside_switch(harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            case 1: return 0;
+        }
+    "#;
+    assert_compile_err!(harness, code, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_duplicate_case(harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            switch (1) {
+                case 1: return 1;
+                case 2 - 1: return 2;
+            }
+            return 0;
+        }
+    "#;
+    assert_compile_err!(harness, code, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_non_constant_case(harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int x = 1;
+            switch (1) {
+                case x: return 1;
+            }
+            return 0;
+        }
+    "#;
+    assert_compile_err!(harness, code, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_continue_in_switch_outside_loop(harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            switch (1) {
+                case 1: continue;
+            }
+            return 0;
+        }
+    "#;
+    assert_compile_err!(harness, code, CompilerError::SemanticError(_));
+}
+
+#[rstest]
+fn test_dense_switch_uses_jump_table(mut harness: CompilerTest) {
+    let code = r#"
+        int pick(int x) {
+            switch (x) {
+                case 0: return 10;
+                case 1: return 11;
+                case 2: return 12;
+                case 3:
+                case 4: return 14;
+                case 6: return 16;
+                case 7: return 17;
+                default: return 99;
+            }
+        }
+        int main() {
+            int total = 0;
+            for (int i = -3; i < 10; i = i + 1)
+                total = total * 3 % 1000003 + pick(i);
+            return total;
+        }
+    "#;
+    let asm = compile(code.to_string()).unwrap();
+    assert!(
+        asm.contains("_table:") && asm.contains("jmp *%rax"),
+        "{}",
+        asm
+    );
+    let expected = [-3, -2, -1, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
+        .iter()
+        .map(|&i| match i {
+            0..=2 => 10 + i,
+            3 | 4 => 14,
+            6 | 7 => 10 + i,
+            _ => 99,
+        })
+        .fold(0, |total, value| total * 3 % 1000003 + value);
+    assert_eq!(harness.load_and_run_asm(&asm), expected);
+
+    let options = CompileOptions {
+        opt_level: OptLevel::O0,
+        ..CompileOptions::default()
+    };
+    let unoptimized = compile_with(code.to_string(), &options).unwrap();
+    assert!(!unoptimized.contains("_table:"), "{}", unoptimized);
+    assert_eq!(harness.load_and_run_asm(&unoptimized), expected);
+}
+
+#[rstest]
+fn test_negative_long_cases_in_table(mut harness: CompilerTest) {
+    let code = r#"
+        long wide(long x) {
+            switch (x) {
+                case -2: return 1;
+                case -1: return 2;
+                case 0: return 3;
+                case 1: return 4;
+            }
+            return 0;
+        }
+        int main() {
+            long total = 0;
+            for (long l = -4; l < 3; l = l + 1)
+                total = total * 5 + wide(l);
+            return total + wide(4294967295l) + wide(-4294967298l);
+        }
+    "#;
+    let asm = compile(code.to_string()).unwrap();
+    assert!(asm.contains("_table:"), "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 970);
+}
+
+#[rstest]
+fn test_sparse_switch_compares_each_case(mut harness: CompilerTest) {
+    let code = r#"
+        int main() {
+            int x = 1000;
+            switch (x) {
+                case 1: return 1;
+                case 10: return 2;
+                case 100: return 3;
+                case 1000: return 4;
+                case 10000: return 5;
+            }
+            return 0;
+        }
+    "#;
+    let asm = compile(code.to_string()).unwrap();
+    assert!(!asm.contains("_table:"), "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 4);
+}
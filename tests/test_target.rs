@@ -0,0 +1,67 @@
+use compiler::{Target, compile, compile_for};
+
+const SOURCE: &str = r#"
+int putchar(int c);
+static long counter = 3;
+int main() {
+    return putchar(counter);
+}
+"#;
+
+#[test]
+fn test_default_target_is_host() {
+    assert_eq!(
+        compile(SOURCE.to_string()).unwrap(),
+        compile_for(SOURCE.to_string(), Target::host()).unwrap()
+    );
+}
+
+#[test]
+fn test_linux_conventions() {
+    let asm = compile_for(SOURCE.to_string(), Target::Linux).unwrap();
+    assert!(asm.contains(".align 8\n"));
+    assert!(asm.contains("call putchar@PLT"));
+}
+
+#[test]
+fn test_macos_conventions() {
+    let asm = compile_for(SOURCE.to_string(), Target::MacOs).unwrap();
+    assert!(asm.contains(".balign 8\n"));
+    assert!(!asm.contains("@PLT"));
+}
+
+#[test]
+fn test_parse_target() {
+    assert_eq!("linux".parse::<Target>(), Ok(Target::Linux));
+    assert_eq!("macos".parse::<Target>(), Ok(Target::MacOs));
+    assert_eq!("windows".parse::<Target>(), Ok(Target::Windows));
+    assert!("plan9".parse::<Target>().is_err());
+}
+
+#[test]
+fn test_macos_mangles_symbols() {
+    let source = r#"
+    static int counter = 1;
+    int helper(int a) {
+        return a + counter;
+    }
+    int main() {
+        counter = counter + 1;
+        return helper(2);
+    }
+    "#;
+    let asm = compile_for(source.to_string(), Target::MacOs).unwrap();
+    assert!(asm.contains("\n_helper:\n"));
+    assert!(asm.contains("call _helper\n"));
+    assert!(asm.contains(".global _main\n"));
+    assert!(asm.contains("\n_counter:\n"));
+    assert!(asm.contains("_counter(%rip)"));
+    assert!(!asm.contains(" counter(%rip)"));
+}
+
+#[test]
+fn test_linux_symbols_unmangled() {
+    let asm = compile_for(SOURCE.to_string(), Target::Linux).unwrap();
+    assert!(asm.contains("\nmain:\n"));
+    assert!(!asm.contains("_main"));
+}
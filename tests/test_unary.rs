@@ -3,7 +3,7 @@ mod simulator;
 
 use rstest::*;
 use simulator::{CompilerTest, harness};
-use compiler::CompilerError;
+use compiler::{CompilerError, compile};
 
 #[rstest]
 fn test_bitwise(mut harness: CompilerTest) {
@@ -53,4 +53,109 @@ int main() {
 }
 "#;
     assert_compile_err!(harness, source, CompilerError::SyntaxError(_));
-}
\ No newline at end of file
+}
+#[rstest]
+fn test_unary_plus_keeps_operand_type(mut harness: CompilerTest) {
+    let source = r#"
+int main() {
+    unsigned int u = 4000000000u;
+    long l = 4294967296l;
+    if (!(+u > 0)) return 1;
+    if (+l != 4294967296l) return 2;
+    if (+-5 != -5) return 3;
+    return 0;
+}
+"#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+fn test_unary_plus_emits_nothing() {
+    let with_plus = compile("int main() { int x = 3; return +x; }".to_string()).unwrap();
+    let without = compile("int main() { int x = 3; return x; }".to_string()).unwrap();
+    assert_eq!(with_plus, without);
+}
+
+#[rstest]
+fn test_logical_not_of_runtime_values(mut harness: CompilerTest) {
+    let source = r#"int main() {
+    int five = 5;
+    long high = 4294967296l;
+    unsigned long zero = 0;
+    if (!five != 0) return 1;
+    if (!!five != 1) return 2;
+    if (!high != 0) return 3;
+    if (!zero != 1) return 4;
+    return !(five - 5) * 10 + !(high - 1);
+}
+"#;
+    harness.assert_runs_ok(source, 10);
+}
+
+#[rstest]
+fn test_sizeof_does_not_evaluate_operand(mut harness: CompilerTest) {
+    let source = r#"
+int hits = 0;
+long g() {
+    hits = hits + 1;
+    return 7;
+}
+int main() {
+    int i = 0;
+    if (sizeof(i = 5) != 4) return 1;
+    return sizeof(g()) * 10 + sizeof i + hits + i;
+}
+"#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(!asm.contains("call"), "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 84);
+}
+
+#[rstest]
+fn test_sizeof_type(mut harness: CompilerTest) {
+    let source = r#"
+int main() {
+    return sizeof(int) + sizeof(long) * 10 + sizeof(unsigned) * 100 + (sizeof(int) - 5 > 0);
+}
+"#;
+    harness.assert_runs_ok(source, 485);
+}
+
+#[rstest]
+fn test_static_assert(mut harness: CompilerTest) {
+    let source = r#"
+_Static_assert(sizeof(int) == 4, "bad");
+long g;
+_Static_assert(sizeof g == 8, "long is 8 bytes");
+int main() {
+    int x = 3;
+    _Static_assert(sizeof(x + 1l) == sizeof(long), "int + long is long");
+    return x;
+}
+"#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(!asm.contains("bad"), "{}", asm);
+    assert_eq!(harness.load_and_run_asm(&asm), 3);
+}
+
+#[rstest]
+fn test_static_assert_is_an_identifier(mut harness: CompilerTest) {
+    // only the keyword spelling is reserved; static_assert needs <assert.h>'s macro
+    let source = "int main() { int static_assert = 3; return static_assert; }";
+    harness.assert_runs_ok(source, 3);
+}
+
+#[rstest]
+#[case::file_scope("_Static_assert(0, \"fail\");\nint main() { return 0; }")]
+#[case::block_scope("int main() { _Static_assert(sizeof(int) > 4, \"fail\"); return 0; }")]
+fn test_failed_static_assert(#[case] source: &str) {
+    let err = compile(source.to_string()).unwrap_err();
+    assert!(matches!(err, CompilerError::SemanticError(_)), "{:?}", err);
+    assert!(err.message().contains("fail"), "{}", err);
+}
+
+#[rstest]
+fn test_non_constant_static_assert(harness: CompilerTest) {
+    let source = "int main() { int x = 1; _Static_assert(x, \"x\"); return x; }";
+    assert_compile_err!(harness, source, CompilerError::SemanticError(_));
+}
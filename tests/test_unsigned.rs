@@ -2,6 +2,7 @@ mod simulator;
 
 use crate::simulator::{CompilerTest, harness};
 use compiler::CompilerError::{SemanticError, SyntaxError};
+use compiler::compile;
 use rstest::rstest;
 
 #[rstest]
@@ -969,4 +970,49 @@ fn test_multiple_unsigned_casts(mut harness: CompilerTest) {
         return 0;
     }"#;
     harness.assert_runs_ok(source, 0);
-}
\ No newline at end of file
+}
+
+#[rstest]
+fn test_static_unsigned_max_values(mut harness: CompilerTest) {
+    let source = r#"
+    static unsigned long big = 18446744073709551615ul;
+    unsigned int ubig = 4294967295u;
+    int main() {
+        if (big + 1 != 0) return 1;
+        if (big >> 63 != 1) return 2;
+        if (ubig + 1 != 0) return 3;
+        return ubig >> 31;
+    }
+    "#;
+    let asm = compile(source.to_string()).unwrap();
+    assert!(asm.contains(".quad 18446744073709551615"), "{}", asm);
+    assert!(asm.contains(".long 4294967295"), "{}", asm);
+    harness.assert_runs_ok(source, 1);
+}
+
+#[rstest]
+fn test_suffix_case_and_order(mut harness: CompilerTest) {
+    let source = r#"
+    int main() {
+        if (10UL - 11 < 0 || 10lu - 11 < 0 || 10LLU - 11 < 0 || 10ull - 11 < 0) return 1;
+        if (10Ul - 11 < 4294967296 || 10uLL - 11 < 4294967296) return 2;
+        if (10ll - 11 >= 0 || 10LL - 11 >= 0) return 3;
+        if ((10ll << 40) == 0 || (10Lu << 40) == 0) return 4;
+        if (10U - 11 < 0 || 10U - 11 > 4294967295u) return 5;
+        return 0;
+    }
+    "#;
+    harness.assert_runs_ok(source, 0);
+}
+
+#[rstest]
+#[case::repeated_unsigned("10uu")]
+#[case::mixed_case_long("10lL")]
+#[case::unsigned_on_both_sides("10ulu")]
+#[case::long_split_by_unsigned("10lul")]
+#[case::triple_long("10lll")]
+#[case::unknown_letter("10x")]
+fn test_invalid_suffix(harness: CompilerTest, #[case] constant: &str) {
+    let source = format!("int main() {{ long x = {}; return 0; }}", constant);
+    assert_compile_err!(harness, &*source, SyntaxError(_));
+}